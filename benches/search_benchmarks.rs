@@ -2,7 +2,10 @@ use criterion::{black_box, criterion_group, criterion_main, Criterion, Benchmark
 use quickset::storage::{Storage, Value};
 use quickset::index::{HashIndex, InvertedIndex, TrieIndex, SortedIndex, BloomFilter};
 use quickset::search::SearchEngine;
-use quickset::table::{Table, Column, ColumnType};
+use quickset::table::{Table, Column, ColumnType, Coercion};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering as AtomicOrdering};
 
 fn generate_random_string(len: usize, seed: u64) -> String {
     let chars: Vec<char> = "abcdefghijklmnopqrstuvwxyz".chars().collect();
@@ -83,15 +86,92 @@ fn bench_inverted_index(c: &mut Criterion) {
     group.finish();
 }
 
+// the trie as it was before path compression, one boxed node and hash map
+// per byte, kept here as the baseline for the radix tree
+#[derive(Default)]
+struct ByteTrie {
+    children: HashMap<u8, Box<ByteTrie>>,
+    row_ids: Vec<u64>,
+}
+
+impl ByteTrie {
+    fn insert(&mut self, key: &str, row_id: u64) {
+        let mut node = self;
+        for byte in key.bytes() {
+            node = node.children.entry(byte).or_default();
+        }
+        node.row_ids.push(row_id);
+    }
+
+    fn search_prefix(&self, prefix: &str) -> Vec<u64> {
+        let mut node = self;
+        for byte in prefix.bytes() {
+            match node.children.get(&byte) {
+                Some(child) => node = child,
+                None => return Vec::new(),
+            }
+        }
+        let mut result = Vec::new();
+        let mut stack = vec![node];
+        while let Some(node) = stack.pop() {
+            result.extend_from_slice(&node.row_ids);
+            stack.extend(node.children.values().map(|c| &**c));
+        }
+        result
+    }
+}
+
+// counts live heap bytes so the tries' footprints can be compared
+struct CountingAlloc;
+
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATED.fetch_add(layout.size(), AtomicOrdering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        ALLOCATED.fetch_sub(layout.size(), AtomicOrdering::Relaxed);
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+// heap bytes still held by whatever `build` returns
+fn heap_size<T>(build: impl FnOnce() -> T) -> (T, usize) {
+    let before = ALLOCATED.load(AtomicOrdering::Relaxed);
+    let built = build();
+    (built, ALLOCATED.load(AtomicOrdering::Relaxed) - before)
+}
+
 fn bench_trie_index(c: &mut Criterion) {
     let mut group = c.benchmark_group("trie_index");
     
     for size in [1_000, 10_000, 100_000].iter() {
-        let mut index = TrieIndex::new();
-        for i in 0..*size {
-            let key = generate_random_string(10, i as u64);
-            index.insert(&key, i as u64);
-        }
+        let keys: Vec<String> = (0..*size).map(|i| generate_random_string(10, i as u64)).collect();
+
+        let (index, radix_bytes) = heap_size(|| {
+            let mut index = TrieIndex::new();
+            for (i, key) in keys.iter().enumerate() {
+                index.insert(key, i as u64);
+            }
+            index
+        });
+        let (byte_trie, byte_bytes) = heap_size(|| {
+            let mut trie = ByteTrie::default();
+            for (i, key) in keys.iter().enumerate() {
+                trie.insert(key, i as u64);
+            }
+            trie
+        });
+        println!(
+            "trie_index/memory/{}: radix {} KiB in {} nodes, byte trie {} KiB",
+            size, radix_bytes / 1024, index.node_count(), byte_bytes / 1024
+        );
         
         let prefix = generate_random_string(3, (*size / 2) as u64);
         
@@ -100,7 +180,34 @@ fn bench_trie_index(c: &mut Criterion) {
                 black_box(index.search_prefix(&prefix))
             });
         });
+
+        group.bench_with_input(BenchmarkId::new("search_prefix_byte_trie", size), size, |b, _| {
+            b.iter(|| {
+                black_box(byte_trie.search_prefix(&prefix))
+            });
+        });
     }
+
+    // a resync that deletes and reinserts every row should leave the tree
+    // no bigger than it was
+    let keys: Vec<String> = (0..10_000).map(|i| generate_random_string(10, i as u64)).collect();
+    let mut index = TrieIndex::new();
+    for (i, key) in keys.iter().enumerate() {
+        index.insert(key, i as u64);
+    }
+    group.bench_function("churn_10000", |b| {
+        let mut next = keys.len() as u64;
+        b.iter(|| {
+            for (i, key) in keys.iter().enumerate().take(100) {
+                index.remove(key, i as u64);
+                index.insert(&generate_random_string(10, next), next);
+                index.remove(&generate_random_string(10, next), next);
+                index.insert(key, i as u64);
+                next += 1;
+            }
+        });
+    });
+    println!("trie_index/churn_10000: {} nodes after churn", index.node_count());
     
     group.finish();
 }
@@ -212,9 +319,9 @@ fn bench_table(c: &mut Criterion) {
         let mut table = Table::with_capacity(
             "bench_table",
             vec![
-                Column { name: "name".into(), col_type: ColumnType::String },
-                Column { name: "description".into(), col_type: ColumnType::String },
-                Column { name: "value".into(), col_type: ColumnType::Int },
+                Column::new("name", ColumnType::String),
+                Column::new("description", ColumnType::String),
+                Column::new("value", ColumnType::Int),
             ],
             *size,
         );
@@ -226,7 +333,7 @@ fn bench_table(c: &mut Criterion) {
                 Value::String(name.into()),
                 Value::String(desc.into()),
                 Value::Int(i as i64),
-            ]).unwrap();
+            ], Coercion::Strict).unwrap();
         }
         
         let search_name = generate_random_string(10, (*size / 2) as u64);
@@ -261,8 +368,8 @@ fn bench_insert(c: &mut Criterion) {
         let mut table = Table::new(
             "bench_table",
             vec![
-                Column { name: "name".into(), col_type: ColumnType::String },
-                Column { name: "value".into(), col_type: ColumnType::Int },
+                Column::new("name", ColumnType::String),
+                Column::new("value", ColumnType::Int),
             ],
         );
         let mut i = 0u64;
@@ -272,7 +379,7 @@ fn bench_insert(c: &mut Criterion) {
             table.insert(vec![
                 Value::String(name.into()),
                 Value::Int(i as i64),
-            ]).unwrap();
+            ], Coercion::Strict).unwrap();
             i += 1;
         });
     });
@@ -1,6 +1,6 @@
 use quickset::http::HttpServer;
 use quickset::storage::Value;
-use quickset::table::{Column, ColumnType, Database};
+use quickset::table::{Coercion, Column, ColumnType, Database};
 use std::time::Instant;
 
 fn generate_string(len: usize, seed: u64) -> String {
@@ -26,15 +26,15 @@ fn main() {
     db.create_table_with_capacity(
         "data",
         vec![
-            Column { name: "id".into(), col_type: ColumnType::Int },
-            Column { name: "name".into(), col_type: ColumnType::String },
-            Column { name: "description".into(), col_type: ColumnType::String },
-            Column { name: "value".into(), col_type: ColumnType::Int },
+            Column::new("id", ColumnType::Int),
+            Column::new("name", ColumnType::String),
+            Column::new("description", ColumnType::String),
+            Column::new("value", ColumnType::Int),
         ],
         row_count,
     ).unwrap();
 
-    let table = db.get_table_mut("data").unwrap();
+    let mut table = db.get_table_mut("data").unwrap();
     let start = Instant::now();
     
     for i in 0..row_count {
@@ -45,13 +45,14 @@ fn main() {
             Value::String(name.into()),
             Value::String(desc.into()),
             Value::Int((i * 7) as i64),
-        ]).unwrap();
+        ], Coercion::Strict).unwrap();
         
         if i > 0 && i % 100_000 == 0 {
             println!("  loaded {} rows...", i);
         }
     }
 
+    drop(table);
     println!("loaded {} rows in {:?}", row_count, start.elapsed());
     println!();
     println!("starting http server on 0.0.0.0:8080");
@@ -0,0 +1,297 @@
+// aggregations over the rows a search matched, computed from stored values so
+// a dashboard gets its numbers without pulling any rows over the wire
+
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+use crate::search::{elements, RowSource};
+use crate::storage::{RowId, Value};
+
+// buckets a terms aggregation returns when the request doesn't say
+pub const DEFAULT_TERMS_SIZE: usize = 10;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Aggregation {
+    Count(Option<usize>),   // matching rows, or those with a value in the column
+    Sum(usize),
+    Min(usize),
+    Max(usize),
+    Avg(usize),
+    // the `size` most common values of a column, each with its own aggregations
+    Terms {
+        column: usize,
+        size: usize,
+        aggregations: Vec<(String, Aggregation)>,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum AggregationResult {
+    Value(Value),   // null when no row had a value to aggregate
+    Terms { buckets: Vec<Bucket>, other: usize },   // other: rows in buckets past `size`
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Bucket {
+    pub key: Value,
+    pub count: usize,
+    pub aggregations: Vec<(String, AggregationResult)>,
+}
+
+// run every aggregation over `row_ids`
+pub fn aggregate(aggregations: &[(String, Aggregation)], row_ids: &[RowId], rows: &dyn RowSource) -> Vec<(String, AggregationResult)> {
+    aggregations
+        .iter()
+        .map(|(name, aggregation)| (name.clone(), run(aggregation, row_ids, rows)))
+        .collect()
+}
+
+fn run(aggregation: &Aggregation, row_ids: &[RowId], rows: &dyn RowSource) -> AggregationResult {
+    let values = |column: usize| row_ids.iter().filter_map(move |id| rows.value(*id, column));
+
+    let value = match aggregation {
+        Aggregation::Count(None) => Value::Int(row_ids.len() as i64),
+        Aggregation::Count(Some(column)) => {
+            Value::Int(values(*column).filter(|v| !matches!(v, Value::Null)).count() as i64)
+        }
+        Aggregation::Sum(column) => match sum(values(*column)) {
+            (0, _) => Value::Null,
+            (_, total) => total,
+        },
+        Aggregation::Avg(column) => match sum(values(*column)) {
+            (0, _) => Value::Null,
+            (n, Value::Int(total)) => Value::Float(total as f64 / n as f64),
+            (n, Value::Float(total)) => Value::Float(total / n as f64),
+            _ => Value::Null,
+        },
+        Aggregation::Min(column) => extreme(values(*column), Ordering::Less),
+        Aggregation::Max(column) => extreme(values(*column), Ordering::Greater),
+        Aggregation::Terms { column, size, aggregations } => return terms(*column, *size, aggregations, row_ids, rows),
+    };
+    AggregationResult::Value(value)
+}
+
+// how many numbers there were and their total. ints add up exactly until
+// they'd overflow, from there on (or once a float shows up) it's floats.
+// nan is skipped like null, it would swallow everything else
+fn sum<'a>(values: impl Iterator<Item = &'a Value>) -> (usize, Value) {
+    let mut count = 0;
+    let mut total = Value::Int(0);
+    for value in values {
+        let next = match (&total, value) {
+            (Value::Int(t), Value::Int(i)) => match t.checked_add(*i) {
+                Some(t) => Value::Int(t),
+                None => Value::Float(*t as f64 + *i as f64),
+            },
+            (Value::Int(t), Value::Float(f)) if !f.is_nan() => Value::Float(*t as f64 + f),
+            (Value::Float(t), Value::Int(i)) => Value::Float(t + *i as f64),
+            (Value::Float(t), Value::Float(f)) if !f.is_nan() => Value::Float(t + f),
+            _ => continue,
+        };
+        total = next;
+        count += 1;
+    }
+    (count, total)
+}
+
+// the smallest or largest value, nulls and nan left out
+fn extreme<'a>(values: impl Iterator<Item = &'a Value>, want: Ordering) -> Value {
+    values
+        .filter(|v| !matches!(v, Value::Null) && !matches!(v, Value::Float(f) if f.is_nan()))
+        .fold(None, |best: Option<&Value>, v| match best {
+            Some(b) if v.sort_cmp(b) != want => Some(b),
+            _ => Some(v),
+        })
+        .cloned()
+        .unwrap_or(Value::Null)
+}
+
+// a value as a hash map key. floats by their bits, with -0.0 folded into 0.0.
+// arrays aren't one, their elements are
+#[derive(Hash, PartialEq, Eq)]
+enum TermKey<'a> {
+    Int(i64),
+    Float(u64),
+    String(&'a str),
+    Bytes(&'a [u8]),
+    Bool(bool),
+    Timestamp(i64),
+    Uuid(&'a [u8; 16]),
+}
+
+impl<'a> TermKey<'a> {
+    fn new(value: &'a Value) -> Option<Self> {
+        match value {
+            Value::Int(i) => Some(Self::Int(*i)),
+            Value::Float(f) => Some(Self::Float(if *f == 0.0 { 0 } else { f.to_bits() })),
+            Value::String(s) => Some(Self::String(s)),
+            Value::Bytes(b) => Some(Self::Bytes(b)),
+            Value::Bool(b) => Some(Self::Bool(*b)),
+            Value::Timestamp(t) => Some(Self::Timestamp(*t)),
+            Value::Uuid(u) => Some(Self::Uuid(u)),
+            Value::Null | Value::Array(_) => None,
+        }
+    }
+}
+
+// how many of `row_ids` hold each value of `column`, nulls left out, in no
+// particular order. an array counts once for each distinct element
+pub fn value_counts<'a>(column: usize, row_ids: &[RowId], rows: &'a dyn RowSource) -> Vec<(&'a Value, usize)> {
+    let mut counts: HashMap<TermKey, (&Value, usize)> = HashMap::new();
+    for value in row_ids.iter().filter_map(|id| rows.value(*id, column)) {
+        for value in elements(value) {
+            if let Some(key) = TermKey::new(value) {
+                counts.entry(key).or_insert((value, 0)).1 += 1;
+            }
+        }
+    }
+    counts.into_values().collect()
+}
+
+// buckets by row count, ties going to the smaller value. rows without a
+// value don't land in any bucket, an array lands in one per distinct element
+fn terms(
+    column: usize,
+    size: usize,
+    aggregations: &[(String, Aggregation)],
+    row_ids: &[RowId],
+    rows: &dyn RowSource,
+) -> AggregationResult {
+    let mut groups: HashMap<TermKey, (&Value, Vec<RowId>)> = HashMap::new();
+    for id in row_ids {
+        let value = match rows.value(*id, column) {
+            Some(value) => value,
+            None => continue,
+        };
+        for value in elements(value) {
+            if let Some(key) = TermKey::new(value) {
+                groups.entry(key).or_insert_with(|| (value, Vec::new())).1.push(*id);
+            }
+        }
+    }
+
+    let mut groups: Vec<(&Value, Vec<RowId>)> = groups.into_values().collect();
+    groups.sort_unstable_by(|a, b| b.1.len().cmp(&a.1.len()).then_with(|| a.0.sort_cmp(b.0)));
+    let other = groups.iter().skip(size).map(|(_, ids)| ids.len()).sum();
+    groups.truncate(size);
+
+    let buckets = groups
+        .into_iter()
+        .map(|(key, ids)| Bucket {
+            key: key.clone(),
+            count: ids.len(),
+            aggregations: aggregate(aggregations, &ids, rows),
+        })
+        .collect();
+    AggregationResult::Terms { buckets, other }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::Storage;
+
+    fn storage(rows: Vec<Vec<Value>>) -> (Storage, Vec<RowId>) {
+        let mut storage = Storage::with_capacity(rows.len());
+        let ids = rows.into_iter().map(|values| storage.insert(values)).collect();
+        (storage, ids)
+    }
+
+    fn value(results: &[(String, AggregationResult)], name: &str) -> Value {
+        match results.iter().find(|(n, _)| n == name) {
+            Some((_, AggregationResult::Value(v))) => v.clone(),
+            other => panic!("no value for {}: {:?}", name, other),
+        }
+    }
+
+    #[test]
+    fn test_metrics() {
+        let (rows, ids) = storage(vec![
+            vec![Value::Int(3), Value::Float(1.5)],
+            vec![Value::Int(-7), Value::Null],
+            vec![Value::Null, Value::Float(f64::NAN)],
+            vec![Value::Int(10), Value::Int(2)],
+        ]);
+        let aggregations = vec![
+            ("rows".to_string(), Aggregation::Count(None)),
+            ("with_a".to_string(), Aggregation::Count(Some(0))),
+            ("sum_a".to_string(), Aggregation::Sum(0)),
+            ("sum_b".to_string(), Aggregation::Sum(1)),
+            ("avg_a".to_string(), Aggregation::Avg(0)),
+            ("min_a".to_string(), Aggregation::Min(0)),
+            ("max_b".to_string(), Aggregation::Max(1)),
+        ];
+        let results = aggregate(&aggregations, &ids, &rows);
+
+        assert_eq!(value(&results, "rows"), Value::Int(4));
+        assert_eq!(value(&results, "with_a"), Value::Int(3));
+        assert_eq!(value(&results, "sum_a"), Value::Int(6));
+        assert_eq!(value(&results, "sum_b"), Value::Float(3.5));
+        assert_eq!(value(&results, "avg_a"), Value::Float(2.0));
+        assert_eq!(value(&results, "min_a"), Value::Int(-7));
+        assert_eq!(value(&results, "max_b"), Value::Int(2));
+
+        // nothing to aggregate is null, not zero
+        let results = aggregate(&aggregations, &[], &rows);
+        assert_eq!(value(&results, "rows"), Value::Int(0));
+        assert_eq!(value(&results, "sum_a"), Value::Null);
+        assert_eq!(value(&results, "min_a"), Value::Null);
+
+        // ints that would overflow carry on as floats
+        let (rows, ids) = storage(vec![vec![Value::Int(i64::MAX)], vec![Value::Int(1)]]);
+        let results = aggregate(&[("sum".to_string(), Aggregation::Sum(0))], &ids, &rows);
+        assert_eq!(value(&results, "sum"), Value::Float(i64::MAX as f64 + 1.0));
+    }
+
+    #[test]
+    fn test_terms() {
+        let (rows, ids) = storage(vec![
+            vec![Value::String("active".into()), Value::Int(10)],
+            vec![Value::String("banned".into()), Value::Int(5)],
+            vec![Value::String("active".into()), Value::Int(20)],
+            vec![Value::Null, Value::Int(1)],
+            vec![Value::String("idle".into()), Value::Int(7)],
+            vec![Value::String("banned".into()), Value::Int(1)],
+            vec![Value::String("active".into()), Value::Int(30)],
+        ]);
+        let aggregations = vec![("status".to_string(), Aggregation::Terms {
+            column: 0,
+            size: 2,
+            aggregations: vec![("total".to_string(), Aggregation::Sum(1))],
+        })];
+
+        let (buckets, other) = match &aggregate(&aggregations, &ids, &rows)[0].1 {
+            AggregationResult::Terms { buckets, other } => (buckets.clone(), *other),
+            other => panic!("not terms: {:?}", other),
+        };
+        assert_eq!(other, 1);
+        let summary: Vec<(Value, usize, Value)> = buckets
+            .iter()
+            .map(|b| (b.key.clone(), b.count, value(&b.aggregations, "total")))
+            .collect();
+        assert_eq!(summary, vec![
+            (Value::String("active".into()), 3, Value::Int(60)),
+            (Value::String("banned".into()), 2, Value::Int(6)),
+        ]);
+
+        // an array row counts in the bucket of each distinct element
+        let strings = |items: &[&str]| Value::Array(items.iter().map(|&s| Value::String(s.into())).collect());
+        let (rows, ids) = storage(vec![
+            vec![strings(&["red", "blue", "red"]), Value::Int(1)],
+            vec![strings(&["blue"]), Value::Int(2)],
+            vec![strings(&[]), Value::Int(4)],
+        ]);
+        let buckets = match &aggregate(&aggregations, &ids, &rows)[0].1 {
+            AggregationResult::Terms { buckets, .. } => buckets.clone(),
+            other => panic!("not terms: {:?}", other),
+        };
+        let summary: Vec<(Value, usize, Value)> = buckets
+            .iter()
+            .map(|b| (b.key.clone(), b.count, value(&b.aggregations, "total")))
+            .collect();
+        assert_eq!(summary, vec![
+            (Value::String("blue".into()), 2, Value::Int(3)),
+            (Value::String("red".into()), 1, Value::Int(1)),
+        ]);
+    }
+}
@@ -0,0 +1,486 @@
+// text analysis for full-text indexes: split text into tokens, then run each
+// token through a chain of filters. the same analyzer has to run when text is
+// indexed, removed and queried, otherwise terms won't line up
+
+use std::borrow::Cow;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TokenFilter {
+    Lowercase,      // unicode lowercasing
+    AsciiFolding,   // "café" -> "cafe", "straße" -> "strasse"
+    StopWords,      // drops "the", "and", ... (english)
+    Stem,           // porter stemmer, "running" -> "run" (english)
+}
+
+impl TokenFilter {
+    pub const ALL: [TokenFilter; 4] = [Self::Lowercase, Self::AsciiFolding, Self::StopWords, Self::Stem];
+
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(s: &str) -> Option<Self> {
+        match s.to_lowercase().as_str() {
+            "lowercase" => Some(Self::Lowercase),
+            "asciifolding" | "ascii_folding" | "fold" => Some(Self::AsciiFolding),
+            "stop" | "stopwords" | "stop_words" => Some(Self::StopWords),
+            "stem" | "stemmer" | "porter" => Some(Self::Stem),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Lowercase => "lowercase",
+            Self::AsciiFolding => "asciifolding",
+            Self::StopWords => "stop",
+            Self::Stem => "stem",
+        }
+    }
+
+    // stable tag for the on-disk format
+    pub fn tag(self) -> u8 {
+        match self {
+            Self::Lowercase => 0,
+            Self::AsciiFolding => 1,
+            Self::StopWords => 2,
+            Self::Stem => 3,
+        }
+    }
+
+    pub fn from_tag(tag: u8) -> Option<Self> {
+        Self::ALL.into_iter().find(|f| f.tag() == tag)
+    }
+
+    fn apply<'a>(self, token: Cow<'a, str>) -> Option<Cow<'a, str>> {
+        match self {
+            Self::Lowercase if token.chars().any(char::is_uppercase) => Some(Cow::Owned(token.to_lowercase())),
+            Self::AsciiFolding if !token.is_ascii() => Some(fold(token)),
+            Self::StopWords if STOP_WORDS.contains(&&*token) => None,
+            Self::Stem => Some(match stem(&token) {
+                Some(stemmed) => Cow::Owned(stemmed),
+                None => token,
+            }),
+            _ => Some(token),
+        }
+    }
+}
+
+// a tokenizer plus the filters every token goes through, in order
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Analyzer {
+    filters: Vec<TokenFilter>,
+}
+
+impl Analyzer {
+    pub fn new(filters: Vec<TokenFilter>) -> Self {
+        Self { filters }
+    }
+
+    // tokens exactly as written, case-sensitive
+    pub fn raw() -> Self {
+        Self::new(Vec::new())
+    }
+
+    // case and accent insensitive, the default
+    pub fn standard() -> Self {
+        Self::new(vec![TokenFilter::Lowercase, TokenFilter::AsciiFolding])
+    }
+
+    // standard plus stop words and stemming
+    pub fn english() -> Self {
+        Self::new(vec![TokenFilter::Lowercase, TokenFilter::AsciiFolding, TokenFilter::StopWords, TokenFilter::Stem])
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "raw" | "none" | "keyword" => Some(Self::raw()),
+            "standard" | "default" => Some(Self::standard()),
+            "english" | "en" => Some(Self::english()),
+            _ => None,
+        }
+    }
+
+    // the preset name, or the filters joined with "+" for a custom chain
+    pub fn name(&self) -> String {
+        if self.filters.is_empty() {
+            return "raw".to_string();
+        }
+        if *self == Self::standard() {
+            return "standard".to_string();
+        }
+        if *self == Self::english() {
+            return "english".to_string();
+        }
+        self.filters.iter().map(|f| f.name()).collect::<Vec<_>>().join("+")
+    }
+
+    pub fn filters(&self) -> &[TokenFilter] {
+        &self.filters
+    }
+
+    // split on anything that isn't a letter or digit
+    #[inline(always)]
+    pub fn tokenize(text: &str) -> impl Iterator<Item = &str> {
+        text.split(|c: char| !c.is_alphanumeric())
+            .filter(|s| !s.is_empty())
+    }
+
+    // the terms `text` is indexed under, in order. dropped tokens leave no gap
+    pub fn analyze<'a>(&self, text: &'a str) -> Vec<Cow<'a, str>> {
+        Self::tokenize(text)
+            .filter_map(|token| {
+                self.filters.iter().try_fold(Cow::Borrowed(token), |token, filter| filter.apply(token))
+            })
+            .filter(|token| !token.is_empty())
+            .collect()
+    }
+}
+
+impl Default for Analyzer {
+    fn default() -> Self {
+        Self::standard()
+    }
+}
+
+// lucene's english stop word list
+const STOP_WORDS: [&str; 33] = [
+    "a", "an", "and", "are", "as", "at", "be", "but", "by", "for", "if", "in", "into", "is", "it", "no",
+    "not", "of", "on", "or", "such", "that", "the", "their", "then", "there", "these", "they", "this",
+    "to", "was", "will", "with",
+];
+
+fn fold(token: Cow<'_, str>) -> Cow<'_, str> {
+    let mut out = String::with_capacity(token.len());
+    for c in token.chars() {
+        match fold_char(c) {
+            Some(folded) => out.push_str(folded),
+            None => out.push(c),
+        }
+    }
+    Cow::Owned(out)
+}
+
+// latin-1 and latin extended-a letters with an ascii spelling
+fn fold_char(c: char) -> Option<&'static str> {
+    Some(match c {
+        'À'..='Å' | 'Ā' | 'Ă' | 'Ą' => "A",
+        'à'..='å' | 'ā' | 'ă' | 'ą' => "a",
+        'Æ' => "AE",
+        'æ' => "ae",
+        'Ç' | 'Ć' | 'Ĉ' | 'Ċ' | 'Č' => "C",
+        'ç' | 'ć' | 'ĉ' | 'ċ' | 'č' => "c",
+        'Ð' | 'Ď' | 'Đ' => "D",
+        'ð' | 'ď' | 'đ' => "d",
+        'È'..='Ë' | 'Ē' | 'Ĕ' | 'Ė' | 'Ę' | 'Ě' => "E",
+        'è'..='ë' | 'ē' | 'ĕ' | 'ė' | 'ę' | 'ě' => "e",
+        'Ĝ' | 'Ğ' | 'Ġ' | 'Ģ' => "G",
+        'ĝ' | 'ğ' | 'ġ' | 'ģ' => "g",
+        'Ĥ' | 'Ħ' => "H",
+        'ĥ' | 'ħ' => "h",
+        'Ì'..='Ï' | 'Ĩ' | 'Ī' | 'Ĭ' | 'Į' | 'İ' => "I",
+        'ì'..='ï' | 'ĩ' | 'ī' | 'ĭ' | 'į' | 'ı' => "i",
+        'Ĳ' => "IJ",
+        'ĳ' => "ij",
+        'Ĵ' => "J",
+        'ĵ' => "j",
+        'Ķ' => "K",
+        'ķ' | 'ĸ' => "k",
+        'Ĺ' | 'Ļ' | 'Ľ' | 'Ŀ' | 'Ł' => "L",
+        'ĺ' | 'ļ' | 'ľ' | 'ŀ' | 'ł' => "l",
+        'Ñ' | 'Ń' | 'Ņ' | 'Ň' | 'Ŋ' => "N",
+        'ñ' | 'ń' | 'ņ' | 'ň' | 'ŉ' | 'ŋ' => "n",
+        'Ò'..='Ö' | 'Ø' | 'Ō' | 'Ŏ' | 'Ő' => "O",
+        'ò'..='ö' | 'ø' | 'ō' | 'ŏ' | 'ő' => "o",
+        'Œ' => "OE",
+        'œ' => "oe",
+        'Ŕ' | 'Ŗ' | 'Ř' => "R",
+        'ŕ' | 'ŗ' | 'ř' => "r",
+        'Ś' | 'Ŝ' | 'Ş' | 'Š' => "S",
+        'ś' | 'ŝ' | 'ş' | 'š' | 'ſ' => "s",
+        'ß' => "ss",
+        'Ţ' | 'Ť' | 'Ŧ' => "T",
+        'ţ' | 'ť' | 'ŧ' => "t",
+        'Þ' => "TH",
+        'þ' => "th",
+        'Ù'..='Ü' | 'Ũ' | 'Ū' | 'Ŭ' | 'Ů' | 'Ű' | 'Ų' => "U",
+        'ù'..='ü' | 'ũ' | 'ū' | 'ŭ' | 'ů' | 'ű' | 'ų' => "u",
+        'Ŵ' => "W",
+        'ŵ' => "w",
+        'Ý' | 'Ŷ' | 'Ÿ' => "Y",
+        'ý' | 'ÿ' | 'ŷ' => "y",
+        'Ź' | 'Ż' | 'Ž' => "Z",
+        'ź' | 'ż' | 'ž' => "z",
+        _ => return None,
+    })
+}
+
+// porter (1980) stemmer over lowercase ascii words. none when the word is
+// left alone: too short, not plain ascii, or nothing to strip
+pub fn stem(word: &str) -> Option<String> {
+    if word.len() <= 2 || !word.bytes().all(|b| b.is_ascii_lowercase()) {
+        return None;
+    }
+
+    let mut s = Stemmer { b: word.as_bytes().to_vec(), k: word.len() - 1, j: 0 };
+    s.step1ab();
+    if s.k > 0 {
+        s.step1c();
+        s.step2();
+        s.step3();
+        s.step4();
+        s.step5();
+    }
+
+    s.b.truncate(s.k + 1);
+    if s.b == word.as_bytes() {
+        return None;
+    }
+    String::from_utf8(s.b).ok()
+}
+
+// the word lives in b[0..=k], j marks the end of the stem once a suffix matched
+struct Stemmer {
+    b: Vec<u8>,
+    k: usize,
+    j: usize,
+}
+
+impl Stemmer {
+    fn cons(&self, i: usize) -> bool {
+        match self.b[i] {
+            b'a' | b'e' | b'i' | b'o' | b'u' => false,
+            b'y' => i == 0 || !self.cons(i - 1),
+            _ => true,
+        }
+    }
+
+    // number of vowel-consonant sequences in b[0..j]
+    fn m(&self) -> usize {
+        let mut n = 0;
+        let mut i = 0;
+        let end = self.j;
+        loop {
+            if i >= end {
+                return n;
+            }
+            if !self.cons(i) {
+                break;
+            }
+            i += 1;
+        }
+        i += 1;
+        loop {
+            loop {
+                if i >= end {
+                    return n;
+                }
+                if self.cons(i) {
+                    break;
+                }
+                i += 1;
+            }
+            i += 1;
+            n += 1;
+            loop {
+                if i >= end {
+                    return n;
+                }
+                if !self.cons(i) {
+                    break;
+                }
+                i += 1;
+            }
+            i += 1;
+        }
+    }
+
+    fn vowel_in_stem(&self) -> bool {
+        (0..self.j).any(|i| !self.cons(i))
+    }
+
+    fn double_cons(&self, i: usize) -> bool {
+        i >= 1 && self.b[i] == self.b[i - 1] && self.cons(i)
+    }
+
+    // consonant-vowel-consonant ending at i, the last not w, x or y
+    fn cvc(&self, i: usize) -> bool {
+        if i < 2 || !self.cons(i) || self.cons(i - 1) || !self.cons(i - 2) {
+            return false;
+        }
+        !matches!(self.b[i], b'w' | b'x' | b'y')
+    }
+
+    // whether b[0..=k] ends with `s`, setting j to the stem length if so
+    fn ends(&mut self, s: &str) -> bool {
+        let s = s.as_bytes();
+        if s.len() > self.k + 1 || &self.b[self.k + 1 - s.len()..=self.k] != s {
+            return false;
+        }
+        self.j = self.k + 1 - s.len();
+        true
+    }
+
+    fn set_to(&mut self, s: &str) {
+        self.b.truncate(self.j);
+        self.b.extend_from_slice(s.as_bytes());
+        self.k = self.b.len() - 1;
+    }
+
+    fn replace(&mut self, s: &str) {
+        if self.m() > 0 {
+            self.set_to(s);
+        }
+    }
+
+    // plurals and -ed / -ing
+    fn step1ab(&mut self) {
+        if self.b[self.k] == b's' {
+            if self.ends("sses") {
+                self.k -= 2;
+            } else if self.ends("ies") {
+                self.set_to("i");
+            } else if self.b[self.k - 1] != b's' {
+                self.k -= 1;
+            }
+        }
+        self.b.truncate(self.k + 1);
+
+        if self.ends("eed") {
+            if self.m() > 0 {
+                self.k -= 1;
+            }
+        } else if (self.ends("ed") || self.ends("ing")) && self.vowel_in_stem() {
+            self.k = self.j - 1;
+            self.b.truncate(self.k + 1);
+            self.j = self.k + 1;
+            if self.ends("at") {
+                self.set_to("ate");
+            } else if self.ends("bl") {
+                self.set_to("ble");
+            } else if self.ends("iz") {
+                self.set_to("ize");
+            } else if self.double_cons(self.k) {
+                if !matches!(self.b[self.k], b'l' | b's' | b'z') {
+                    self.k -= 1;
+                }
+            } else {
+                self.j = self.k + 1;
+                if self.m() == 1 && self.cvc(self.k) {
+                    self.set_to("e");
+                }
+            }
+        }
+        self.b.truncate(self.k + 1);
+    }
+
+    // terminal y to i when there's another vowel in the stem
+    fn step1c(&mut self) {
+        if self.ends("y") && self.vowel_in_stem() {
+            self.b[self.k] = b'i';
+        }
+    }
+
+    // double suffixes to single ones
+    fn step2(&mut self) {
+        const RULES: [(&str, &str); 21] = [
+            ("ational", "ate"), ("tional", "tion"), ("enci", "ence"), ("anci", "ance"), ("izer", "ize"),
+            ("bli", "ble"), ("alli", "al"), ("entli", "ent"), ("eli", "e"), ("ousli", "ous"),
+            ("ization", "ize"), ("ation", "ate"), ("ator", "ate"), ("alism", "al"), ("iveness", "ive"),
+            ("fulness", "ful"), ("ousness", "ous"), ("aliti", "al"), ("iviti", "ive"), ("biliti", "ble"),
+            ("logi", "log"),
+        ];
+        self.apply_rules(&RULES);
+    }
+
+    // -ic-, -full, -ness etc.
+    fn step3(&mut self) {
+        const RULES: [(&str, &str); 7] = [
+            ("icate", "ic"), ("ative", ""), ("alize", "al"), ("iciti", "ic"), ("ical", "ic"), ("ful", ""), ("ness", ""),
+        ];
+        self.apply_rules(&RULES);
+    }
+
+    // the first matching suffix wins, and it's only replaced if the stem is long enough
+    fn apply_rules(&mut self, rules: &[(&str, &str)]) {
+        if let Some((_, to)) = rules.iter().find(|(from, _)| self.ends(from)) {
+            self.replace(to);
+        }
+    }
+
+    // -ant, -ence etc. in <c>vcvc<v>
+    fn step4(&mut self) {
+        const SUFFIXES: [&str; 19] = [
+            "al", "ance", "ence", "er", "ic", "able", "ible", "ant", "ement", "ment", "ent", "ion", "ou",
+            "ism", "ate", "iti", "ous", "ive", "ize",
+        ];
+        let found = SUFFIXES.iter().find(|s| self.ends(s)).copied();
+        let strip = match found {
+            Some("ion") => self.j > 0 && matches!(self.b[self.j - 1], b's' | b't'),
+            Some(_) => true,
+            None => false,
+        };
+        if strip && self.m() > 1 {
+            self.k = self.j - 1;
+        }
+    }
+
+    // final -e and -ll
+    // both checks measure the word as it was coming in, like the original
+    fn step5(&mut self) {
+        self.j = self.k + 1;
+        if self.b[self.k] == b'e' {
+            let m = self.m();
+            if m > 1 || (m == 1 && !self.cvc(self.k - 1)) {
+                self.k -= 1;
+            }
+        }
+        if self.b[self.k] == b'l' && self.double_cons(self.k) && self.m() > 1 {
+            self.k -= 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn terms(analyzer: &Analyzer, text: &str) -> Vec<String> {
+        analyzer.analyze(text).into_iter().map(Cow::into_owned).collect()
+    }
+
+    #[test]
+    fn test_presets() {
+        let text = "The Café was RUNNING, naïvely!";
+        assert_eq!(terms(&Analyzer::raw(), text), vec!["The", "Café", "was", "RUNNING", "naïvely"]);
+        assert_eq!(terms(&Analyzer::standard(), text), vec!["the", "cafe", "was", "running", "naively"]);
+        assert_eq!(terms(&Analyzer::english(), text), vec!["cafe", "run", "naiv"]);
+
+        assert_eq!(Analyzer::from_name("English"), Some(Analyzer::english()));
+        assert_eq!(Analyzer::english().name(), "english");
+        assert_eq!(Analyzer::new(vec![TokenFilter::Lowercase, TokenFilter::Stem]).name(), "lowercase+stem");
+        assert_eq!(Analyzer::default().name(), "standard");
+    }
+
+    #[test]
+    fn test_folding() {
+        let analyzer = Analyzer::new(vec![TokenFilter::AsciiFolding]);
+        assert_eq!(terms(&analyzer, "Straße Ærø Łódź"), vec!["Strasse", "AEro", "Lodz"]);
+        // scripts without an ascii spelling are left alone
+        assert_eq!(terms(&analyzer, "日本語"), vec!["日本語"]);
+    }
+
+    #[test]
+    fn test_porter_stemmer() {
+        let cases = [
+            ("caresses", "caress"), ("ponies", "poni"), ("cats", "cat"), ("feed", "feed"),
+            ("agreed", "agre"), ("plastered", "plaster"), ("motoring", "motor"), ("sing", "sing"),
+            ("conflated", "conflat"), ("hopping", "hop"), ("falling", "fall"), ("filing", "file"),
+            ("happy", "happi"), ("relational", "relat"), ("conditional", "condit"), ("digitizer", "digit"),
+            ("generalization", "gener"), ("hopeful", "hope"), ("goodness", "good"), ("revival", "reviv"),
+            ("adoption", "adopt"), ("controll", "control"), ("rate", "rate"), ("running", "run"),
+            ("runs", "run"), ("as", "as"),
+        ];
+        for (word, expected) in cases {
+            assert_eq!(stem(word).as_deref().unwrap_or(word), expected, "stemming {}", word);
+        }
+        assert_eq!(stem("Running"), None);
+    }
+}
@@ -1,5 +1,7 @@
 use std::env;
 
+use crate::persist::FsyncPolicy;
+
 // controls which operations require authentication
 #[derive(Clone, Copy, PartialEq, Debug)]
 pub enum AuthLevel {
@@ -10,6 +12,7 @@ pub enum AuthLevel {
 }
 
 impl AuthLevel {
+    #[allow(clippy::should_implement_trait)]
     pub fn from_str(s: &str) -> Option<Self> {
         match s.to_lowercase().as_str() {
             "none" | "off" | "false" | "0" => Some(Self::None),
@@ -42,6 +45,9 @@ pub struct Config {
     pub admin_pass: String,
     pub log_level: String,
     pub max_connections: usize,
+    pub data_dir: Option<String>,           // persistence is off when unset
+    pub snapshot_interval_secs: u64,        // 0 = only on demand
+    pub wal_fsync: Option<FsyncPolicy>,     // none = write-ahead log disabled
 }
 
 impl Config {
@@ -52,11 +58,11 @@ impl Config {
             .and_then(|s| AuthLevel::from_str(&s))
             .or_else(|| {
                 // backwards compatibility: treat old bool as all-or-nothing
-                env::var("QUICKSET_AUTH").ok().and_then(|s| {
+                env::var("QUICKSET_AUTH").ok().map(|s| {
                     if s == "1" || s.to_lowercase() == "true" {
-                        Some(AuthLevel::All)
+                        AuthLevel::All
                     } else {
-                        Some(AuthLevel::None)
+                        AuthLevel::None
                     }
                 })
             })
@@ -76,6 +82,16 @@ impl Config {
                 .ok()
                 .and_then(|s| s.parse().ok())
                 .unwrap_or(1000),
+            data_dir: env::var("QUICKSET_DATA_DIR").ok().filter(|s| !s.is_empty()),
+            snapshot_interval_secs: env::var("QUICKSET_SNAPSHOT_INTERVAL")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(300),
+            wal_fsync: match env::var("QUICKSET_WAL_FSYNC") {
+                Ok(s) if matches!(s.to_lowercase().as_str(), "off" | "disabled" | "false") => None,
+                Ok(s) => Some(FsyncPolicy::from_str(&s).unwrap_or(FsyncPolicy::Always)),
+                Err(_) => Some(FsyncPolicy::Always),
+            },
         }
     }
 
@@ -162,9 +178,13 @@ mod tests {
             admin_pass: "admin".to_string(),
             log_level: "info".to_string(),
             max_connections: 1000,
+            data_dir: None,
+            snapshot_interval_secs: 300,
+            wal_fsync: Some(FsyncPolicy::Always),
         };
         
         assert_eq!(config.address(), "0.0.0.0:8080");
+        assert!(config.data_dir.is_none());
     }
 
     #[test]
@@ -0,0 +1,108 @@
+// levenshtein automaton for typo-tolerant search. the automaton is run as a
+// row of the edit distance table: feeding it a char computes the next row, so
+// walking a trie (or any term) only costs one row per char, and a branch is
+// dropped as soon as no completion could come back within the limit
+
+// more than this and nearly every short word matches every other one
+pub const MAX_EDITS: u32 = 2;
+
+pub struct LevenshteinAutomaton {
+    query: Vec<char>,
+    max_edits: u32,
+}
+
+// distances from each prefix of the query to the input consumed so far,
+// capped at max_edits + 1
+#[derive(Clone, Debug, PartialEq)]
+pub struct State(Vec<u32>);
+
+impl LevenshteinAutomaton {
+    pub fn new(query: &str, max_edits: u32) -> Self {
+        Self {
+            query: query.chars().collect(),
+            max_edits,
+        }
+    }
+
+    pub fn max_edits(&self) -> u32 {
+        self.max_edits
+    }
+
+    pub fn start(&self) -> State {
+        let cap = self.max_edits + 1;
+        State((0..=self.query.len() as u32).map(|d| d.min(cap)).collect())
+    }
+
+    pub fn step(&self, state: &State, c: char) -> State {
+        let cap = self.max_edits + 1;
+        let prev = &state.0;
+        let mut next = Vec::with_capacity(prev.len());
+        next.push((prev[0] + 1).min(cap));
+        for (i, q) in self.query.iter().enumerate() {
+            let substitute = prev[i] + u32::from(*q != c);
+            let delete = prev[i + 1] + 1;
+            let insert = next[i] + 1;
+            next.push(substitute.min(delete).min(insert).min(cap));
+        }
+        State(next)
+    }
+
+    // the edit distance if the input so far is close enough to the query
+    pub fn distance(&self, state: &State) -> Option<u32> {
+        let d = *state.0.last().unwrap();
+        (d <= self.max_edits).then_some(d)
+    }
+
+    // whether some continuation of the input could still match
+    pub fn can_match(&self, state: &State) -> bool {
+        state.0.iter().any(|d| *d <= self.max_edits)
+    }
+
+    // run a whole string through, giving up early once it can't match
+    pub fn eval(&self, text: &str) -> Option<u32> {
+        let mut state = self.start();
+        for c in text.chars() {
+            state = self.step(&state, c);
+            if !self.can_match(&state) {
+                return None;
+            }
+        }
+        self.distance(&state)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_automaton_distances() {
+        let automaton = LevenshteinAutomaton::new("kitten", 2);
+        assert_eq!(automaton.eval("kitten"), Some(0));
+        assert_eq!(automaton.eval("sitten"), Some(1));
+        assert_eq!(automaton.eval("kittn"), Some(1));
+        assert_eq!(automaton.eval("kittens"), Some(1));
+        assert_eq!(automaton.eval("sittin"), Some(2));
+        assert_eq!(automaton.eval("sitting"), None);
+        assert_eq!(automaton.eval(""), None);
+
+        // chars, not bytes
+        let automaton = LevenshteinAutomaton::new("café", 1);
+        assert_eq!(automaton.eval("cafe"), Some(1));
+        assert_eq!(LevenshteinAutomaton::new("", 1).eval("a"), Some(1));
+    }
+
+    #[test]
+    fn test_automaton_prunes() {
+        let automaton = LevenshteinAutomaton::new("abc", 1);
+        let mut state = automaton.start();
+        for c in "xy".chars() {
+            state = automaton.step(&state, c);
+        }
+        assert!(!automaton.can_match(&state));
+
+        let state = automaton.step(&automaton.start(), 'x');
+        assert!(automaton.can_match(&state));
+        assert_eq!(automaton.distance(&state), None);
+    }
+}
@@ -6,18 +6,20 @@ use std::time::Instant;
 
 use crate::auth::{AuthManager, Role};
 use crate::config::{AuthLevel, Config, SyncSourceConfig};
+use crate::index::{IndexKind, SUGGEST_TOP_K};
 use crate::log::{LogLevel, Logger};
+use crate::persist::{PersistError, Persistence};
 use crate::query::*;
-use crate::search::SearchType;
 use crate::storage::Value;
 use crate::sync::{ClickHouseSource, Source, SourceConfig, SyncConfig, SyncManager, SyncTable};
-use crate::table::{Column, ColumnType, Database};
+use crate::table::{Coercion, Column, ColumnType, Database, Table, TableError, Upsert};
 use crate::{log_debug, log_error, log_info, log_warn};
 
 pub struct HttpServer {
     db: Arc<RwLock<Database>>,
     auth: Arc<AuthManager>,
     sync: Option<Arc<SyncManager>>,
+    persist: Option<Arc<Persistence>>,
     config: Config,
 }
 
@@ -27,7 +29,16 @@ impl HttpServer {
         Self::with_config(config)
     }
 
+    // panics if the data directory holds a snapshot that can't be loaded,
+    // use try_with_config to handle that yourself
     pub fn with_config(config: Config) -> Self {
+        match Self::try_with_config(config) {
+            Ok(server) => server,
+            Err(e) => panic!("failed to load data: {}", e),
+        }
+    }
+
+    pub fn try_with_config(config: Config) -> Result<Self, PersistError> {
         if let Some(level) = LogLevel::from_str(&config.log_level) {
             Logger::init(level);
         }
@@ -37,17 +48,30 @@ impl HttpServer {
             auth.add_user(&config.admin_user, &config.admin_pass, Role::Admin).ok();
         }
 
-        let db = Arc::new(RwLock::new(Database::new()));
+        // restore from the data directory if persistence is configured
+        let (db, persist) = match &config.data_dir {
+            Some(dir) => {
+                let persist = Persistence::open(dir, config.snapshot_interval_secs, config.wal_fsync)?;
+                let db = persist.load()?;
+                (Arc::new(RwLock::new(db)), Some(Arc::new(persist)))
+            }
+            None => (Arc::new(RwLock::new(Database::new())), None),
+        };
+
+        if let Some(persist) = &persist {
+            Arc::clone(persist).start_background(Arc::clone(&db));
+        }
         
         // setup sync from environment if configured
         let sync = Self::setup_sync_from_env(&db);
 
-        Self {
+        Ok(Self {
             db,
             auth: Arc::new(auth),
             sync,
+            persist,
             config,
-        }
+        })
     }
 
     pub fn with_database(db: Database) -> Self {
@@ -62,6 +86,7 @@ impl HttpServer {
             db: Arc::new(RwLock::new(db)),
             auth: Arc::new(auth),
             sync: None,
+            persist: None,
             config,
         }
     }
@@ -120,7 +145,7 @@ impl HttpServer {
         Some(manager)
     }
 
-    // parse table config string: "source:target:col1=type1,col2=type2"
+    // parse table config string: "source:target:col1=type1,col2=type2+exact+prefix"
     fn parse_table_config(s: &str) -> Option<SyncTable> {
         log_info!("sync", "parsing table config: {}", s);
         let parts: Vec<&str> = s.split(':').collect();
@@ -142,15 +167,28 @@ impl HttpServer {
                 log_debug!("sync", "column def: {:?}", col_parts);
                 if col_parts.len() >= 2 {
                     let col_name = col_parts[0];
-                    let col_type = match col_parts[1].to_lowercase().as_str() {
-                        "int" | "i64" => ColumnType::Int,
-                        "float" | "f64" => ColumnType::Float,
-                        "string" | "text" => ColumnType::String,
-                        "bytes" => ColumnType::Bytes,
-                        _ => ColumnType::String,
+                    // type, optionally followed by "+index" for each index to keep and
+                    // "+key" for primary key columns
+                    let mut spec = col_parts[1].split('+');
+                    let col_type = parse_column_type(spec.next().unwrap_or("")).unwrap_or(ColumnType::String);
+                    let (keys, names): (Vec<&str>, Vec<&str>) = spec.partition(|s| s.eq_ignore_ascii_case("key"));
+                    let names: Vec<String> = names.into_iter().map(|s| s.to_string()).collect();
+                    let indexes = if names.is_empty() {
+                        col_type.default_indexes()
+                    } else {
+                        match IndexesDef::List(names).to_index_set(col_type) {
+                            Ok(set) => set,
+                            Err(e) => {
+                                log_warn!("sync", "column {}: {}, using default indexes", col_name, e);
+                                col_type.default_indexes()
+                            }
+                        }
                     };
-                    log_info!("sync", "adding column: {} ({:?})", col_name, col_type);
-                    table = table.with_column(col_name, col_name, col_type);
+                    log_info!("sync", "adding column: {} ({})", col_name, col_type.name());
+                    table = table.with_indexed_column(col_name, col_name, col_type, indexes);
+                    if !keys.is_empty() {
+                        table.primary_key.push(col_name.to_string());
+                    }
                 }
             }
         }
@@ -168,15 +206,20 @@ impl HttpServer {
             log_info!("server", "sync enabled");
         }
 
+        if let Some(persist) = &self.persist {
+            log_info!("server", "persisting to {}", persist.data_dir().display());
+        }
+
         for stream in listener.incoming() {
             match stream {
                 Ok(stream) => {
                     let db = Arc::clone(&self.db);
                     let auth = Arc::clone(&self.auth);
                     let sync = self.sync.clone();
+                    let persist = self.persist.clone();
                     let auth_level = self.config.auth_level;
                     std::thread::spawn(move || {
-                        if let Err(e) = handle_connection(stream, db, auth, sync, auth_level) {
+                        if let Err(e) = handle_connection(stream, db, auth, sync, persist, auth_level) {
                             log_error!("http", "connection error: {}", e);
                         }
                     });
@@ -194,6 +237,10 @@ impl HttpServer {
     pub fn auth(&self) -> Arc<AuthManager> {
         Arc::clone(&self.auth)
     }
+
+    pub fn persistence(&self) -> Option<Arc<Persistence>> {
+        self.persist.clone()
+    }
 }
 
 impl Default for HttpServer {
@@ -214,7 +261,7 @@ fn parse_request(stream: &mut TcpStream) -> std::io::Result<HttpRequest> {
     let mut first_line = String::new();
     reader.read_line(&mut first_line)?;
 
-    let parts: Vec<&str> = first_line.trim().split_whitespace().collect();
+    let parts: Vec<&str> = first_line.split_whitespace().collect();
     if parts.len() < 2 {
         return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "invalid request"));
     }
@@ -262,6 +309,7 @@ fn send_response(stream: &mut TcpStream, status: u16, body: &[u8]) -> std::io::R
         401 => "Unauthorized",
         403 => "Forbidden",
         404 => "Not Found",
+        409 => "Conflict",
         500 => "Internal Server Error",
         _ => "Unknown",
     };
@@ -281,13 +329,14 @@ fn handle_connection(
     db: Arc<RwLock<Database>>,
     auth: Arc<AuthManager>,
     sync: Option<Arc<SyncManager>>,
+    persist: Option<Arc<Persistence>>,
     auth_level: AuthLevel,
 ) -> std::io::Result<()> {
     let request = parse_request(&mut stream)?;
     
     log_debug!("http", "{} {}", request.method, request.path);
     
-    let (status, response_body) = route_request(&request, db, auth, sync, auth_level);
+    let (status, response_body) = route_request(&request, db, auth, sync, persist, auth_level);
     
     if status >= 400 {
         log_warn!("http", "{} {} -> {}", request.method, request.path, status);
@@ -341,6 +390,7 @@ fn route_request(
     db: Arc<RwLock<Database>>, 
     auth: Arc<AuthManager>, 
     sync: Option<Arc<SyncManager>>,
+    persist: Option<Arc<Persistence>>,
     auth_level: AuthLevel
 ) -> (u16, String) {
     match (request.method.as_str(), request.path.as_str()) {
@@ -356,6 +406,10 @@ fn route_request(
             if let Err(e) = check_auth(request, &auth, auth_level, true, false) { return e; }
             handle_drop_table(request, db)
         }
+        ("POST", "/table/alter") => {
+            if let Err(e) = check_auth(request, &auth, auth_level, true, false) { return e; }
+            handle_alter_table(request, db)
+        }
         ("GET", "/tables") => {
             if let Err(e) = check_auth(request, &auth, auth_level, false, false) { return e; }
             handle_list_tables(db)
@@ -372,10 +426,18 @@ fn route_request(
             if let Err(e) = check_auth(request, &auth, auth_level, true, false) { return e; }
             handle_insert(request, db)
         }
+        ("POST", "/upsert") => {
+            if let Err(e) = check_auth(request, &auth, auth_level, true, false) { return e; }
+            handle_upsert(request, db)
+        }
         ("POST", "/search") => {
             if let Err(e) = check_auth(request, &auth, auth_level, false, false) { return e; }
             handle_search(request, db)
         }
+        ("POST", "/suggest") => {
+            if let Err(e) = check_auth(request, &auth, auth_level, false, false) { return e; }
+            handle_suggest(request, db)
+        }
         ("POST", "/get") => {
             if let Err(e) = check_auth(request, &auth, auth_level, false, false) { return e; }
             handle_get(request, db)
@@ -388,6 +450,18 @@ fn route_request(
             if let Err(e) = check_auth(request, &auth, auth_level, true, false) { return e; }
             handle_update(request, db)
         }
+        ("POST", "/patch") => {
+            if let Err(e) = check_auth(request, &auth, auth_level, true, false) { return e; }
+            handle_patch(request, db)
+        }
+        ("POST", "/delete_by_query") => {
+            if let Err(e) = check_auth(request, &auth, auth_level, true, false) { return e; }
+            handle_delete_by_query(request, db)
+        }
+        ("POST", "/update_by_query") => {
+            if let Err(e) = check_auth(request, &auth, auth_level, true, false) { return e; }
+            handle_update_by_query(request, db)
+        }
         // sync endpoints
         ("GET", "/sync/status") => {
             if let Err(e) = check_auth(request, &auth, auth_level, false, false) { return e; }
@@ -407,6 +481,14 @@ fn route_request(
                 Ok(_) => handle_sync_configure(request, db),
             }
         }
+        // admin endpoints
+        ("POST", "/admin/snapshot") => {
+            match check_auth(request, &auth, auth_level, true, false) {
+                Err(e) => e,
+                Ok(role) if !role.can_admin() => (403, serde_json::to_string(&ApiResponse::<()>::err("admin required")).unwrap()),
+                Ok(_) => handle_snapshot(db, persist),
+            }
+        }
         // auth endpoints
         ("POST", "/auth/user/add") => {
             match check_auth(request, &auth, auth_level, true, false) {
@@ -433,20 +515,23 @@ fn route_request(
     }
 }
 
+// look up a table, holding the catalog lock only for the lookup itself so
+// requests against different tables never wait on each other
+fn find_table(db: &RwLock<Database>, name: &str) -> Result<Arc<RwLock<Table>>, (u16, String)> {
+    db.read().unwrap()
+        .table(name)
+        .ok_or_else(|| (404, serde_json::to_string(&ApiResponse::<()>::err("table not found")).unwrap()))
+}
+
 fn handle_create_table(request: &HttpRequest, db: Arc<RwLock<Database>>) -> (u16, String) {
     let req: CreateTableRequest = match serde_json::from_slice(&request.body) {
         Ok(r) => r,
         Err(e) => return (400, serde_json::to_string(&ApiResponse::<()>::err(&e.to_string())).unwrap()),
     };
 
-    let columns: Vec<Column> = match req.columns.iter().map(|c| {
-        c.to_column_type().map(|ct| Column {
-            name: c.name.clone().into_boxed_str(),
-            col_type: ct,
-        })
-    }).collect::<Option<Vec<_>>>() {
-        Some(cols) => cols,
-        None => return (400, serde_json::to_string(&ApiResponse::<()>::err("invalid column type")).unwrap()),
+    let columns: Vec<Column> = match req.to_columns() {
+        Ok(cols) => cols,
+        Err(e) => return (400, serde_json::to_string(&ApiResponse::<()>::err(&e)).unwrap()),
     };
 
     let mut db = db.write().unwrap();
@@ -458,7 +543,9 @@ fn handle_create_table(request: &HttpRequest, db: Arc<RwLock<Database>>) -> (u16
 
     match result {
         Ok(_) => (200, serde_json::to_string(&ApiResponse::ok("table created")).unwrap()),
-        Err(e) => (400, serde_json::to_string(&ApiResponse::<()>::err(e)).unwrap()),
+        Err(e @ TableError::TableExists) => (409, serde_json::to_string(&ApiResponse::<()>::err(&e.to_string())).unwrap()),
+        Err(e @ TableError::Log) => (500, serde_json::to_string(&ApiResponse::<()>::err(&e.to_string())).unwrap()),
+        Err(e) => (400, serde_json::to_string(&ApiResponse::<()>::err(&e.to_string())).unwrap()),
     }
 }
 
@@ -474,10 +561,29 @@ fn handle_drop_table(request: &HttpRequest, db: Arc<RwLock<Database>>) -> (u16,
     };
 
     let mut db = db.write().unwrap();
-    if db.drop_table(&req.name) {
-        (200, serde_json::to_string(&ApiResponse::ok("table dropped")).unwrap())
-    } else {
-        (404, serde_json::to_string(&ApiResponse::<()>::err("table not found")).unwrap())
+    match db.drop_table(&req.name) {
+        Ok(true) => (200, serde_json::to_string(&ApiResponse::ok("table dropped")).unwrap()),
+        Ok(false) => (404, serde_json::to_string(&ApiResponse::<()>::err("table not found")).unwrap()),
+        Err(e) => (500, serde_json::to_string(&ApiResponse::<()>::err(&e.to_string())).unwrap()),
+    }
+}
+
+fn handle_alter_table(request: &HttpRequest, db: Arc<RwLock<Database>>) -> (u16, String) {
+    let req: AlterTableRequest = match serde_json::from_slice(&request.body) {
+        Ok(r) => r,
+        Err(e) => return (400, serde_json::to_string(&ApiResponse::<()>::err(&e.to_string())).unwrap()),
+    };
+    let alteration = match req.change.to_alteration() {
+        Ok(a) => a,
+        Err(e) => return (400, serde_json::to_string(&ApiResponse::<()>::err(&e)).unwrap()),
+    };
+
+    let result = db.read().unwrap().alter_table(&req.table, &alteration);
+    match result {
+        Ok(()) => (200, serde_json::to_string(&ApiResponse::ok("table altered")).unwrap()),
+        Err(e @ TableError::UnknownTable) => (404, serde_json::to_string(&ApiResponse::<()>::err(&e.to_string())).unwrap()),
+        Err(e @ TableError::Log) => (500, serde_json::to_string(&ApiResponse::<()>::err(&e.to_string())).unwrap()),
+        Err(e) => (400, serde_json::to_string(&ApiResponse::<()>::err(&e.to_string())).unwrap()),
     }
 }
 
@@ -493,29 +599,49 @@ fn handle_describe(request: &HttpRequest, db: Arc<RwLock<Database>>) -> (u16, St
     #[derive(Deserialize)]
     struct DescribeRequest { table: String }
     #[derive(Serialize)]
-    struct ColumnInfo { name: String, column_type: String }
+    struct ColumnInfo {
+        name: String,
+        column_type: String,
+        indexes: Vec<&'static str>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        analyzer: Option<String>,
+        nullable: bool,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        default: Option<JsonValue>,
+    }
     #[derive(Serialize)]
-    struct DescribeResponse { table: String, columns: Vec<ColumnInfo>, row_count: usize }
+    struct DescribeResponse {
+        table: String,
+        columns: Vec<ColumnInfo>,
+        #[serde(skip_serializing_if = "Vec::is_empty")]
+        primary_key: Vec<String>,
+        row_count: usize,
+    }
 
     let req: DescribeRequest = match serde_json::from_slice(&request.body) {
         Ok(r) => r,
         Err(e) => return (400, serde_json::to_string(&ApiResponse::<()>::err(&e.to_string())).unwrap()),
     };
 
-    let db = db.read().unwrap();
-    let table = match db.get_table(&req.table) {
-        Some(t) => t,
-        None => return (404, serde_json::to_string(&ApiResponse::<()>::err("table not found")).unwrap()),
+    let table = match find_table(&db, &req.table) {
+        Ok(t) => t,
+        Err(e) => return e,
     };
+    let table = table.read().unwrap();
 
     let columns: Vec<ColumnInfo> = table.columns().iter().map(|c| ColumnInfo {
         name: c.name.to_string(),
-        column_type: format!("{:?}", c.col_type),
+        column_type: c.col_type.name().to_string(),
+        indexes: c.indexes.iter().map(|k| k.name()).collect(),
+        analyzer: c.indexes.contains(IndexKind::FullText).then(|| c.analyzer.name()),
+        nullable: c.nullable,
+        default: (c.default != Value::Null).then(|| JsonValue::from(&c.default)),
     }).collect();
 
     let resp = DescribeResponse {
         table: req.table,
         columns,
+        primary_key: table.primary_key().iter().map(|&c| table.columns()[c].name.to_string()).collect(),
         row_count: table.len(),
     };
 
@@ -538,79 +664,137 @@ fn handle_insert(request: &HttpRequest, db: Arc<RwLock<Database>>) -> (u16, Stri
         Err(e) => return (400, serde_json::to_string(&ApiResponse::<()>::err(&e.to_string())).unwrap()),
     };
 
-    let mut db = db.write().unwrap();
-    let table = match db.get_table_mut(&req.table) {
-        Some(t) => t,
-        None => return (404, serde_json::to_string(&ApiResponse::<()>::err("table not found")).unwrap()),
+    let table = match find_table(&db, &req.table) {
+        Ok(t) => t,
+        Err(e) => return e,
     };
+    let mut table = table.write().unwrap();
 
-    let values: Vec<Vec<Value>> = req.rows.iter()
-        .map(|row| row.iter().map(|v| v.to_value()).collect())
+    // one row that doesn't fit refuses the whole batch
+    let coercion = if req.coerce { Coercion::Convert } else { Coercion::Strict };
+    let values: Result<Vec<Vec<Value>>, String> = req.rows.iter()
+        .enumerate()
+        .map(|(i, row)| row.to_values(&table).map_err(|e| format!("row {}: {}", i, e)))
         .collect();
+    let values = match values {
+        Ok(values) => values,
+        Err(e) => return (400, serde_json::to_string(&ApiResponse::<()>::err(&e)).unwrap()),
+    };
 
-    let results = table.insert_batch(values);
-    let ids: Vec<u64> = results.into_iter().filter_map(|r| r.ok()).collect();
+    let ids = match table.insert_all(values, coercion) {
+        Ok(ids) => ids,
+        Err(e @ TableError::DuplicateKey { .. }) => return (409, serde_json::to_string(&ApiResponse::<()>::err(&e.to_string())).unwrap()),
+        Err(e @ TableError::Log) => return (500, serde_json::to_string(&ApiResponse::<()>::err(&e.to_string())).unwrap()),
+        Err(e) => return (400, serde_json::to_string(&ApiResponse::<()>::err(&e.to_string())).unwrap()),
+    };
     let count = ids.len();
 
     (200, serde_json::to_string(&ApiResponse::ok(InsertResponse { ids, count })).unwrap())
 }
 
+// insert rows whose primary key is new, overwrite the rows holding the rest
+fn handle_upsert(request: &HttpRequest, db: Arc<RwLock<Database>>) -> (u16, String) {
+    let req: InsertRequest = match serde_json::from_slice(&request.body) {
+        Ok(r) => r,
+        Err(e) => return (400, serde_json::to_string(&ApiResponse::<()>::err(&e.to_string())).unwrap()),
+    };
+
+    let table = match find_table(&db, &req.table) {
+        Ok(t) => t,
+        Err(e) => return e,
+    };
+    let mut table = table.write().unwrap();
+
+    // one row that doesn't fit refuses the whole batch
+    let coercion = if req.coerce { Coercion::Convert } else { Coercion::Strict };
+    let values: Result<Vec<Vec<Value>>, String> = req.rows.iter()
+        .enumerate()
+        .map(|(i, row)| row.to_values(&table).map_err(|e| format!("row {}: {}", i, e)))
+        .collect();
+    let values = match values {
+        Ok(values) => values,
+        Err(e) => return (400, serde_json::to_string(&ApiResponse::<()>::err(&e)).unwrap()),
+    };
+
+    let upserts = match table.upsert_all(values, coercion) {
+        Ok(upserts) => upserts,
+        Err(e @ TableError::Log) => return (500, serde_json::to_string(&ApiResponse::<()>::err(&e.to_string())).unwrap()),
+        Err(e) => return (400, serde_json::to_string(&ApiResponse::<()>::err(&e.to_string())).unwrap()),
+    };
+    let inserted = upserts.iter().filter(|u| matches!(u, Upsert::Inserted(_))).count();
+    let resp = UpsertResponse {
+        ids: upserts.iter().map(|u| u.row_id()).collect(),
+        inserted,
+        updated: upserts.len() - inserted,
+    };
+
+    (200, serde_json::to_string(&ApiResponse::ok(resp)).unwrap())
+}
+
 fn handle_search(request: &HttpRequest, db: Arc<RwLock<Database>>) -> (u16, String) {
     let req: SearchRequest = match serde_json::from_slice(&request.body) {
         Ok(r) => r,
         Err(e) => return (400, serde_json::to_string(&ApiResponse::<()>::err(&e.to_string())).unwrap()),
     };
 
-    let mut db = db.write().unwrap();
-    let table = match db.get_table_mut(&req.table) {
-        Some(t) => t,
-        None => return (404, serde_json::to_string(&ApiResponse::<()>::err("table not found")).unwrap()),
+    let table = match find_table(&db, &req.table) {
+        Ok(t) => t,
+        Err(e) => return e,
     };
+    let table = table.read().unwrap();
 
-    let col_idx = match table.column_index(&req.column) {
-        Some(idx) => idx,
-        None => return (400, serde_json::to_string(&ApiResponse::<()>::err("column not found")).unwrap()),
+    let query = match req.to_query(&table) {
+        Ok(q) => q,
+        Err(e) => return (400, serde_json::to_string(&ApiResponse::<()>::err(&e)).unwrap()),
     };
-
-    let search_type = match req.search_type.as_str() {
-        "exact" => {
-            let value = match &req.value {
-                Some(v) => v.to_value(),
-                None => return (400, serde_json::to_string(&ApiResponse::<()>::err("value required for exact search")).unwrap()),
-            };
-            SearchType::Exact(value)
-        }
-        "prefix" => {
-            let prefix = match &req.prefix {
-                Some(p) => p.clone(),
-                None => return (400, serde_json::to_string(&ApiResponse::<()>::err("prefix required")).unwrap()),
-            };
-            SearchType::Prefix(prefix)
-        }
-        "fulltext" => {
-            let query = match &req.query {
-                Some(q) => q.clone(),
-                None => return (400, serde_json::to_string(&ApiResponse::<()>::err("query required")).unwrap()),
-            };
-            SearchType::FullText(query)
-        }
-        "range" => {
-            let min = req.min.unwrap_or(i64::MIN);
-            let max = req.max.unwrap_or(i64::MAX);
-            SearchType::Range { min, max }
-        }
-        "contains" => {
-            let query = match &req.query {
-                Some(q) => q.clone(),
-                None => return (400, serde_json::to_string(&ApiResponse::<()>::err("query required")).unwrap()),
-            };
-            SearchType::Contains(query)
-        }
-        _ => return (400, serde_json::to_string(&ApiResponse::<()>::err("invalid search type")).unwrap()),
+    let aggregations = match req.to_aggregations(&table) {
+        Ok(a) => a,
+        Err(e) => return (400, serde_json::to_string(&ApiResponse::<()>::err(&e)).unwrap()),
+    };
+    let facets = match req.to_facets(&table) {
+        Ok(f) => f,
+        Err(e) => return (400, serde_json::to_string(&ApiResponse::<()>::err(&e)).unwrap()),
+    };
+    let sort_keys = match req.order_by.iter().map(|o| o.to_sort_key(&table)).collect::<Result<Vec<_>, _>>() {
+        Ok(keys) => keys,
+        Err(e) => return (400, serde_json::to_string(&ApiResponse::<()>::err(&e)).unwrap()),
     };
 
-    let mut row_ids = table.search(col_idx, search_type);
+    let mut row_ids = match table.search_query(&query) {
+        Ok(ids) => ids,
+        Err(e) => return (400, serde_json::to_string(&ApiResponse::<()>::err(&e.to_string())).unwrap()),
+    };
     let total = row_ids.len();
+    // over every match, not just the page
+    let aggregations = (!aggregations.is_empty())
+        .then(|| AggregationResponse::from_results(&table.aggregate(&aggregations, &row_ids)));
+    let facets = (!facets.is_empty()).then(|| {
+        facets
+            .iter()
+            .map(|(name, facet)| {
+                let counts = table.facet(*facet, &row_ids)
+                    .into_iter()
+                    .map(|(value, count)| FacetCount { value: JsonValue::from(&value), count })
+                    .collect();
+                (name.clone(), counts)
+            })
+            .collect()
+    });
+    let scores = table.scores(&query);
+    let distances = table.distances(&query);
+
+    // matches are already in row id order, anything else is sorted here. only
+    // the rows up to the end of the requested page need to be in order.
+    // full-text matches rank by relevance and fuzzy ones by closeness unless
+    // order_by says otherwise
+    let page_end = req.limit.map(|limit| req.offset.unwrap_or(0).saturating_add(limit));
+    if !sort_keys.is_empty() {
+        row_ids = table.order(row_ids, &sort_keys, page_end);
+    } else if let Some(scores) = &scores {
+        row_ids = table.rank(row_ids, scores, page_end);
+    } else if let Some(distances) = &distances {
+        row_ids = table.closest(row_ids, distances, page_end);
+    }
 
     if let Some(offset) = req.offset {
         if offset < row_ids.len() {
@@ -627,12 +811,51 @@ fn handle_search(request: &HttpRequest, db: Arc<RwLock<Database>>) -> (u16, Stri
     let rows: Vec<RowResponse> = table.get_many(&row_ids)
         .into_iter()
         .map(|(id, values)| RowResponse {
-            id,
-            values: values.iter().map(JsonValue::from).collect(),
+            score: scores.as_ref().map(|s| s.get(&id).copied().unwrap_or(0.0)),
+            distance: distances.as_ref().map(|d| d.get(&id).copied().unwrap_or(0)),
+            ..RowResponse::new(id, values, table.columns(), req.format)
         })
         .collect();
 
-    (200, serde_json::to_string(&ApiResponse::ok(SearchResponse { rows, total })).unwrap())
+    (200, serde_json::to_string(&ApiResponse::ok(SearchResponse { rows, total, aggregations, facets })).unwrap())
+}
+
+fn handle_suggest(request: &HttpRequest, db: Arc<RwLock<Database>>) -> (u16, String) {
+    let req: SuggestRequest = match serde_json::from_slice(&request.body) {
+        Ok(r) => r,
+        Err(e) => return (400, serde_json::to_string(&ApiResponse::<()>::err(&e.to_string())).unwrap()),
+    };
+
+    let table = match find_table(&db, &req.table) {
+        Ok(t) => t,
+        Err(e) => return e,
+    };
+    let table = table.read().unwrap();
+
+    let column = match table.column_index(&req.column) {
+        Some(c) => c,
+        None => return (400, serde_json::to_string(&ApiResponse::<()>::err(&format!("column not found: {}", req.column))).unwrap()),
+    };
+    let weight = match &req.weight {
+        Some(name) => match table.column_index(name) {
+            Some(c) if matches!(table.columns()[c].col_type, ColumnType::Int | ColumnType::Float) => Some(c),
+            Some(_) => return (400, serde_json::to_string(&ApiResponse::<()>::err(&format!("weight column must be int or float: {}", name))).unwrap()),
+            None => return (400, serde_json::to_string(&ApiResponse::<()>::err(&format!("weight column not found: {}", name))).unwrap()),
+        },
+        None => None,
+    };
+
+    let limit = req.limit.unwrap_or(SUGGEST_TOP_K);
+    let suggestions = match table.suggest(column, &req.prefix, limit, weight) {
+        Ok(s) => s,
+        Err(e) => return (400, serde_json::to_string(&ApiResponse::<()>::err(&format!("{}: {}", req.column, e))).unwrap()),
+    };
+
+    let suggestions = suggestions
+        .into_iter()
+        .map(|s| SuggestionResponse { value: s.value, count: s.count, weight: s.weight })
+        .collect();
+    (200, serde_json::to_string(&ApiResponse::ok(SuggestResponse { suggestions })).unwrap())
 }
 
 fn handle_get(request: &HttpRequest, db: Arc<RwLock<Database>>) -> (u16, String) {
@@ -641,18 +864,20 @@ fn handle_get(request: &HttpRequest, db: Arc<RwLock<Database>>) -> (u16, String)
         Err(e) => return (400, serde_json::to_string(&ApiResponse::<()>::err(&e.to_string())).unwrap()),
     };
 
-    let db = db.read().unwrap();
-    let table = match db.get_table(&req.table) {
-        Some(t) => t,
-        None => return (404, serde_json::to_string(&ApiResponse::<()>::err("table not found")).unwrap()),
+    let table = match find_table(&db, &req.table) {
+        Ok(t) => t,
+        Err(e) => return e,
     };
+    let table = table.read().unwrap();
 
-    let rows: Vec<RowResponse> = table.get_many(&req.ids)
+    let ids = match resolve_rows(&req.ids, &req.keys, &table) {
+        Ok(ids) => ids,
+        Err(e) => return (400, serde_json::to_string(&ApiResponse::<()>::err(&e)).unwrap()),
+    };
+
+    let rows: Vec<RowResponse> = table.get_many(&ids)
         .into_iter()
-        .map(|(id, values)| RowResponse {
-            id,
-            values: values.iter().map(JsonValue::from).collect(),
-        })
+        .map(|(id, values)| RowResponse::new(id, values, table.columns(), req.format))
         .collect();
 
     (200, serde_json::to_string(&ApiResponse::ok(rows)).unwrap())
@@ -664,14 +889,20 @@ fn handle_delete(request: &HttpRequest, db: Arc<RwLock<Database>>) -> (u16, Stri
         Err(e) => return (400, serde_json::to_string(&ApiResponse::<()>::err(&e.to_string())).unwrap()),
     };
 
-    let mut db = db.write().unwrap();
-    let table = match db.get_table_mut(&req.table) {
-        Some(t) => t,
-        None => return (404, serde_json::to_string(&ApiResponse::<()>::err("table not found")).unwrap()),
+    let table = match find_table(&db, &req.table) {
+        Ok(t) => t,
+        Err(e) => return e,
     };
+    let mut table = table.write().unwrap();
 
-    let deleted: usize = req.ids.iter().filter(|&&id| table.delete(id)).count();
-    (200, serde_json::to_string(&ApiResponse::ok(deleted)).unwrap())
+    let ids = match resolve_rows(&req.ids, &req.keys, &table) {
+        Ok(ids) => ids,
+        Err(e) => return (400, serde_json::to_string(&ApiResponse::<()>::err(&e)).unwrap()),
+    };
+    match table.delete_many(&ids) {
+        Ok(deleted) => (200, serde_json::to_string(&ApiResponse::ok(deleted)).unwrap()),
+        Err(e) => (500, serde_json::to_string(&ApiResponse::<()>::err(&e.to_string())).unwrap()),
+    }
 }
 
 fn handle_update(request: &HttpRequest, db: Arc<RwLock<Database>>) -> (u16, String) {
@@ -680,17 +911,107 @@ fn handle_update(request: &HttpRequest, db: Arc<RwLock<Database>>) -> (u16, Stri
         Err(e) => return (400, serde_json::to_string(&ApiResponse::<()>::err(&e.to_string())).unwrap()),
     };
 
-    let mut db = db.write().unwrap();
-    let table = match db.get_table_mut(&req.table) {
-        Some(t) => t,
-        None => return (404, serde_json::to_string(&ApiResponse::<()>::err("table not found")).unwrap()),
+    let table = match find_table(&db, &req.table) {
+        Ok(t) => t,
+        Err(e) => return e,
     };
+    let mut table = table.write().unwrap();
 
-    let values: Vec<Value> = req.values.iter().map(|v| v.to_value()).collect();
-    match table.update(req.id, values) {
+    let coercion = if req.coerce { Coercion::Convert } else { Coercion::Strict };
+    match table.update(req.id, req.values.iter().map(|v| v.to_value()).collect(), coercion) {
         Ok(true) => (200, serde_json::to_string(&ApiResponse::ok("updated")).unwrap()),
         Ok(false) => (404, serde_json::to_string(&ApiResponse::<()>::err("row not found")).unwrap()),
-        Err(e) => (400, serde_json::to_string(&ApiResponse::<()>::err(e)).unwrap()),
+        Err(e @ TableError::DuplicateKey { .. }) => (409, serde_json::to_string(&ApiResponse::<()>::err(&e.to_string())).unwrap()),
+        Err(e @ TableError::Log) => (500, serde_json::to_string(&ApiResponse::<()>::err(&e.to_string())).unwrap()),
+        Err(e) => (400, serde_json::to_string(&ApiResponse::<()>::err(&e.to_string())).unwrap()),
+    }
+}
+
+fn handle_patch(request: &HttpRequest, db: Arc<RwLock<Database>>) -> (u16, String) {
+    let req: PatchRequest = match serde_json::from_slice(&request.body) {
+        Ok(r) => r,
+        Err(e) => return (400, serde_json::to_string(&ApiResponse::<()>::err(&e.to_string())).unwrap()),
+    };
+
+    let table = match find_table(&db, &req.table) {
+        Ok(t) => t,
+        Err(e) => return e,
+    };
+    let mut table = table.write().unwrap();
+
+    let coercion = if req.coerce { Coercion::Convert } else { Coercion::Strict };
+    let changes = match req.to_changes(&table) {
+        Ok(c) => c,
+        Err(e) => return (400, serde_json::to_string(&ApiResponse::<()>::err(&e)).unwrap()),
+    };
+    match table.patch(req.id, changes, coercion) {
+        Ok(true) => (200, serde_json::to_string(&ApiResponse::ok("updated")).unwrap()),
+        Ok(false) => (404, serde_json::to_string(&ApiResponse::<()>::err("row not found")).unwrap()),
+        Err(e @ TableError::DuplicateKey { .. }) => (409, serde_json::to_string(&ApiResponse::<()>::err(&e.to_string())).unwrap()),
+        Err(e @ TableError::Log) => (500, serde_json::to_string(&ApiResponse::<()>::err(&e.to_string())).unwrap()),
+        Err(e) => (400, serde_json::to_string(&ApiResponse::<()>::err(&e.to_string())).unwrap()),
+    }
+}
+
+// the search and the deletes run under one write lock, so rows written in
+// between can't be missed or caught half way
+fn handle_delete_by_query(request: &HttpRequest, db: Arc<RwLock<Database>>) -> (u16, String) {
+    let req: DeleteByQueryRequest = match serde_json::from_slice(&request.body) {
+        Ok(r) => r,
+        Err(e) => return (400, serde_json::to_string(&ApiResponse::<()>::err(&e.to_string())).unwrap()),
+    };
+
+    let table = match find_table(&db, &req.table) {
+        Ok(t) => t,
+        Err(e) => return e,
+    };
+    let mut table = table.write().unwrap();
+
+    let query = match req.clause.to_query(&table) {
+        Ok(q) => q,
+        Err(e) => return (400, serde_json::to_string(&ApiResponse::<()>::err(&e)).unwrap()),
+    };
+    let ids = match table.search_query(&query) {
+        Ok(ids) => ids,
+        Err(e) => return (400, serde_json::to_string(&ApiResponse::<()>::err(&e.to_string())).unwrap()),
+    };
+    match table.delete_many(&ids) {
+        Ok(deleted) => (200, serde_json::to_string(&ApiResponse::ok(deleted)).unwrap()),
+        Err(e) => (500, serde_json::to_string(&ApiResponse::<()>::err(&e.to_string())).unwrap()),
+    }
+}
+
+fn handle_update_by_query(request: &HttpRequest, db: Arc<RwLock<Database>>) -> (u16, String) {
+    let req: UpdateByQueryRequest = match serde_json::from_slice(&request.body) {
+        Ok(r) => r,
+        Err(e) => return (400, serde_json::to_string(&ApiResponse::<()>::err(&e.to_string())).unwrap()),
+    };
+
+    let table = match find_table(&db, &req.table) {
+        Ok(t) => t,
+        Err(e) => return e,
+    };
+    let mut table = table.write().unwrap();
+
+    let query = match req.clause.to_query(&table) {
+        Ok(q) => q,
+        Err(e) => return (400, serde_json::to_string(&ApiResponse::<()>::err(&e)).unwrap()),
+    };
+    let changes = match req.to_changes(&table) {
+        Ok(c) => c,
+        Err(e) => return (400, serde_json::to_string(&ApiResponse::<()>::err(&e)).unwrap()),
+    };
+    let ids = match table.search_query(&query) {
+        Ok(ids) => ids,
+        Err(e) => return (400, serde_json::to_string(&ApiResponse::<()>::err(&e.to_string())).unwrap()),
+    };
+
+    let coercion = if req.coerce { Coercion::Convert } else { Coercion::Strict };
+    match table.update_many(&ids, changes, coercion) {
+        Ok(updated) => (200, serde_json::to_string(&ApiResponse::ok(updated)).unwrap()),
+        Err(e @ TableError::DuplicateKey { .. }) => (409, serde_json::to_string(&ApiResponse::<()>::err(&e.to_string())).unwrap()),
+        Err(e @ TableError::Log) => (500, serde_json::to_string(&ApiResponse::<()>::err(&e.to_string())).unwrap()),
+        Err(e) => (400, serde_json::to_string(&ApiResponse::<()>::err(&e.to_string())).unwrap()),
     }
 }
 
@@ -758,6 +1079,32 @@ fn handle_list_users(auth: &AuthManager) -> (u16, String) {
     (200, serde_json::to_string(&ApiResponse::ok(users)).unwrap())
 }
 
+// admin handlers
+
+fn handle_snapshot(db: Arc<RwLock<Database>>, persist: Option<Arc<Persistence>>) -> (u16, String) {
+    let persist = match persist {
+        Some(p) => p,
+        None => return (400, serde_json::to_string(&ApiResponse::<()>::err("persistence not configured")).unwrap()),
+    };
+
+    let start = Instant::now();
+    match persist.snapshot(&db) {
+        Ok(info) => {
+            let response = SnapshotResponse {
+                tables: info.tables,
+                rows: info.rows,
+                bytes: info.bytes,
+                duration_ms: start.elapsed().as_millis() as u64,
+            };
+            (200, serde_json::to_string(&ApiResponse::ok(response)).unwrap())
+        }
+        Err(e) => {
+            log_error!("persist", "snapshot failed: {}", e);
+            (500, serde_json::to_string(&ApiResponse::<()>::err(&e.to_string())).unwrap())
+        }
+    }
+}
+
 // sync handlers
 
 fn handle_sync_status(sync: Option<Arc<SyncManager>>) -> (u16, String) {
@@ -817,6 +1164,7 @@ fn handle_sync_trigger(
                 table: r.table,
                 success: r.success,
                 rows_synced: r.rows_synced,
+                rows_failed: r.rows_failed,
                 duration_ms: r.duration_ms,
                 error: r.error,
             })
@@ -827,6 +1175,7 @@ fn handle_sync_trigger(
                 table: r.table,
                 success: r.success,
                 rows_synced: r.rows_synced,
+                rows_failed: r.rows_failed,
                 duration_ms: r.duration_ms,
                 error: r.error,
             })
@@ -873,7 +1222,7 @@ mod tests {
     fn test_with_database() {
         let mut db = Database::new();
         db.create_table("test", vec![
-            Column { name: "col".into(), col_type: ColumnType::String },
+            Column::new("col", ColumnType::String),
         ]).unwrap();
         
         let server = HttpServer::with_database(db);
@@ -881,6 +1230,723 @@ mod tests {
         assert_eq!(db.read().unwrap().table_names().len(), 1);
     }
 
+    #[test]
+    fn test_loads_snapshot_on_boot() {
+        let dir = crate::persist::test_dir("http_boot");
+        let mut config = Config::from_env();
+        config.data_dir = Some(dir.to_str().unwrap().to_string());
+        config.snapshot_interval_secs = 0;
+
+        let server = HttpServer::try_with_config(config.clone()).unwrap();
+        {
+            let db = server.database();
+            let mut db = db.write().unwrap();
+            db.create_table("test", vec![
+                Column::new("col", ColumnType::String),
+            ]).unwrap();
+            db.get_table_mut("test").unwrap().insert(vec![Value::String("kept".into())], Coercion::Strict).unwrap();
+        }
+        server.persistence().unwrap().snapshot(&server.database()).unwrap();
+
+        let restarted = HttpServer::try_with_config(config.clone()).unwrap();
+        let db = restarted.database();
+        let db = db.read().unwrap();
+        assert_eq!(db.get_table("test").unwrap().len(), 1);
+
+        // a corrupt snapshot must stop the boot instead of starting empty
+        std::fs::write(dir.join(crate::persist::SNAPSHOT_FILE), b"QSNAPSHTgarbage").unwrap();
+        assert!(HttpServer::try_with_config(config).is_err());
+    }
+
+    fn post(path: &str, body: &str) -> HttpRequest {
+        HttpRequest {
+            method: "POST".to_string(),
+            path: path.to_string(),
+            headers: HashMap::new(),
+            body: body.as_bytes().to_vec(),
+        }
+    }
+
+    #[test]
+    fn test_declared_indexes() {
+        let db = Arc::new(RwLock::new(Database::new()));
+        let (status, _) = handle_create_table(&post("/table/create", r#"{
+            "name": "users",
+            "columns": [
+                {"name": "email", "type": "string", "indexes": ["exact"]},
+                {"name": "bio", "type": "string", "indexes": "none"},
+                {"name": "age", "type": "int"}
+            ]
+        }"#), Arc::clone(&db));
+        assert_eq!(status, 200);
+        handle_insert(&post("/insert", r#"{"table": "users", "rows": [["a@b.c", "hi", 30]]}"#), Arc::clone(&db));
+
+        let (status, body) = handle_search(&post("/search", r#"{"table": "users", "column": "email", "type": "exact", "value": "a@b.c"}"#), Arc::clone(&db));
+        assert_eq!(status, 200);
+        assert!(body.contains(r#""total":1"#));
+
+        let (status, body) = handle_search(&post("/search", r#"{"table": "users", "column": "email", "type": "prefix", "prefix": "a"}"#), Arc::clone(&db));
+        assert_eq!(status, 400);
+        assert!(body.contains("email: column has no prefix index"));
+
+        let (status, _) = handle_search(&post("/search", r#"{"table": "users", "column": "bio", "type": "fulltext", "query": "hi"}"#), Arc::clone(&db));
+        assert_eq!(status, 400);
+
+        let (status, body) = handle_describe(&post("/describe", r#"{"table": "users"}"#), Arc::clone(&db));
+        assert_eq!(status, 200);
+        assert!(body.contains(r#""indexes":["exact","range"]"#));
+
+        // indexes the column type can't hold are refused up front
+        let (status, body) = handle_create_table(&post("/table/create", r#"{
+            "name": "bad", "columns": [{"name": "n", "type": "int", "indexes": ["prefix"]}]
+        }"#), Arc::clone(&db));
+        assert_eq!(status, 400);
+        assert!(body.contains("prefix index not supported on int columns"));
+    }
+
+    #[test]
+    fn test_typed_insert() {
+        let db = Arc::new(RwLock::new(Database::new()));
+        handle_create_table(&post("/table/create", r#"{
+            "name": "users",
+            "columns": [
+                {"name": "email", "type": "string", "nullable": false},
+                {"name": "age", "type": "int"}
+            ]
+        }"#), Arc::clone(&db));
+
+        // one bad row refuses the whole batch
+        let (status, body) = handle_insert(&post("/insert", r#"{"table": "users", "rows": [["a@b.c", 30], ["d@e.f", "42"]]}"#), Arc::clone(&db));
+        assert_eq!(status, 400);
+        assert!(body.contains("row 1: column 'age' expects int, got string"));
+        let (status, body) = handle_insert(&post("/insert", r#"{"table": "users", "rows": [[null, 30]]}"#), Arc::clone(&db));
+        assert_eq!(status, 400);
+        assert!(body.contains("row 0: column 'email' is not nullable"));
+        assert!(db.read().unwrap().get_table("users").unwrap().is_empty());
+
+        let (status, body) = handle_insert(&post("/insert", r#"{"table": "users", "rows": [["a@b.c", 30], ["d@e.f", "42"]], "coerce": true}"#), Arc::clone(&db));
+        assert_eq!(status, 200);
+        assert!(body.contains(r#""count":2"#));
+        let (_, body) = handle_search(&post("/search", r#"{"table": "users", "column": "age", "type": "range", "min": 40}"#), Arc::clone(&db));
+        assert!(body.contains(r#""total":1"#));
+
+        let (status, _) = handle_update(&post("/update", r#"{"table": "users", "id": 1, "values": ["a@b.c", "31"]}"#), Arc::clone(&db));
+        assert_eq!(status, 400);
+        let (status, _) = handle_update(&post("/update", r#"{"table": "users", "id": 1, "values": ["a@b.c", "31"], "coerce": true}"#), Arc::clone(&db));
+        assert_eq!(status, 200);
+        let (status, _) = handle_update(&post("/update", r#"{"table": "users", "id": 1, "values": ["a@b.c"]}"#), Arc::clone(&db));
+        assert_eq!(status, 400);
+        let (status, _) = handle_update(&post("/update", r#"{"table": "users", "id": 99, "values": ["a@b.c", 31]}"#), Arc::clone(&db));
+        assert_eq!(status, 404);
+
+        let (_, body) = handle_describe(&post("/describe", r#"{"table": "users"}"#), Arc::clone(&db));
+        assert!(body.contains(r#""nullable":false"#));
+    }
+
+    #[test]
+    fn test_object_rows() {
+        let db = Arc::new(RwLock::new(Database::new()));
+        handle_create_table(&post("/table/create", r#"{
+            "name": "users",
+            "columns": [
+                {"name": "name", "type": "string", "nullable": false},
+                {"name": "age", "type": "int"},
+                {"name": "status", "type": "string", "default": "active"}
+            ]
+        }"#), Arc::clone(&db));
+
+        // keys in any order, left out ones get the column default or null
+        let (status, _) = handle_insert(&post("/insert", r#"{"table": "users", "rows": [
+            {"age": 30, "name": "alice"},
+            {"name": "bob", "status": "banned"},
+            ["carol", 41, "idle"]
+        ]}"#), Arc::clone(&db));
+        assert_eq!(status, 200);
+
+        let (status, body) = handle_get(&post("/get", r#"{"table": "users", "ids": [1, 2], "format": "object"}"#), Arc::clone(&db));
+        assert_eq!(status, 200);
+        assert!(body.contains(r#""values":{"name":"alice","age":30,"status":"active"}"#));
+        assert!(body.contains(r#""values":{"name":"bob","age":null,"status":"banned"}"#));
+
+        let (_, body) = handle_search(&post("/search", r#"{"table": "users", "column": "name", "type": "exact", "value": "carol", "format": "object"}"#), Arc::clone(&db));
+        assert!(body.contains(r#""values":{"name":"carol","age":41,"status":"idle"}"#));
+        let (_, body) = handle_search(&post("/search", r#"{"table": "users", "column": "name", "type": "exact", "value": "carol"}"#), Arc::clone(&db));
+        assert!(body.contains(r#""values":["carol",41,"idle"]"#));
+
+        let (status, body) = handle_insert(&post("/insert", r#"{"table": "users", "rows": [{"name": "dave", "agee": 3}]}"#), Arc::clone(&db));
+        assert_eq!(status, 400);
+        assert!(body.contains("row 0: unknown column 'agee'"));
+        let (status, body) = handle_insert(&post("/insert", r#"{"table": "users", "rows": [{"age": 3}]}"#), Arc::clone(&db));
+        assert_eq!(status, 400);
+        assert!(body.contains("column 'name' is not nullable"));
+
+        let (status, body) = handle_create_table(&post("/table/create", r#"{
+            "name": "bad", "columns": [{"name": "n", "type": "int", "default": "zero"}]
+        }"#), Arc::clone(&db));
+        assert_eq!(status, 400);
+        assert!(body.contains("default must be int"));
+    }
+
+    #[test]
+    fn test_column_types() {
+        let db = Arc::new(RwLock::new(Database::new()));
+        let (status, _) = handle_create_table(&post("/table/create", r#"{
+            "name": "events",
+            "columns": [
+                {"name": "id", "type": "uuid"},
+                {"name": "at", "type": "timestamp"},
+                {"name": "public", "type": "bool"},
+                {"name": "tags", "type": "array<string>"},
+                {"name": "scores", "type": "int[]"}
+            ],
+            "primary_key": "id"
+        }"#), Arc::clone(&db));
+        assert_eq!(status, 200);
+
+        let (status, body) = handle_insert(&post("/insert", r#"{"table": "events", "rows": [
+            ["67e55044-10b1-426f-9247-bb680e5fe0c8", "2024-05-01T12:00:00Z", true, ["launch", "web"], [1, 2]],
+            {"id": "7c9e6679-7425-40de-944b-e07fc1f90ae7", "at": 1714600000, "public": false, "tags": ["web"], "scores": [300]},
+            ["00000000-0000-0000-0000-000000000001", "2024-05-02", null, [], []]
+        ]}"#), Arc::clone(&db));
+        assert_eq!(status, 200, "{}", body);
+        let (status, body) = handle_insert(&post("/insert", r#"{"table": "events", "rows": [["not-a-uuid", null, null, null, null]]}"#), Arc::clone(&db));
+        assert_eq!(status, 400);
+        assert!(body.contains("column 'id' expects uuid, got string"));
+
+        let search = |body: &str| handle_search(&post("/search", body), Arc::clone(&db)).1;
+        assert!(search(r#"{"table": "events", "column": "tags", "type": "exact", "value": "web"}"#).contains(r#""total":2"#));
+        assert!(search(r#"{"table": "events", "column": "tags", "type": "prefix", "prefix": "lau"}"#).contains(r#""total":1"#));
+        assert!(search(r#"{"table": "events", "column": "scores", "type": "range", "min": 2}"#).contains(r#""total":2"#));
+        assert!(search(r#"{"table": "events", "column": "public", "type": "exact", "value": true}"#).contains(r#""total":1"#));
+        let body = search(r#"{"table": "events", "column": "at", "type": "range", "min": "2024-05-01T13:00:00Z", "max": "2024-05-01T23:59:59Z"}"#);
+        assert!(body.contains(r#""total":1"#));
+        assert!(body.contains(r#""2024-05-01T21:46:40Z",false,["web"],[300]"#), "{}", body);
+        assert!(search(r#"{"table": "events", "column": "at", "type": "range", "min": "soon"}"#).contains("range bounds must be timestamps"));
+
+        let (_, body) = handle_get(&post("/get", r#"{"table": "events", "keys": ["67E55044-10B1-426F-9247-BB680E5FE0C8"]}"#), Arc::clone(&db));
+        assert!(body.contains(r#""67e55044-10b1-426f-9247-bb680e5fe0c8","2024-05-01T12:00:00Z",true,["launch","web"],[1,2]"#), "{}", body);
+
+        // described under the names a schema declares them by
+        let (_, body) = handle_describe(&post("/describe", r#"{"table": "events"}"#), Arc::clone(&db));
+        assert!(body.contains(r#""name":"at","column_type":"timestamp""#), "{}", body);
+        assert!(body.contains(r#""name":"tags","column_type":"array<string>""#), "{}", body);
+    }
+
+    #[test]
+    fn test_patch() {
+        let db = Arc::new(RwLock::new(Database::new()));
+        handle_create_table(&post("/table/create", r#"{
+            "name": "users",
+            "columns": [
+                {"name": "email", "type": "string"},
+                {"name": "bio", "type": "string"},
+                {"name": "age", "type": "int"}
+            ],
+            "primary_key": "email"
+        }"#), Arc::clone(&db));
+        handle_insert(&post("/insert", r#"{"table": "users", "rows": [["a@x", "likes rust", 30], ["b@x", "likes go", 40]]}"#), Arc::clone(&db));
+
+        let (status, _) = handle_patch(&post("/patch", r#"{"table": "users", "id": 1, "set": {"age": "31"}}"#), Arc::clone(&db));
+        assert_eq!(status, 400);
+        let (status, _) = handle_patch(&post("/patch", r#"{"table": "users", "id": 1, "set": {"age": "31"}, "coerce": true}"#), Arc::clone(&db));
+        assert_eq!(status, 200);
+        let (_, body) = handle_get(&post("/get", r#"{"table": "users", "ids": [1]}"#), Arc::clone(&db));
+        assert!(body.contains(r#"["a@x","likes rust",31]"#));
+
+        let (status, _) = handle_patch(&post("/patch", r#"{"table": "users", "id": 1, "set": {"email": "b@x"}}"#), Arc::clone(&db));
+        assert_eq!(status, 409);
+        let (status, _) = handle_patch(&post("/patch", r#"{"table": "users", "id": 9, "set": {"age": 1}}"#), Arc::clone(&db));
+        assert_eq!(status, 404);
+        let (status, _) = handle_patch(&post("/patch", r#"{"table": "users", "id": 1, "set": {"nope": 1}}"#), Arc::clone(&db));
+        assert_eq!(status, 400);
+    }
+
+    #[test]
+    fn test_by_query() {
+        let db = Arc::new(RwLock::new(Database::new()));
+        handle_create_table(&post("/table/create", r#"{
+            "name": "users",
+            "columns": [
+                {"name": "tenant", "type": "int"},
+                {"name": "name", "type": "string"},
+                {"name": "status", "type": "string"}
+            ]
+        }"#), Arc::clone(&db));
+        handle_insert(&post("/insert", r#"{"table": "users", "rows": [
+            [42, "alice", "active"], [42, "bob", "active"], [7, "carol", "active"], [42, "dave", "banned"]
+        ]}"#), Arc::clone(&db));
+
+        let (status, body) = handle_update_by_query(&post("/update_by_query", r#"{
+            "table": "users", "column": "tenant", "type": "exact", "value": 42,
+            "must_not": [{"column": "status", "type": "exact", "value": "banned"}],
+            "set": {"status": "suspended"}
+        }"#), Arc::clone(&db));
+        assert_eq!((status, body.as_str()), (200, r#"{"success":true,"data":2}"#));
+        let (_, body) = handle_search(&post("/search", r#"{"table": "users", "column": "status", "type": "exact", "value": "suspended"}"#), Arc::clone(&db));
+        assert!(body.contains(r#"[42,"alice","suspended"]"#) && body.contains(r#"[42,"bob","suspended"]"#));
+
+        let (status, body) = handle_update_by_query(&post("/update_by_query", r#"{"table": "users", "column": "tenant", "type": "exact", "value": 7, "set": {"tenant": "seven"}}"#), Arc::clone(&db));
+        assert_eq!(status, 400);
+        assert!(body.contains("expects int, got string"));
+        let (status, _) = handle_update_by_query(&post("/update_by_query", r#"{"table": "users", "column": "tenant", "type": "exact", "value": 7, "set": {}}"#), Arc::clone(&db));
+        assert_eq!(status, 400);
+        let (status, _) = handle_update_by_query(&post("/update_by_query", r#"{"table": "users", "column": "tenant", "type": "exact", "value": 7, "set": {"age": 1}}"#), Arc::clone(&db));
+        assert_eq!(status, 400);
+
+        // a query is required, an empty one doesn't mean every row
+        let (status, _) = handle_delete_by_query(&post("/delete_by_query", r#"{"table": "users"}"#), Arc::clone(&db));
+        assert_eq!(status, 400);
+        let (status, body) = handle_delete_by_query(&post("/delete_by_query", r#"{"table": "users", "column": "tenant", "type": "exact", "value": 42}"#), Arc::clone(&db));
+        assert_eq!((status, body.as_str()), (200, r#"{"success":true,"data":3}"#));
+        assert_eq!(db.read().unwrap().get_table("users").unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_primary_keys() {
+        let db = Arc::new(RwLock::new(Database::new()));
+        let (status, _) = handle_create_table(&post("/table/create", r#"{
+            "name": "stock",
+            "columns": [
+                {"name": "region", "type": "string"},
+                {"name": "sku", "type": "int"},
+                {"name": "count", "type": "int"}
+            ],
+            "primary_key": ["region", "sku"]
+        }"#), Arc::clone(&db));
+        assert_eq!(status, 200);
+
+        handle_insert(&post("/insert", r#"{"table": "stock", "rows": [["eu", 7, 1], ["us", 7, 2]]}"#), Arc::clone(&db));
+        let (status, body) = handle_insert(&post("/insert", r#"{"table": "stock", "rows": [["asia", 1, 0], ["eu", 7, 3]]}"#), Arc::clone(&db));
+        assert_eq!(status, 409);
+        assert!(body.contains(r#"row 1: primary key (\"eu\", 7) already exists"#));
+        assert_eq!(db.read().unwrap().get_table("stock").unwrap().len(), 2);
+
+        let (status, body) = handle_upsert(&post("/upsert", r#"{"table": "stock", "rows": [
+            {"region": "eu", "sku": 7, "count": 12},
+            {"region": "asia", "sku": 1, "count": 5}
+        ]}"#), Arc::clone(&db));
+        assert_eq!(status, 200);
+        assert!(body.contains(r#""ids":[1,3],"inserted":1,"updated":1"#));
+
+        // a row that doesn't fit refuses the whole batch, "12" only goes in with coerce
+        let rows = r#"[{"region": "eu", "sku": 7, "count": 13}, {"region": "asia", "sku": 2, "count": "12"}]"#;
+        let (status, body) = handle_upsert(&post("/upsert", &format!(r#"{{"table": "stock", "rows": {}}}"#, rows)), Arc::clone(&db));
+        assert_eq!(status, 400);
+        assert!(body.contains("row 1"), "{}", body);
+        assert_eq!(db.read().unwrap().get_table("stock").unwrap().get(1).unwrap()[2], Value::Int(12));
+        let (status, body) = handle_upsert(&post("/upsert", &format!(r#"{{"table": "stock", "rows": {}, "coerce": true}}"#, rows)), Arc::clone(&db));
+        assert_eq!(status, 200);
+        assert!(body.contains(r#""inserted":1,"updated":1"#));
+
+        let (status, body) = handle_get(&post("/get", r#"{"table": "stock", "keys": [{"region": "eu", "sku": 7}, {"region": "eu", "sku": 8}]}"#), Arc::clone(&db));
+        assert_eq!(status, 200);
+        assert!(body.contains(r#"[{"id":1,"values":["eu",7,13]}]"#));
+        let (status, body) = handle_get(&post("/get", r#"{"table": "stock", "keys": ["eu"]}"#), Arc::clone(&db));
+        assert_eq!(status, 400);
+        assert!(body.contains("composite keys must be objects"));
+        let (status, body) = handle_get(&post("/get", r#"{"table": "stock", "keys": [{"region": "eu", "count": 1}]}"#), Arc::clone(&db));
+        assert_eq!(status, 400);
+        assert!(body.contains("count is not a primary key column"));
+
+        let (status, _) = handle_update(&post("/update", r#"{"table": "stock", "id": 2, "values": ["eu", 7, 0]}"#), Arc::clone(&db));
+        assert_eq!(status, 409);
+
+        let (_, body) = handle_delete(&post("/delete", r#"{"table": "stock", "keys": [{"region": "asia", "sku": 1}]}"#), Arc::clone(&db));
+        assert!(body.contains(r#""data":1"#));
+
+        let (_, body) = handle_describe(&post("/describe", r#"{"table": "stock"}"#), Arc::clone(&db));
+        assert!(body.contains(r#""primary_key":["region","sku"]"#));
+
+        // single column keys are addressed by the bare value
+        handle_create_table(&post("/table/create", r#"{
+            "name": "users", "columns": [{"name": "email", "type": "string"}], "primary_key": "email"
+        }"#), Arc::clone(&db));
+        handle_insert(&post("/insert", r#"{"table": "users", "rows": [["a@b.c"]]}"#), Arc::clone(&db));
+        let (_, body) = handle_get(&post("/get", r#"{"table": "users", "keys": ["a@b.c"]}"#), Arc::clone(&db));
+        assert!(body.contains(r#""values":["a@b.c"]"#));
+
+        let (status, body) = handle_upsert(&post("/upsert", r#"{"table": "users", "rows": [["x"]]}"#), Arc::clone(&db));
+        assert_eq!(status, 200, "{}", body);
+        handle_create_table(&post("/table/create", r#"{"name": "plain", "columns": [{"name": "n", "type": "int"}]}"#), Arc::clone(&db));
+        let (status, body) = handle_upsert(&post("/upsert", r#"{"table": "plain", "rows": [[1]]}"#), Arc::clone(&db));
+        assert_eq!(status, 400);
+        assert!(body.contains("table has no primary key"));
+
+        let (status, body) = handle_create_table(&post("/table/create", r#"{
+            "name": "bad", "columns": [{"name": "f", "type": "float"}], "primary_key": "f"
+        }"#), Arc::clone(&db));
+        assert_eq!(status, 400);
+        assert!(body.contains("primary key column f can't be a float"));
+    }
+
+    #[test]
+    fn test_alter_table() {
+        let db = Arc::new(RwLock::new(Database::new()));
+        handle_create_table(&post("/table/create", r#"{
+            "name": "users", "columns": [{"name": "name", "type": "string"}, {"name": "age", "type": "string"}]
+        }"#), Arc::clone(&db));
+        handle_insert(&post("/insert", r#"{"table": "users", "rows": [["alice", "30"], ["bob", "25"]]}"#), Arc::clone(&db));
+        let (status, body) = handle_create_table(&post("/table/create", r#"{"name": "users", "columns": [{"name": "n", "type": "int"}]}"#), Arc::clone(&db));
+        assert_eq!(status, 409);
+        assert!(body.contains("table already exists"));
+
+        let alter = |body: &str| handle_alter_table(&post("/table/alter", body), Arc::clone(&db));
+        assert_eq!(alter(r#"{"table": "users", "retype": {"column": "age", "type": "int"}}"#).0, 200);
+        assert_eq!(alter(r#"{"table": "users", "add": {"name": "status", "type": "string", "default": "active"}}"#).0, 200);
+        assert_eq!(alter(r#"{"table": "users", "rename": {"from": "name", "to": "handle"}}"#).0, 200);
+        assert_eq!(alter(r#"{"table": "users", "drop": "status"}"#).0, 200);
+
+        let (status, body) = alter(r#"{"table": "users", "drop": "status"}"#);
+        assert_eq!(status, 400);
+        assert!(body.contains("column not found: status"));
+        assert_eq!(alter(r#"{"table": "nope", "drop": "age"}"#).0, 404);
+        assert_eq!(alter(r#"{"table": "users", "retype": {"column": "age", "type": "decimal"}}"#).0, 400);
+
+        let (_, body) = handle_search(&post("/search", r#"{"table": "users", "column": "age", "type": "range", "min": 28, "format": "object"}"#), Arc::clone(&db));
+        assert!(body.contains(r#""values":{"handle":"alice","age":30}"#));
+        let (_, body) = handle_describe(&post("/describe", r#"{"table": "users"}"#), Arc::clone(&db));
+        assert!(body.contains(r#""name":"age","column_type":"int""#));
+    }
+
+    #[test]
+    fn test_bool_search() {
+        let db = Arc::new(RwLock::new(Database::new()));
+        handle_create_table(&post("/table/create", r#"{
+            "name": "users",
+            "columns": [
+                {"name": "name", "type": "string"},
+                {"name": "age", "type": "int"},
+                {"name": "status", "type": "string"}
+            ]
+        }"#), Arc::clone(&db));
+        handle_insert(&post("/insert", r#"{"table": "users", "rows": [
+            ["alice", 30, "active"], ["alan", 45, "active"], ["albert", 25, "banned"], ["bob", 35, "active"]
+        ]}"#), Arc::clone(&db));
+
+        let (status, body) = handle_search(&post("/search", r#"{
+            "table": "users",
+            "must": [
+                {"column": "name", "type": "prefix", "prefix": "al"},
+                {"column": "age", "type": "range", "min": 20, "max": 40}
+            ],
+            "must_not": [{"column": "status", "type": "exact", "value": "banned"}]
+        }"#), Arc::clone(&db));
+        assert_eq!(status, 200);
+        assert!(body.contains(r#""total":1"#));
+        assert!(body.contains("alice"));
+
+        // nested: (bob or over 40) and active
+        let (status, body) = handle_search(&post("/search", r#"{
+            "table": "users",
+            "column": "status", "type": "exact", "value": "active",
+            "must": [{"should": [
+                {"column": "name", "type": "exact", "value": "bob"},
+                {"column": "age", "type": "range", "min": 41}
+            ]}]
+        }"#), Arc::clone(&db));
+        assert_eq!(status, 200);
+        assert!(body.contains(r#""total":2"#));
+
+        let (status, body) = handle_search(&post("/search", r#"{"table": "users", "must": [{"column": "nope", "type": "exact", "value": 1}]}"#), Arc::clone(&db));
+        assert_eq!(status, 400);
+        assert!(body.contains("column not found: nope"));
+
+        let (status, _) = handle_search(&post("/search", r#"{"table": "users"}"#), Arc::clone(&db));
+        assert_eq!(status, 400);
+
+        // ordered pages line up with the full ordering
+        let page = |offset: usize| {
+            let (status, body) = handle_search(&post("/search", &format!(r#"{{
+                "table": "users", "column": "status", "type": "exact", "value": "active",
+                "order_by": [{{"column": "age", "direction": "desc"}}], "offset": {}, "limit": 2
+            }}"#, offset)), Arc::clone(&db));
+            assert_eq!(status, 200);
+            body
+        };
+        assert!(page(0).contains(r#""rows":[{"id":2,"values":["alan",45,"active"]},{"id":4,"#));
+        assert!(page(2).contains(r#""rows":[{"id":1,"values":["alice",30,"active"]}]"#));
+
+        let (status, body) = handle_search(&post("/search", r#"{"table": "users", "column": "age", "type": "range", "order_by": [{"column": "height"}]}"#), Arc::clone(&db));
+        assert_eq!(status, 400);
+        assert!(body.contains("order_by column not found: height"));
+    }
+
+    #[test]
+    fn test_substring_search() {
+        let db = Arc::new(RwLock::new(Database::new()));
+        handle_create_table(&post("/table/create", r#"{
+            "name": "users",
+            "columns": [{"name": "email", "type": "string"}, {"name": "bio", "type": "string", "indexes": ["fulltext"]}]
+        }"#), Arc::clone(&db));
+        handle_insert(&post("/insert", r#"{"table": "users", "rows": [
+            ["alice@example.com", "x"], ["malice@evil.org", "y"], ["bob@example.com", "z"]
+        ]}"#), Arc::clone(&db));
+
+        let (status, body) = handle_search(&post("/search", r#"{"table": "users", "column": "email", "type": "contains", "query": "lice"}"#), Arc::clone(&db));
+        assert_eq!(status, 200);
+        assert!(body.contains(r#""total":2"#));
+
+        let (status, body) = handle_search(&post("/search", r#"{"table": "users", "column": "email", "type": "suffix", "suffix": "example.com"}"#), Arc::clone(&db));
+        assert_eq!(status, 200);
+        assert!(body.contains(r#""total":2"#));
+        assert!(!body.contains("malice"));
+
+        let (status, body) = handle_search(&post("/search", r#"{"table": "users", "column": "bio", "type": "contains", "query": "x"}"#), Arc::clone(&db));
+        assert_eq!(status, 400);
+        assert!(body.contains("bio: column has no substring index"));
+    }
+
+    #[test]
+    fn test_fuzzy_search() {
+        let db = Arc::new(RwLock::new(Database::new()));
+        handle_create_table(&post("/table/create", r#"{"name": "users", "columns": [{"name": "name", "type": "string"}]}"#), Arc::clone(&db));
+        handle_insert(&post("/insert", r#"{"table": "users", "rows": [
+            ["Jonathon Smyth"], ["Jonathan Smith"], ["Joan Smit"]
+        ]}"#), Arc::clone(&db));
+
+        // closest first, with the distance
+        let (status, body) = handle_search(&post("/search", r#"{"table": "users", "column": "name", "type": "fuzzy", "query": "jonathan smith", "fuzziness": 2}"#), Arc::clone(&db));
+        assert_eq!(status, 200);
+        assert!(body.contains(r#""rows":[{"id":2,"values":["Jonathan Smith"],"distance":0},{"id":1,"values":["Jonathon Smyth"],"distance":2}]"#));
+
+        // whole values, default of one edit
+        let (status, body) = handle_search(&post("/search", r#"{"table": "users", "column": "name", "type": "fuzzy", "value": "Joan Smith"}"#), Arc::clone(&db));
+        assert_eq!(status, 200);
+        assert!(body.contains(r#""total":1"#));
+        assert!(body.contains("Joan Smit"));
+
+        let (status, body) = handle_search(&post("/search", r#"{"table": "users", "column": "name", "type": "fuzzy", "value": "x", "fuzziness": 3}"#), Arc::clone(&db));
+        assert_eq!(status, 400);
+        assert!(body.contains("max edit distance is 2, got 3"));
+    }
+
+    #[test]
+    fn test_aggregations() {
+        let db = Arc::new(RwLock::new(Database::new()));
+        handle_create_table(&post("/table/create", r#"{
+            "name": "orders",
+            "columns": [{"name": "status", "type": "string"}, {"name": "price", "type": "float"}, {"name": "qty", "type": "int"}]
+        }"#), Arc::clone(&db));
+        handle_insert(&post("/insert", r#"{"table": "orders", "rows": [
+            ["paid", 10.0, 1], ["paid", 30.0, 3], ["refunded", 5.0, 1], ["paid", 20.0, 2], ["open", null, 4]
+        ]}"#), Arc::clone(&db));
+
+        // aggregations see every match even with limit 0
+        let (status, body) = handle_search(&post("/search", r#"{
+            "table": "orders", "column": "qty", "type": "range", "min": 1, "max": 3, "limit": 0,
+            "aggregations": {
+                "orders": {"type": "count"},
+                "revenue": {"type": "sum", "column": "price"},
+                "biggest": {"type": "max", "column": "qty"},
+                "statuses": {"type": "terms", "column": "status", "size": 1, "aggregations": {"avg_price": {"type": "avg", "column": "price"}}}
+            }
+        }"#), Arc::clone(&db));
+        assert_eq!(status, 200);
+        assert!(body.contains(r#""rows":[],"total":4"#));
+        assert!(body.contains(r#""biggest":{"value":3}"#));
+        assert!(body.contains(r#""orders":{"value":4}"#));
+        assert!(body.contains(r#""revenue":{"value":65.0}"#));
+        assert!(body.contains(r#""statuses":{"buckets":[{"key":"paid","count":3,"aggregations":{"avg_price":{"value":20.0}}}],"other_count":1}"#));
+
+        // no query means every row
+        let (status, body) = handle_search(&post("/search", r#"{
+            "table": "orders", "aggregations": {"priced": {"type": "count", "column": "price"}, "cheapest": {"type": "min", "column": "price"}}
+        }"#), Arc::clone(&db));
+        assert_eq!(status, 200);
+        assert!(body.contains(r#""total":5"#));
+        assert!(body.contains(r#""cheapest":{"value":5.0},"priced":{"value":4}"#));
+
+        let (status, body) = handle_search(&post("/search", r#"{
+            "table": "orders", "aggregations": {"total": {"type": "sum", "column": "status"}}
+        }"#), Arc::clone(&db));
+        assert_eq!(status, 400);
+        assert!(body.contains("total: sum needs an int or float column"));
+
+        let (status, body) = handle_search(&post("/search", r#"{
+            "table": "orders", "aggregations": {"x": {"type": "median", "column": "qty"}}
+        }"#), Arc::clone(&db));
+        assert_eq!(status, 400);
+        assert!(body.contains("x: unknown aggregation 'median'"));
+    }
+
+    #[test]
+    fn test_facets() {
+        let db = Arc::new(RwLock::new(Database::new()));
+        handle_create_table(&post("/table/create", r#"{
+            "name": "products",
+            "columns": [{"name": "name", "type": "string"}, {"name": "brand", "type": "string"}, {"name": "color", "type": "string"}]
+        }"#), Arc::clone(&db));
+        handle_insert(&post("/insert", r#"{"table": "products", "rows": [
+            ["red shoe", "acme", "red"], ["blue shoe", "acme", "blue"], ["red hat", "globex", "red"], ["red shoe lace", "globex", "red"]
+        ]}"#), Arc::clone(&db));
+
+        let (status, body) = handle_search(&post("/search", r#"{
+            "table": "products", "column": "name", "type": "fulltext", "query": "shoe",
+            "facets": ["brand", {"column": "color", "size": 1}]
+        }"#), Arc::clone(&db));
+        assert_eq!(status, 200);
+        assert!(body.contains(r#""facets":{"brand":[{"value":"acme","count":2},{"value":"globex","count":1}],"color":[{"value":"red","count":2}]}"#));
+
+        let (status, body) = handle_search(&post("/search", r#"{"table": "products", "facets": ["nope"]}"#), Arc::clone(&db));
+        assert_eq!(status, 400);
+        assert!(body.contains("facet column not found: nope"));
+    }
+
+    #[test]
+    fn test_typed_ranges() {
+        let db = Arc::new(RwLock::new(Database::new()));
+        handle_create_table(&post("/table/create", r#"{
+            "name": "orders",
+            "columns": [{"name": "price", "type": "float"}, {"name": "placed", "type": "string", "indexes": ["exact", "range"]}]
+        }"#), Arc::clone(&db));
+        handle_insert(&post("/insert", r#"{"table": "orders", "rows": [
+            [19.99, "2024-01-15"], [20, "2024-02-01"], [5.5, "2023-12-31"]
+        ]}"#), Arc::clone(&db));
+
+        let (status, body) = handle_search(&post("/search", r#"{
+            "table": "orders", "column": "price", "type": "range", "max": 20, "max_exclusive": true
+        }"#), Arc::clone(&db));
+        assert_eq!(status, 200);
+        assert!(body.contains(r#""total":2"#));
+        assert!(!body.contains("2024-02-01"));
+
+        let (status, body) = handle_search(&post("/search", r#"{
+            "table": "orders", "column": "placed", "type": "range", "min": "2024-01-01", "max": "2024-02-01"
+        }"#), Arc::clone(&db));
+        assert_eq!(status, 200);
+        assert!(body.contains(r#""total":2"#));
+        assert!(!body.contains("2023-12-31"));
+
+        let (status, body) = handle_search(&post("/search", r#"{
+            "table": "orders", "column": "placed", "type": "range", "min": 2024
+        }"#), Arc::clone(&db));
+        assert_eq!(status, 400);
+        assert!(body.contains("placed: range bounds must be strings"));
+    }
+
+    #[test]
+    fn test_suggest() {
+        let db = Arc::new(RwLock::new(Database::new()));
+        handle_create_table(&post("/table/create", r#"{
+            "name": "searches",
+            "columns": [{"name": "term", "type": "string"}, {"name": "clicks", "type": "int"}, {"name": "label", "type": "string"}]
+        }"#), Arc::clone(&db));
+        handle_insert(&post("/insert", r#"{"table": "searches", "rows": [
+            ["rust book", 1, "a"], ["rust book", 1, "a"], ["rust lang", 9, "a"], ["rusty nail", 2, "a"], ["ruby", 50, "a"]
+        ]}"#), Arc::clone(&db));
+
+        let (status, body) = handle_suggest(&post("/suggest", r#"{"table": "searches", "column": "term", "prefix": "rust", "limit": 2}"#), Arc::clone(&db));
+        assert_eq!(status, 200);
+        assert!(body.contains(r#""suggestions":[{"value":"rust book","count":2},{"value":"rust lang","count":1}]"#));
+
+        let (status, body) = handle_suggest(&post("/suggest", r#"{"table": "searches", "column": "term", "prefix": "ru", "weight": "clicks"}"#), Arc::clone(&db));
+        assert_eq!(status, 200);
+        assert!(body.contains(r#"[{"value":"ruby","count":1,"weight":50.0},{"value":"rust lang","count":1,"weight":9.0},{"value":"rust book","count":2,"weight":2.0}"#));
+
+        let (status, body) = handle_suggest(&post("/suggest", r#"{"table": "searches", "column": "term", "prefix": "x"}"#), Arc::clone(&db));
+        assert_eq!(status, 200);
+        assert!(body.contains(r#""suggestions":[]"#));
+
+        let (status, body) = handle_suggest(&post("/suggest", r#"{"table": "searches", "column": "term", "weight": "label"}"#), Arc::clone(&db));
+        assert_eq!(status, 400);
+        assert!(body.contains("weight column must be int or float: label"));
+
+        let (status, body) = handle_suggest(&post("/suggest", r#"{"table": "searches", "column": "clicks", "prefix": "1"}"#), Arc::clone(&db));
+        assert_eq!(status, 400);
+        assert!(body.contains("clicks: column has no prefix index"));
+
+        let (status, body) = handle_suggest(&post("/suggest", r#"{"table": "searches", "column": "term", "weight": "clicks"}"#), Arc::clone(&db));
+        assert_eq!(status, 400);
+        assert!(body.contains("term: a weighted suggest needs a prefix"));
+    }
+
+    #[test]
+    fn test_column_analyzers() {
+        let db = Arc::new(RwLock::new(Database::new()));
+        let (status, _) = handle_create_table(&post("/table/create", r#"{
+            "name": "posts",
+            "columns": [
+                {"name": "title", "type": "string"},
+                {"name": "body", "type": "string", "analyzer": "english"},
+                {"name": "tag", "type": "string", "analyzer": "raw"}
+            ]
+        }"#), Arc::clone(&db));
+        assert_eq!(status, 200);
+        handle_insert(&post("/insert", r#"{"table": "posts", "rows": [
+            ["Café Opening", "The runners were Running to the café", "Rust"]
+        ]}"#), Arc::clone(&db));
+
+        let total = |column: &str, query: &str| {
+            let (status, body) = handle_search(&post("/search", &format!(
+                r#"{{"table": "posts", "column": "{}", "type": "fulltext", "query": "{}"}}"#, column, query
+            )), Arc::clone(&db));
+            assert_eq!(status, 200);
+            body.contains(r#""total":1"#)
+        };
+
+        // standard: case and accents don't matter, word forms do
+        assert!(total("title", "CAFE opening"));
+        assert!(!total("title", "open"));
+        // english: stemmed and stop words ignored, also in phrases
+        assert!(total("body", "run"));
+        assert!(total("body", "\\\"Running to the cafe\\\""));
+        // raw: exactly as written
+        assert!(total("tag", "Rust"));
+        assert!(!total("tag", "rust"));
+
+        let (_, body) = handle_describe(&post("/describe", r#"{"table": "posts"}"#), Arc::clone(&db));
+        assert!(body.contains(r#""analyzer":"english""#));
+
+        let (status, body) = handle_create_table(&post("/table/create", r#"{
+            "name": "bad", "columns": [{"name": "s", "type": "string", "analyzer": "klingon"}]
+        }"#), Arc::clone(&db));
+        assert_eq!(status, 400);
+        assert!(body.contains("unknown analyzer 'klingon'"));
+    }
+
+    #[test]
+    fn test_ranked_search() {
+        let db = Arc::new(RwLock::new(Database::new()));
+        handle_create_table(&post("/table/create", r#"{"name": "docs", "columns": [{"name": "body", "type": "string"}]}"#), Arc::clone(&db));
+        handle_insert(&post("/insert", r#"{"table": "docs", "rows": [
+            ["a long note that mentions rust once among many other words"], ["rust rust"], ["python"], ["rust and python"]
+        ]}"#), Arc::clone(&db));
+
+        // strict and: only the row with both terms
+        let (status, body) = handle_search(&post("/search", r#"{"table": "docs", "column": "body", "type": "fulltext", "query": "rust python"}"#), Arc::clone(&db));
+        assert_eq!(status, 200);
+        assert!(body.contains(r#""total":1"#));
+        assert!(body.contains(r#""score":"#));
+
+        // or: everything with either term, best match first
+        let (status, body) = handle_search(&post("/search", r#"{
+            "table": "docs", "column": "body", "type": "fulltext", "query": "rust python", "operator": "or", "limit": 2
+        }"#), Arc::clone(&db));
+        assert_eq!(status, 200);
+        assert!(body.contains(r#""total":4"#));
+        assert!(body.contains(r#""rows":[{"id":4,"#));
+
+        let (status, body) = handle_search(&post("/search", r#"{"table": "docs", "column": "body", "type": "fulltext", "query": "rust", "operator": "xor"}"#), Arc::clone(&db));
+        assert_eq!(status, 400);
+        assert!(body.contains("invalid operator"));
+
+        // quoted phrases keep word order
+        let (_, body) = handle_search(&post("/search", r#"{"table": "docs", "column": "body", "type": "fulltext", "query": "\"python and\" rust"}"#), Arc::clone(&db));
+        assert!(body.contains(r#""total":0"#));
+        let (_, body) = handle_search(&post("/search", r#"{"table": "docs", "column": "body", "type": "fulltext", "query": "\"and python\" rust"}"#), Arc::clone(&db));
+        assert!(body.contains(r#""total":1"#));
+
+        // explicit ordering wins over relevance, scores are still reported
+        let (_, body) = handle_search(&post("/search", r#"{
+            "table": "docs", "column": "body", "type": "fulltext", "query": "rust", "order_by": [{"column": "body"}]
+        }"#), Arc::clone(&db));
+        assert!(body.contains(r#""rows":[{"id":1,"#));
+
+        // no full-text clause, no score
+        let (_, body) = handle_search(&post("/search", r#"{"table": "docs", "column": "body", "type": "exact", "value": "python"}"#), Arc::clone(&db));
+        assert!(!body.contains("score"));
+    }
+
     #[test]
     fn test_check_auth_none_level() {
         let auth = AuthManager::new(false);
@@ -1,67 +1,299 @@
+use crate::analyzer::Analyzer;
+use crate::fuzzy::LevenshteinAutomaton;
 use crate::storage::{RowId, Value};
-use std::collections::HashMap;
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeSet, HashMap};
+use std::hash::{BuildHasher, BuildHasherDefault, Hash, Hasher};
+use std::ops::Bound;
+use std::sync::Arc;
 
-// exact match index using hash table for o(1) lookup
-pub struct HashIndex {
-    map: HashMap<u64, Vec<RowId>>,
+// the kinds of index a column can ask for, named after the searches they serve
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IndexKind {
+    Exact,      // hash index (+ bloom filter for strings)
+    Prefix,     // trie
+    FullText,   // inverted index
+    Range,      // sorted index
+    Substring,  // n-gram index, serves `contains` and `suffix`
+}
+
+impl IndexKind {
+    pub const ALL: [IndexKind; 5] = [Self::Exact, Self::Prefix, Self::FullText, Self::Range, Self::Substring];
+
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(s: &str) -> Option<Self> {
+        match s.to_lowercase().as_str() {
+            "exact" | "hash" => Some(Self::Exact),
+            "prefix" | "trie" => Some(Self::Prefix),
+            "fulltext" | "text" | "inverted" => Some(Self::FullText),
+            "range" | "sorted" => Some(Self::Range),
+            "substring" | "ngram" | "contains" => Some(Self::Substring),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Exact => "exact",
+            Self::Prefix => "prefix",
+            Self::FullText => "fulltext",
+            Self::Range => "range",
+            Self::Substring => "substring",
+        }
+    }
+
+    fn bit(self) -> u8 {
+        1 << self as u8
+    }
+}
+
+impl std::fmt::Display for IndexKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+// which indexes a column keeps, as a small bit set
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct IndexSet(u8);
+
+impl IndexSet {
+    pub const NONE: IndexSet = IndexSet(0);
+
+    pub fn all() -> Self {
+        IndexKind::ALL.iter().fold(Self::NONE, |set, kind| set.with(*kind))
+    }
+
+    pub fn with(self, kind: IndexKind) -> Self {
+        Self(self.0 | kind.bit())
+    }
+
+    pub fn without(self, kind: IndexKind) -> Self {
+        Self(self.0 & !kind.bit())
+    }
+
+    pub fn contains(self, kind: IndexKind) -> bool {
+        self.0 & kind.bit() != 0
+    }
+
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn iter(self) -> impl Iterator<Item = IndexKind> {
+        IndexKind::ALL.into_iter().filter(move |kind| self.contains(*kind))
+    }
+
+    // raw bits, for the on-disk format
+    pub fn bits(self) -> u8 {
+        self.0
+    }
+
+    pub fn from_bits(bits: u8) -> Option<Self> {
+        if bits & !Self::all().0 != 0 {
+            return None;
+        }
+        Some(Self(bits))
+    }
+}
+
+impl FromIterator<IndexKind> for IndexSet {
+    fn from_iter<I: IntoIterator<Item = IndexKind>>(iter: I) -> Self {
+        iter.into_iter().fold(Self::NONE, |set, kind| set.with(kind))
+    }
+}
+
+type DefaultBuildHasher = BuildHasherDefault<DefaultHasher>;
+
+// exact match index using hash table for o(1) lookup. entries keep their key
+// so values whose hashes collide never see each other's rows
+pub struct HashIndex<S = DefaultBuildHasher> {
+    map: HashMap<u64, HashEntry>,
+    hasher: S,
+    keys: usize,
+}
+
+// the first value for a hash lives inline, collisions spill into `overflow`
+// which stays unallocated in practice
+struct HashEntry {
+    key: Value,
+    ids: Vec<RowId>,
+    overflow: Vec<(Value, Vec<RowId>)>,
+}
+
+// floats compare by bit pattern, matching how they hash
+#[inline(always)]
+fn same_key(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (Value::Null, Value::Null) => true,
+        (Value::Int(a), Value::Int(b)) => a == b,
+        (Value::Float(a), Value::Float(b)) => a.to_bits() == b.to_bits(),
+        (Value::String(a), Value::String(b)) => a == b,
+        (Value::Bytes(a), Value::Bytes(b)) => a == b,
+        (Value::Bool(a), Value::Bool(b)) => a == b,
+        (Value::Timestamp(a), Value::Timestamp(b)) => a == b,
+        (Value::Uuid(a), Value::Uuid(b)) => a == b,
+        (Value::Array(a), Value::Array(b)) => a.len() == b.len() && a.iter().zip(b.iter()).all(|(a, b)| same_key(a, b)),
+        _ => false,
+    }
 }
 
 impl HashIndex {
     pub fn new() -> Self {
-        Self {
-            map: HashMap::with_capacity(1_000_000),
-        }
+        Self::with_capacity_and_hasher(1_000_000, DefaultBuildHasher::default())
     }
 
     pub fn with_capacity(capacity: usize) -> Self {
+        Self::with_capacity_and_hasher(capacity, DefaultBuildHasher::default())
+    }
+}
+
+impl<S: BuildHasher> HashIndex<S> {
+    pub fn with_hasher(hasher: S) -> Self {
+        Self::with_capacity_and_hasher(0, hasher)
+    }
+
+    pub fn with_capacity_and_hasher(capacity: usize, hasher: S) -> Self {
         Self {
             map: HashMap::with_capacity(capacity),
+            hasher,
+            keys: 0,
         }
     }
 
     #[inline(always)]
-    fn hash_value(value: &Value) -> u64 {
-        use std::hash::{Hash, Hasher};
-        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    fn hash_value(&self, value: &Value) -> u64 {
+        let mut hasher = self.hasher.build_hasher();
+        // tag each variant so e.g. Null and Int(0) never feed the same bytes
         match value {
             Value::Null => 0u8.hash(&mut hasher),
-            Value::Int(i) => i.hash(&mut hasher),
-            Value::Float(f) => f.to_bits().hash(&mut hasher),
-            Value::String(s) => s.hash(&mut hasher),
-            Value::Bytes(b) => b.hash(&mut hasher),
+            Value::Int(i) => {
+                1u8.hash(&mut hasher);
+                i.hash(&mut hasher);
+            }
+            Value::Float(f) => {
+                2u8.hash(&mut hasher);
+                f.to_bits().hash(&mut hasher);
+            }
+            Value::String(s) => {
+                3u8.hash(&mut hasher);
+                s.hash(&mut hasher);
+            }
+            Value::Bytes(b) => {
+                4u8.hash(&mut hasher);
+                b.hash(&mut hasher);
+            }
+            Value::Bool(b) => {
+                5u8.hash(&mut hasher);
+                b.hash(&mut hasher);
+            }
+            Value::Timestamp(t) => {
+                6u8.hash(&mut hasher);
+                t.hash(&mut hasher);
+            }
+            Value::Uuid(u) => {
+                7u8.hash(&mut hasher);
+                u.hash(&mut hasher);
+            }
+            Value::Array(items) => {
+                8u8.hash(&mut hasher);
+                items.len().hash(&mut hasher);
+                for item in items.iter() {
+                    self.hash_value(item).hash(&mut hasher);
+                }
+            }
         }
         hasher.finish()
     }
 
     #[inline(always)]
     pub fn insert(&mut self, value: &Value, row_id: RowId) {
-        let hash = Self::hash_value(value);
-        self.map.entry(hash).or_insert_with(Vec::new).push(row_id);
+        let hash = self.hash_value(value);
+        match self.map.get_mut(&hash) {
+            None => {
+                self.map.insert(hash, HashEntry { key: value.clone(), ids: vec![row_id], overflow: Vec::new() });
+                self.keys += 1;
+            }
+            Some(entry) if same_key(&entry.key, value) => entry.ids.push(row_id),
+            Some(entry) => match entry.overflow.iter_mut().find(|(key, _)| same_key(key, value)) {
+                Some((_, ids)) => ids.push(row_id),
+                None => {
+                    entry.overflow.push((value.clone(), vec![row_id]));
+                    self.keys += 1;
+                }
+            },
+        }
     }
 
     #[inline(always)]
     pub fn search(&self, value: &Value) -> &[RowId] {
-        let hash = Self::hash_value(value);
-        self.map.get(&hash).map(|v| v.as_slice()).unwrap_or(&[])
+        let hash = self.hash_value(value);
+        let entry = match self.map.get(&hash) {
+            Some(entry) => entry,
+            None => return &[],
+        };
+
+        if same_key(&entry.key, value) {
+            return &entry.ids;
+        }
+        entry.overflow
+            .iter()
+            .find(|(key, _)| same_key(key, value))
+            .map(|(_, ids)| ids.as_slice())
+            .unwrap_or(&[])
     }
 
     #[inline(always)]
     pub fn remove(&mut self, value: &Value, row_id: RowId) {
-        let hash = Self::hash_value(value);
-        if let Some(ids) = self.map.get_mut(&hash) {
+        let hash = self.hash_value(value);
+        let entry = match self.map.get_mut(&hash) {
+            Some(entry) => entry,
+            None => return,
+        };
+
+        if same_key(&entry.key, value) {
+            entry.ids.retain(|&id| id != row_id);
+            if !entry.ids.is_empty() {
+                return;
+            }
+            self.keys -= 1;
+            // promote a collided key into the inline slot, or drop the entry
+            match entry.overflow.pop() {
+                Some((key, ids)) => {
+                    entry.key = key;
+                    entry.ids = ids;
+                }
+                None => {
+                    self.map.remove(&hash);
+                }
+            }
+        } else if let Some(pos) = entry.overflow.iter().position(|(key, _)| same_key(key, value)) {
+            let ids = &mut entry.overflow[pos].1;
             ids.retain(|&id| id != row_id);
             if ids.is_empty() {
-                self.map.remove(&hash);
+                entry.overflow.swap_remove(pos);
+                self.keys -= 1;
             }
         }
     }
 
+    // every distinct value with the rows holding it, in no particular order
+    pub fn entries(&self) -> impl Iterator<Item = (&Value, &[RowId])> {
+        self.map.values().flat_map(|entry| {
+            std::iter::once((&entry.key, entry.ids.as_slice()))
+                .chain(entry.overflow.iter().map(|(key, ids)| (key, ids.as_slice())))
+        })
+    }
+
+    // number of distinct values indexed
     pub fn len(&self) -> usize {
-        self.map.len()
+        self.keys
     }
 
     pub fn is_empty(&self) -> bool {
-        self.map.is_empty()
+        self.keys == 0
     }
 }
 
@@ -70,75 +302,360 @@ impl Default for HashIndex {
         Self::new()
     }
 }
+// one row's entry in a term's posting list
+#[derive(Clone, Debug, PartialEq)]
+pub struct Posting {
+    pub row_id: RowId,
+    pub positions: Vec<u32>,    // token offsets of the term in the row, ascending
+}
+
+impl Posting {
+    // times the term occurs in the row
+    #[inline(always)]
+    pub fn tf(&self) -> u32 {
+        self.positions.len() as u32
+    }
+}
+
+// a parsed full-text query. bare words and "quoted phrases" must all match,
+// `a NEAR/n b` needs at most n tokens between the two sides, in either order
+#[derive(Clone, Debug, PartialEq)]
+pub struct TextQuery {
+    pub clauses: Vec<TextClause>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum TextClause {
+    // consecutive tokens, a single word is a phrase of one
+    Phrase(Vec<Box<str>>),
+    Near { left: Vec<Box<str>>, right: Vec<Box<str>>, distance: u32 },
+}
+
+// NEAR without a distance, same default as sqlite fts5
+const DEFAULT_NEAR: u32 = 10;
+
+impl TextQuery {
+    // the words in each clause go through `analyzer`, operators and quotes don't
+    pub fn parse(text: &str, analyzer: &Analyzer) -> Self {
+        enum Item {
+            Words(Vec<Box<str>>),
+            Near(u32, Box<str>),   // keeps the raw text in case it has no operands
+        }
+
+        let words = |s: &str| -> Vec<Box<str>> { analyzer.analyze(s).into_iter().map(Into::into).collect() };
+
+        let mut items = Vec::new();
+        let mut rest = text;
+        while let Some(start) = rest.find(|c: char| !c.is_whitespace()) {
+            rest = &rest[start..];
+            if let Some(quoted) = rest.strip_prefix('"') {
+                let end = quoted.find('"').unwrap_or(quoted.len());
+                items.push(Item::Words(words(&quoted[..end])));
+                rest = quoted.get(end + 1..).unwrap_or("");
+                continue;
+            }
+
+            let end = rest.find(|c: char| c.is_whitespace() || c == '"').unwrap_or(rest.len());
+            let word = &rest[..end];
+            rest = &rest[end..];
+            let near = match word.strip_prefix("NEAR") {
+                Some("") => Some(DEFAULT_NEAR),
+                Some(n) => n.strip_prefix('/').and_then(|n| n.parse().ok()),
+                None => None,
+            };
+            items.push(match near {
+                Some(n) => Item::Near(n, word.into()),
+                None => Item::Words(words(word)),
+            });
+        }
+        items.retain(|item| !matches!(item, Item::Words(w) if w.is_empty()));
+
+        // an operand next to a NEAR belongs to it, everything else stands alone
+        let operand = |i: Option<usize>| match i.and_then(|i| items.get(i)) {
+            Some(Item::Words(w)) => Some(w.clone()),
+            _ => None,
+        };
+        let mut bound = vec![false; items.len()];
+        let mut clauses = Vec::new();
+        for (i, item) in items.iter().enumerate() {
+            if let Item::Near(distance, _) = item {
+                if let (Some(left), Some(right)) = (operand(i.checked_sub(1)), operand(Some(i + 1))) {
+                    clauses.push(TextClause::Near { left, right, distance: *distance });
+                    bound[i - 1] = true;
+                    bound[i] = true;
+                    bound[i + 1] = true;
+                }
+            }
+        }
+        for (i, item) in items.into_iter().enumerate() {
+            if bound[i] {
+                continue;
+            }
+            match item {
+                Item::Words(w) => clauses.push(TextClause::Phrase(w)),
+                // a NEAR with nothing on one side is just text
+                Item::Near(_, raw) => {
+                    let raw = words(&raw);
+                    if !raw.is_empty() {
+                        clauses.push(TextClause::Phrase(raw));
+                    }
+                }
+            }
+        }
+
+        Self { clauses }
+    }
+
+    // every token in the query, for scoring
+    pub fn terms(&self) -> impl Iterator<Item = &str> {
+        self.clauses.iter().flat_map(|c| {
+            let (a, b): (&[Box<str>], &[Box<str>]) = match c {
+                TextClause::Phrase(words) => (words, &[]),
+                TextClause::Near { left, right, .. } => (left, right),
+            };
+            a.iter().chain(b)
+        }).map(|w| &**w)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.clauses.is_empty()
+    }
+}
+
+// positions left empty between the texts of one row
+const TEXT_GAP: u32 = 100;
 
-// inverted index for full-text search
+// inverted index for full-text search. keeps term frequencies and document
+// lengths alongside the postings so matches can be ranked. each row has at
+// most one posting per term
 pub struct InvertedIndex {
-    terms: HashMap<Box<str>, Vec<RowId>>,
+    analyzer: Analyzer,
+    terms: HashMap<Box<str>, Vec<Posting>>,
+    doc_lens: HashMap<RowId, u32>,
+    total_len: u64,
 }
 
 impl InvertedIndex {
     pub fn new() -> Self {
-        Self {
-            terms: HashMap::with_capacity(100_000),
-        }
+        Self::with_capacity(100_000)
     }
 
     pub fn with_capacity(capacity: usize) -> Self {
         Self {
+            analyzer: Analyzer::default(),
             terms: HashMap::with_capacity(capacity),
+            doc_lens: HashMap::new(),
+            total_len: 0,
         }
     }
 
+    // set before indexing anything, existing terms aren't re-analyzed
+    pub fn with_analyzer(mut self, analyzer: Analyzer) -> Self {
+        self.analyzer = analyzer;
+        self
+    }
+
+    pub fn analyzer(&self) -> &Analyzer {
+        &self.analyzer
+    }
+
+    // parse query text with the same analyzer the index uses
+    pub fn parse_query(&self, text: &str) -> TextQuery {
+        TextQuery::parse(text, &self.analyzer)
+    }
+
     #[inline(always)]
     pub fn index_text(&mut self, text: &str, row_id: RowId) {
-        for token in Self::tokenize(text) {
-            self.terms
-                .entry(token.into())
-                .or_insert_with(Vec::new)
-                .push(row_id);
+        self.index_texts([text], row_id);
+    }
+
+    // several texts as one row, e.g. an array's strings. each starts
+    // `TEXT_GAP` positions past the last, so phrases don't match across two
+    pub fn index_texts<'a>(&mut self, texts: impl IntoIterator<Item = &'a str>, row_id: RowId) {
+        let mut positions: HashMap<Cow<'_, str>, Vec<u32>> = HashMap::new();
+        let mut len = 0;
+        let mut position = 0;
+        for (i, text) in texts.into_iter().enumerate() {
+            if i > 0 {
+                position += TEXT_GAP;
+            }
+            for token in self.analyzer.analyze(text) {
+                positions.entry(token).or_default().push(position);
+                position += 1;
+                len += 1;
+            }
         }
+
+        for (token, positions) in positions {
+            self.terms.entry(token.into()).or_default().push(Posting { row_id, positions });
+        }
+        *self.doc_lens.entry(row_id).or_default() += len;
+        self.total_len += len as u64;
     }
 
     #[inline(always)]
-    fn tokenize(text: &str) -> impl Iterator<Item = &str> {
-        text.split(|c: char| !c.is_alphanumeric())
-            .filter(|s| !s.is_empty())
+    pub fn postings(&self, term: &str) -> &[Posting] {
+        self.terms.get(term).map(|v| v.as_slice()).unwrap_or(&[])
     }
 
+    // rows containing the term
     #[inline(always)]
-    pub fn search_term(&self, term: &str) -> &[RowId] {
-        self.terms.get(term).map(|v| v.as_slice()).unwrap_or(&[])
+    pub fn search_term(&self, term: &str) -> Vec<RowId> {
+        self.postings(term).iter().map(|p| p.row_id).collect()
     }
 
+    // rows containing every term
     pub fn search_terms(&self, terms: &[&str]) -> Vec<RowId> {
-        if terms.is_empty() {
-            return Vec::new();
+        let mut lists: Vec<&[Posting]> = terms.iter().map(|t| self.postings(t)).collect();
+        lists.sort_by_key(|l| l.len());
+
+        let (first, rest) = match lists.split_first() {
+            Some(split) => split,
+            None => return Vec::new(),
+        };
+
+        let mut result: Vec<RowId> = first.iter().map(|p| p.row_id).collect();
+        for list in rest {
+            if result.is_empty() {
+                break;
+            }
+            let ids: std::collections::HashSet<RowId> = list.iter().map(|p| p.row_id).collect();
+            result.retain(|id| ids.contains(id));
         }
+        result
+    }
 
+    // rows matching every clause of a parsed query, or any of them. sorted
+    pub fn search_text(&self, query: &TextQuery, any: bool) -> Vec<RowId> {
         let mut result: Option<Vec<RowId>> = None;
-        
-        for term in terms {
-            let ids = self.search_term(term);
-            match &mut result {
-                None => result = Some(ids.to_vec()),
-                Some(r) => {
-                    r.retain(|id| ids.contains(id));
+        for clause in &query.clauses {
+            let mut ids = self.search_clause(clause);
+            ids.sort_unstable();
+            ids.dedup();
+            result = Some(match result {
+                None => ids,
+                Some(mut current) if any => {
+                    current.extend(ids);
+                    current.sort_unstable();
+                    current.dedup();
+                    current
                 }
-            }
+                Some(mut current) => {
+                    current.retain(|id| ids.binary_search(id).is_ok());
+                    current
+                }
+            });
         }
-        
         result.unwrap_or_default()
     }
 
+    fn search_clause(&self, clause: &TextClause) -> Vec<RowId> {
+        match clause {
+            TextClause::Phrase(words) if words.len() == 1 => self.search_term(&words[0]),
+            TextClause::Phrase(words) => self.phrase_starts(words).into_keys().collect(),
+            TextClause::Near { left, right, distance } => {
+                let lefts = self.phrase_starts(left);
+                let rights = self.phrase_starts(right);
+                lefts.iter()
+                    .filter(|(id, a)| rights.get(*id).is_some_and(|b| {
+                        near(a, left.len() as u32, b, right.len() as u32, *distance)
+                    }))
+                    .map(|(id, _)| *id)
+                    .collect()
+            }
+        }
+    }
+
+    // per row, every position where `words` occur one after another
+    fn phrase_starts(&self, words: &[Box<str>]) -> HashMap<RowId, Vec<u32>> {
+        let (first, rest) = match words.split_first() {
+            Some(split) => split,
+            None => return HashMap::new(),
+        };
+
+        let rest: Vec<HashMap<RowId, &[u32]>> = rest.iter()
+            .map(|w| self.postings(w).iter().map(|p| (p.row_id, p.positions.as_slice())).collect())
+            .collect();
+
+        let mut result = HashMap::new();
+        for posting in self.postings(first) {
+            let found: Option<Vec<&[u32]>> = rest.iter().map(|m| m.get(&posting.row_id).copied()).collect();
+            let following = match found {
+                Some(following) => following,
+                None => continue,
+            };
+
+            let starts: Vec<u32> = posting.positions.iter().copied()
+                .filter(|start| following.iter().enumerate().all(|(i, positions)| {
+                    positions.binary_search(&(start + i as u32 + 1)).is_ok()
+                }))
+                .collect();
+            if !starts.is_empty() {
+                result.insert(posting.row_id, starts);
+            }
+        }
+        result
+    }
+
+    // indexed terms within the automaton's edit distance, with that distance
+    pub fn fuzzy_terms(&self, automaton: &LevenshteinAutomaton) -> Vec<(&str, u32)> {
+        self.terms.keys()
+            .filter_map(|term| automaton.eval(term).map(|d| (&**term, d)))
+            .collect()
+    }
+
+    // rows containing at least one of the terms
+    pub fn search_any(&self, terms: &[&str]) -> Vec<RowId> {
+        let mut result: Vec<RowId> = terms.iter().flat_map(|t| self.postings(t)).map(|p| p.row_id).collect();
+        result.sort_unstable();
+        result.dedup();
+        result
+    }
+
     pub fn remove_text(&mut self, text: &str, row_id: RowId) {
-        for token in Self::tokenize(text) {
-            if let Some(ids) = self.terms.get_mut(token) {
-                ids.retain(|&id| id != row_id);
-                if ids.is_empty() {
-                    self.terms.remove(token);
+        self.remove_texts([text], row_id);
+    }
+
+    // takes the same texts the row was indexed with
+    pub fn remove_texts<'a>(&mut self, texts: impl IntoIterator<Item = &'a str>, row_id: RowId) {
+        let mut tokens: Vec<Cow<'_, str>> = texts.into_iter().flat_map(|text| self.analyzer.analyze(text)).collect();
+        let len = tokens.len() as u32;
+        tokens.sort_unstable();
+        tokens.dedup();
+
+        for token in &tokens {
+            if let Some(postings) = self.terms.get_mut(&**token) {
+                postings.retain(|p| p.row_id != row_id);
+                if postings.is_empty() {
+                    self.terms.remove(&**token);
                 }
             }
         }
+
+        if let Some(doc_len) = self.doc_lens.get_mut(&row_id) {
+            *doc_len = doc_len.saturating_sub(len);
+            if *doc_len == 0 {
+                self.doc_lens.remove(&row_id);
+            }
+        }
+        self.total_len = self.total_len.saturating_sub(len as u64);
+    }
+
+    // number of terms in the row's text
+    pub fn doc_len(&self, row_id: RowId) -> u32 {
+        self.doc_lens.get(&row_id).copied().unwrap_or(0)
+    }
+
+    // rows with any indexed text
+    pub fn doc_count(&self) -> usize {
+        self.doc_lens.len()
+    }
+
+    pub fn avg_doc_len(&self) -> f64 {
+        if self.doc_lens.is_empty() {
+            return 0.0;
+        }
+        self.total_len as f64 / self.doc_lens.len() as f64
     }
 
     pub fn len(&self) -> usize {
@@ -150,19 +667,241 @@ impl InvertedIndex {
     }
 }
 
+// whether a phrase starting somewhere in `a` (length `a_len`) and one in `b`
+// have at most `distance` tokens between them, without overlapping
+fn near(a: &[u32], a_len: u32, b: &[u32], b_len: u32, distance: u32) -> bool {
+    a.iter().any(|&pa| {
+        // b after a
+        let after = pa.saturating_add(a_len);
+        let i = b.partition_point(|&pb| pb < after);
+        if b.get(i).is_some_and(|&pb| pb - after <= distance) {
+            return true;
+        }
+        // b before a
+        match pa.checked_sub(b_len) {
+            Some(latest) => {
+                let j = b.partition_point(|&pb| pb < latest.saturating_sub(distance));
+                b.get(j).is_some_and(|&pb| pb <= latest)
+            }
+            None => false,
+        }
+    })
+}
+
 impl Default for InvertedIndex {
     fn default() -> Self {
         Self::new()
     }
 }
 
-// trie for prefix search
+// n-gram index for substring and suffix search. every string is indexed under
+// each run of `n` chars, with an end marker appended so suffixes get grams of
+// their own; strings too short for a gram are kept whole. lookups only narrow
+// down candidates, the caller checks them against the actual values
+pub struct NgramIndex {
+    n: usize,
+    grams: HashMap<Box<str>, Vec<RowId>>,  // row ids kept sorted
+}
+
+pub const DEFAULT_NGRAM: usize = 3;
+
+// appended to every indexed string so its last grams mark where it ends
+const END_MARKER: char = '\0';
+
+impl NgramIndex {
+    pub fn new() -> Self {
+        Self::with_n(DEFAULT_NGRAM)
+    }
+
+    pub fn with_n(n: usize) -> Self {
+        Self {
+            n: n.max(1),
+            grams: HashMap::new(),
+        }
+    }
+
+    // every distinct gram of `text`, or the whole of it when shorter than n
+    fn grams(text: &str, n: usize) -> Vec<&str> {
+        let bounds: Vec<usize> = text.char_indices().map(|(i, _)| i).chain(Some(text.len())).collect();
+        let chars = bounds.len() - 1;
+        if chars < n {
+            return vec![text];
+        }
+
+        let mut grams: Vec<&str> = (0..=chars - n).map(|i| &text[bounds[i]..bounds[i + n]]).collect();
+        grams.sort_unstable();
+        grams.dedup();
+        grams
+    }
+
+    fn marked(text: &str) -> String {
+        let mut marked = String::with_capacity(text.len() + 1);
+        marked.push_str(text);
+        marked.push(END_MARKER);
+        marked
+    }
+
+    pub fn insert(&mut self, text: &str, row_id: RowId) {
+        let marked = Self::marked(text);
+        for gram in Self::grams(&marked, self.n) {
+            let ids = match self.grams.get_mut(gram) {
+                Some(ids) => ids,
+                None => self.grams.entry(gram.into()).or_default(),
+            };
+            // ids mostly arrive in order, so this is usually a push
+            if ids.last().is_none_or(|last| *last < row_id) {
+                ids.push(row_id);
+            } else if let Err(pos) = ids.binary_search(&row_id) {
+                ids.insert(pos, row_id);
+            }
+        }
+    }
+
+    pub fn remove(&mut self, text: &str, row_id: RowId) {
+        let marked = Self::marked(text);
+        for gram in Self::grams(&marked, self.n) {
+            if let Some(ids) = self.grams.get_mut(gram) {
+                if let Ok(pos) = ids.binary_search(&row_id) {
+                    ids.remove(pos);
+                }
+                if ids.is_empty() {
+                    self.grams.remove(gram);
+                }
+            }
+        }
+    }
+
+    // sorted rows that might contain `pattern` (or end with it), none when
+    // the index can't narrow things down and every row is a candidate
+    pub fn candidates(&self, pattern: &str, suffix: bool) -> Option<Vec<RowId>> {
+        let pattern = if suffix { Self::marked(pattern) } else { pattern.to_string() };
+        if pattern.is_empty() {
+            return None;
+        }
+
+        // too short for a gram of its own: any gram holding it will do
+        if pattern.chars().count() < self.n {
+            let mut ids: Vec<RowId> = self.grams.iter()
+                .filter(|(gram, _)| gram.contains(pattern.as_str()))
+                .flat_map(|(_, ids)| ids.iter().copied())
+                .collect();
+            ids.sort_unstable();
+            ids.dedup();
+            return Some(ids);
+        }
+
+        let mut lists = Vec::new();
+        for gram in Self::grams(&pattern, self.n) {
+            match self.grams.get(gram) {
+                Some(ids) => lists.push(ids.as_slice()),
+                None => return Some(Vec::new()),
+            }
+        }
+        lists.sort_by_key(|ids| ids.len());
+
+        let mut result = lists[0].to_vec();
+        for ids in &lists[1..] {
+            result.retain(|id| ids.binary_search(id).is_ok());
+        }
+        Some(result)
+    }
+
+    // how many candidates a search would start from, without collecting them
+    pub fn estimate(&self, pattern: &str, suffix: bool) -> usize {
+        let pattern = if suffix { Self::marked(pattern) } else { pattern.to_string() };
+        if pattern.chars().count() < self.n {
+            return usize::MAX;
+        }
+        Self::grams(&pattern, self.n).iter()
+            .map(|gram| self.grams.get(*gram).map_or(0, |ids| ids.len()))
+            .min()
+            .unwrap_or(0)
+    }
+
+    pub fn n(&self) -> usize {
+        self.n
+    }
+
+    pub fn len(&self) -> usize {
+        self.grams.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.grams.is_empty()
+    }
+}
+
+impl Default for NgramIndex {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// radix tree for prefix search. an edge holds a run of bytes rather than a
+// single one, so chains of one-child nodes collapse into a single node, and
+// remove prunes whatever is left without rows or a fork. besides the rows
+// under each key, every node counts the distinct keys below it and, once
+// there are more of those than a suggestion list holds, keeps the most common
+// ones so `suggest` never has to walk a big subtree
 #[derive(Default)]
 pub struct TrieNode {
-    children: HashMap<u8, Box<TrieNode>>,
+    label: Box<[u8]>,               // the bytes on the edge in from the parent
+    children: Vec<TrieNode>,        // by the first byte of their label
     row_ids: Vec<RowId>,
+    distinct: usize,
+    top: Vec<(Arc<str>, usize)>,    // best first, empty while distinct <= SUGGEST_TOP_K
+}
+
+impl TrieNode {
+    fn leaf(label: &[u8]) -> Self {
+        Self {
+            label: label.into(),
+            ..Self::default()
+        }
+    }
+
+    fn child_pos(&self, byte: u8) -> Result<usize, usize> {
+        self.children.binary_search_by_key(&byte, |c| c.label[0])
+    }
+
+    fn child(&self, byte: u8) -> Option<&TrieNode> {
+        self.child_pos(byte).ok().map(|i| &self.children[i])
+    }
+
+    fn child_mut(&mut self, byte: u8) -> Option<&mut TrieNode> {
+        match self.child_pos(byte) {
+            Ok(i) => Some(&mut self.children[i]),
+            Err(_) => None,
+        }
+    }
+
+    // cut the edge in from the parent after `at` bytes, the node keeping the
+    // first part and a new child taking the rest along with everything below.
+    // the subtree doesn't change, so neither do its counts
+    fn split(&mut self, at: usize) {
+        let mut tail = std::mem::take(self);
+        self.label = tail.label[..at].into();
+        self.distinct = tail.distinct;
+        self.top = tail.top.clone();
+        tail.label = tail.label[at..].into();
+        self.children.push(tail);
+    }
+
+    // fold a node with no rows into its only child
+    fn merge_child(&mut self) {
+        let child = self.children.pop().unwrap();
+        let mut label = std::mem::take(&mut self.label).into_vec();
+        label.extend_from_slice(&child.label);
+        *self = TrieNode {
+            label: label.into_boxed_slice(),
+            ..child
+        };
+    }
 }
 
+// completions precomputed per node. asking for more walks the subtree
+pub const SUGGEST_TOP_K: usize = 10;
+
 pub struct TrieIndex {
     root: TrieNode,
 }
@@ -176,43 +915,362 @@ impl TrieIndex {
 
     #[inline(always)]
     pub fn insert(&mut self, key: &str, row_id: RowId) {
+        let bytes = key.as_bytes();
         let mut node = &mut self.root;
-        for byte in key.bytes() {
-            node = node.children.entry(byte).or_insert_with(|| Box::new(TrieNode::default()));
+        let mut depth = 0;
+        while depth < bytes.len() {
+            let rest = &bytes[depth..];
+            let i = match node.child_pos(rest[0]) {
+                Ok(i) => i,
+                Err(i) => {
+                    node.children.insert(i, TrieNode::leaf(rest));
+                    i
+                }
+            };
+            let child = &mut node.children[i];
+            let common = shared_len(&child.label, rest);
+            if common < child.label.len() {
+                child.split(common);
+            }
+            depth += common;
+            node = child;
         }
         node.row_ids.push(row_id);
+        let count = node.row_ids.len();
+
+        // the key's count went up by one, everything else stayed put, so each
+        // node on the path only needs that entry moved up
+        let mut node = &mut self.root;
+        let mut depth = 0;
+        loop {
+            if count == 1 {
+                node.distinct += 1;
+            }
+            if node.distinct > SUGGEST_TOP_K {
+                if node.top.is_empty() {
+                    node.top = ranked(collect_keys(node, &bytes[..depth]), SUGGEST_TOP_K);
+                } else {
+                    raise(&mut node.top, key, count);
+                }
+            }
+            if depth == bytes.len() {
+                break;
+            }
+            node = node.child_mut(bytes[depth]).unwrap();
+            depth += node.label.len();
+        }
     }
 
     #[inline(always)]
     pub fn search_prefix(&self, prefix: &str) -> Vec<RowId> {
+        let mut result = Vec::new();
+        if let Some((node, _)) = self.locate(prefix.as_bytes()) {
+            let mut stack = vec![node];
+            while let Some(node) = stack.pop() {
+                result.extend_from_slice(&node.row_ids);
+                stack.extend(&node.children);
+            }
+        }
+        result
+    }
+
+    // the topmost node whose keys all start with `prefix`, and that node's
+    // own key, which runs past the prefix when it ends partway along an edge
+    fn locate(&self, prefix: &[u8]) -> Option<(&TrieNode, Vec<u8>)> {
         let mut node = &self.root;
-        for byte in prefix.bytes() {
-            match node.children.get(&byte) {
-                Some(child) => node = child,
-                None => return Vec::new(),
+        let mut depth = 0;
+        while depth < prefix.len() {
+            let rest = &prefix[depth..];
+            let child = node.child(rest[0])?;
+            let common = shared_len(&child.label, rest);
+            if common < child.label.len() {
+                if common < rest.len() {
+                    return None;
+                }
+                let mut key = prefix.to_vec();
+                key.extend_from_slice(&child.label[common..]);
+                return Some((child, key));
             }
+            depth += common;
+            node = child;
         }
-        self.collect_all_ids(node)
+        Some((node, prefix.to_vec()))
     }
 
-    fn collect_all_ids(&self, node: &TrieNode) -> Vec<RowId> {
-        let mut result = node.row_ids.clone();
-        for child in node.children.values() {
-            result.extend(self.collect_all_ids(child));
+    // the node for exactly `key`, if one ends there
+    fn node_mut(&mut self, key: &[u8]) -> Option<&mut TrieNode> {
+        let mut node = &mut self.root;
+        let mut depth = 0;
+        while depth < key.len() {
+            node = node.child_mut(key[depth])?;
+            if !key[depth..].starts_with(&node.label) {
+                return None;
+            }
+            depth += node.label.len();
         }
-        result
+        Some(node)
     }
 
     pub fn remove(&mut self, key: &str, row_id: RowId) {
+        let bytes = key.as_bytes();
+        let node = match self.node_mut(bytes) {
+            Some(node) => node,
+            None => return,
+        };
+        let before = node.row_ids.len();
+        node.row_ids.retain(|&id| id != row_id);
+        if node.row_ids.len() == before {
+            return;
+        }
+        let count = node.row_ids.len();
+
+        // distinct counts first, noting where each node on the path starts
+        // and how deep the ones with a top list go
+        let mut depths = Vec::new();
+        let mut listed = 0;
         let mut node = &mut self.root;
-        for byte in key.bytes() {
-            match node.children.get_mut(&byte) {
-                Some(child) => node = child,
-                None => return,
+        let mut depth = 0;
+        loop {
+            depths.push(depth);
+            if count == 0 {
+                node.distinct -= 1;
+            }
+            if node.distinct > SUGGEST_TOP_K {
+                listed = depths.len();
+            } else {
+                node.top.clear();
             }
+            if depth == bytes.len() {
+                break;
+            }
+            node = node.child_mut(bytes[depth]).unwrap();
+            depth += node.label.len();
+        }
+
+        // a list may now be missing something that overtook the key, so fix
+        // them bottom up, each one from its children's. once a list doesn't
+        // have the key, no list above it has it either
+        for &depth in depths[..listed].iter().rev() {
+            let node = self.node_mut(&bytes[..depth]).unwrap();
+            let pos = match node.top.iter().position(|(v, _)| **v == *key) {
+                Some(pos) => pos,
+                None => break,
+            };
+            if count > 0 {
+                node.top[pos].1 = count;
+                node.top.sort_unstable_by(by_count);
+            }
+            // anywhere but last and nothing outside the list can have passed it
+            if count == 0 || *node.top[node.top.len() - 1].0 == *key {
+                node.top = ranked(node_candidates(node, &bytes[..depth]), SUGGEST_TOP_K);
+            }
+        }
+
+        if count == 0 {
+            self.prune(bytes);
+        }
+    }
+
+    // drop the node of a key that just lost its last row, then fold away
+    // whatever that leaves with no rows and a single child
+    fn prune(&mut self, key: &[u8]) {
+        if key.is_empty() {
+            return;
+        }
+        let mut parent = &mut self.root;
+        let mut depth = 0;
+        loop {
+            let i = parent.child_pos(key[depth]).unwrap();
+            let end = depth + parent.children[i].label.len();
+            if end < key.len() {
+                parent = &mut parent.children[i];
+                depth = end;
+                continue;
+            }
+
+            let node = &mut parent.children[i];
+            match node.children.len() {
+                0 => {
+                    parent.children.remove(i);
+                }
+                1 => node.merge_child(),
+                _ => {}
+            }
+            // the root stays put even when it's just a fork
+            if depth > 0 && parent.row_ids.is_empty() && parent.children.len() == 1 {
+                parent.merge_child();
+            }
+            return;
+        }
+    }
+
+    // nodes in the tree, the root included
+    pub fn node_count(&self) -> usize {
+        let mut count = 0;
+        let mut stack = vec![&self.root];
+        while let Some(node) = stack.pop() {
+            count += 1;
+            stack.extend(&node.children);
+        }
+        count
+    }
+
+    // the `limit` most common keys starting with `prefix` and how many rows
+    // hold each, ties going to the smaller key
+    pub fn suggest(&self, prefix: &str, limit: usize) -> Vec<(Arc<str>, usize)> {
+        let (node, key) = match self.locate(prefix.as_bytes()) {
+            Some(found) => found,
+            None => return Vec::new(),
+        };
+        if limit <= node.top.len() {
+            return node.top[..limit].to_vec();
+        }
+        ranked(collect_keys(node, &key), limit)
+    }
+
+    // every key starting with `prefix` with the rows holding it
+    pub fn completions(&self, prefix: &str) -> Vec<(String, &[RowId])> {
+        let (node, key) = match self.locate(prefix.as_bytes()) {
+            Some(found) => found,
+            None => return Vec::new(),
+        };
+        let mut result = Vec::new();
+        let mut stack = vec![(node, key)];
+        while let Some((node, key)) = stack.pop() {
+            if !node.row_ids.is_empty() {
+                result.push((String::from_utf8_lossy(&key).into_owned(), node.row_ids.as_slice()));
+            }
+            for child in &node.children {
+                let mut key = key.clone();
+                key.extend_from_slice(&child.label);
+                stack.push((child, key));
+            }
+        }
+        result
+    }
+
+    // rows whose key is within the automaton's edit distance, with that
+    // distance. edges are bytes, so multi-byte chars are buffered until
+    // complete before the automaton sees them, even across nodes
+    pub fn search_fuzzy(&self, automaton: &LevenshteinAutomaton) -> Vec<(RowId, u32)> {
+        let mut result = Vec::new();
+        let mut stack = vec![(&self.root, automaton.start(), Vec::new())];
+
+        while let Some((node, state, pending)) = stack.pop() {
+            if pending.is_empty() {
+                if let Some(distance) = automaton.distance(&state) {
+                    result.extend(node.row_ids.iter().map(|id| (*id, distance)));
+                }
+            }
+
+            'children: for child in &node.children {
+                let mut state = state.clone();
+                let mut pending = pending.clone();
+                for byte in child.label.iter() {
+                    pending.push(*byte);
+                    if pending.len() < utf8_len(pending[0]) {
+                        continue;
+                    }
+
+                    let c = match std::str::from_utf8(&pending).ok().and_then(|s| s.chars().next()) {
+                        Some(c) => c,
+                        None => continue 'children,
+                    };
+                    state = automaton.step(&state, c);
+                    pending.clear();
+                    if !automaton.can_match(&state) {
+                        continue 'children;
+                    }
+                }
+                stack.push((child, state, pending));
+            }
+        }
+        result
+    }
+}
+
+// bytes in the utf-8 sequence starting with `lead`
+fn utf8_len(lead: u8) -> usize {
+    match lead {
+        0x00..=0x7F => 1,
+        0xC0..=0xDF => 2,
+        0xE0..=0xEF => 3,
+        _ => 4,
+    }
+}
+
+fn shared_len(a: &[u8], b: &[u8]) -> usize {
+    a.iter().zip(b).take_while(|(x, y)| x == y).count()
+}
+
+fn by_count(a: &(Arc<str>, usize), b: &(Arc<str>, usize)) -> Ordering {
+    b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0))
+}
+
+fn ranked(mut keys: Vec<(Arc<str>, usize)>, limit: usize) -> Vec<(Arc<str>, usize)> {
+    keys.sort_unstable_by(by_count);
+    keys.truncate(limit);
+    keys
+}
+
+// move `value` up to `count` in a top list, letting it in if it now beats the
+// last entry
+fn raise(top: &mut Vec<(Arc<str>, usize)>, value: &str, count: usize) {
+    let mut pos = match top.iter().position(|(v, _)| **v == *value) {
+        Some(pos) => pos,
+        None => {
+            if let Some((last, n)) = top.last().filter(|_| top.len() >= SUGGEST_TOP_K) {
+                if count < *n || (count == *n && value > &**last) {
+                    return;
+                }
+            }
+            top.push((Arc::from(value), 0));
+            top.len() - 1
+        }
+    };
+    top[pos].1 = count;
+    while pos > 0 && by_count(&top[pos], &top[pos - 1]) == Ordering::Less {
+        top.swap(pos, pos - 1);
+        pos -= 1;
+    }
+    top.truncate(SUGGEST_TOP_K);
+}
+
+// every key in the subtree of the node whose own key is `prefix`, with its
+// row count
+fn collect_keys(node: &TrieNode, prefix: &[u8]) -> Vec<(Arc<str>, usize)> {
+    let mut keys = Vec::new();
+    let mut stack = vec![(node, prefix.to_vec())];
+    while let Some((node, key)) = stack.pop() {
+        if !node.row_ids.is_empty() {
+            keys.push((Arc::from(String::from_utf8_lossy(&key)), node.row_ids.len()));
+        }
+        for child in &node.children {
+            let mut key = key.clone();
+            key.extend_from_slice(&child.label);
+            stack.push((child, key));
         }
-        node.row_ids.retain(|&id| id != row_id);
     }
+    keys
+}
+
+// what a node's top list is picked from: its own key and its children's
+// lists, or their whole subtrees when those are too small to keep one
+fn node_candidates(node: &TrieNode, prefix: &[u8]) -> Vec<(Arc<str>, usize)> {
+    let mut keys = Vec::new();
+    if !node.row_ids.is_empty() {
+        keys.push((Arc::from(String::from_utf8_lossy(prefix)), node.row_ids.len()));
+    }
+    for child in &node.children {
+        if child.top.is_empty() {
+            let mut key = prefix.to_vec();
+            key.extend_from_slice(&child.label);
+            keys.extend(collect_keys(child, &key));
+        } else {
+            keys.extend(child.top.iter().cloned());
+        }
+    }
+    keys
 }
 
 impl Default for TrieIndex {
@@ -221,73 +1279,224 @@ impl Default for TrieIndex {
     }
 }
 
-// sorted index for range queries
-pub struct SortedIndex {
-    entries: Vec<(i64, RowId)>,
-    sorted: bool,
+// tear down iteratively, long keys would otherwise blow the stack in the recursive drop
+impl Drop for TrieIndex {
+    fn drop(&mut self) {
+        let mut stack = std::mem::take(&mut self.root.children);
+        while let Some(mut node) = stack.pop() {
+            stack.append(&mut node.children);
+        }
+    }
+}
+
+// a float that can key an ordered index. nan has no place in a range so it
+// never gets a key, and -0.0 is folded into 0.0 so a range over zero finds both
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FloatKey(f64);
+
+impl FloatKey {
+    pub fn new(f: f64) -> Option<Self> {
+        if f.is_nan() {
+            return None;
+        }
+        Some(Self(if f == 0.0 { 0.0 } else { f }))
+    }
+
+    pub fn get(self) -> f64 {
+        self.0
+    }
 }
 
-impl SortedIndex {
+impl Eq for FloatKey {}
+
+impl PartialOrd for FloatKey {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for FloatKey {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
+
+// sorted index for range queries over any ordered key: ints, `FloatKey`s,
+// strings in byte order. kept in order at write time so searches only need
+// `&self`: new entries land in a small ordered delta that is merged into the
+// main run once it grows past a fraction of it
+pub struct SortedIndex<K = i64> {
+    entries: Vec<(K, RowId)>,
+    delta: BTreeSet<(K, RowId)>,
+}
+
+impl<K: Ord + Clone> SortedIndex<K> {
+    const MIN_DELTA: usize = 4096;
+
     pub fn new() -> Self {
         Self {
             entries: Vec::with_capacity(1_000_000),
-            sorted: true,
+            delta: BTreeSet::new(),
         }
     }
 
     pub fn with_capacity(capacity: usize) -> Self {
         Self {
             entries: Vec::with_capacity(capacity),
-            sorted: true,
+            delta: BTreeSet::new(),
         }
     }
 
     #[inline(always)]
-    pub fn insert(&mut self, value: i64, row_id: RowId) {
-        self.entries.push((value, row_id));
-        self.sorted = false;
+    pub fn insert(&mut self, value: K, row_id: RowId) {
+        let entry = (value, row_id);
+
+        // appends in order (ids from a fresh load, timestamps...) skip the delta
+        if self.delta.is_empty() && self.entries.last().is_none_or(|last| *last <= entry) {
+            self.entries.push(entry);
+            return;
+        }
+
+        self.delta.insert(entry);
+        if self.delta.len() > Self::MIN_DELTA.max(self.entries.len() / 8) {
+            self.merge();
+        }
     }
 
-    #[inline(always)]
-    fn ensure_sorted(&mut self) {
-        if !self.sorted {
-            self.entries.sort_unstable_by_key(|(v, _)| *v);
-            self.sorted = true;
+    // fold the delta into the main run
+    pub fn merge(&mut self) {
+        if self.delta.is_empty() {
+            return;
+        }
+
+        let main = std::mem::take(&mut self.entries);
+        let mut merged = Vec::with_capacity(main.len() + self.delta.len());
+        let mut main = main.into_iter().peekable();
+        let mut delta = std::mem::take(&mut self.delta).into_iter().peekable();
+
+        loop {
+            let next = match (main.peek(), delta.peek()) {
+                (Some(a), Some(b)) => if a <= b { main.next() } else { delta.next() },
+                (Some(_), None) => main.next(),
+                (None, Some(_)) => delta.next(),
+                (None, None) => break,
+            };
+            merged.extend(next);
         }
+
+        self.entries = merged;
     }
 
-    pub fn search_range(&mut self, min: i64, max: i64) -> Vec<RowId> {
-        self.ensure_sorted();
-        
-        let start = self.entries.partition_point(|(v, _)| *v < min);
-        let end = self.entries.partition_point(|(v, _)| *v <= max);
-        
-        self.entries[start..end].iter().map(|(_, id)| *id).collect()
+    // rows with min <= value <= max, by value
+    pub fn search_range(&self, min: K, max: K) -> Vec<RowId> {
+        self.search_bounds(Bound::Included(&min), Bound::Included(&max))
     }
 
-    pub fn search_exact(&mut self, value: i64) -> Vec<RowId> {
-        self.ensure_sorted();
-        
-        let start = self.entries.partition_point(|(v, _)| *v < value);
-        let end = self.entries.partition_point(|(v, _)| *v <= value);
-        
-        self.entries[start..end].iter().map(|(_, id)| *id).collect()
+    // rows between two bounds, each inclusive, exclusive or open, by value
+    pub fn search_bounds(&self, min: Bound<&K>, max: Bound<&K>) -> Vec<RowId> {
+        if is_empty_range(min, max) {
+            return Vec::new();
+        }
+
+        let main = &self.entries[self.main_span(min, max)];
+        if self.delta.is_empty() {
+            return main.iter().map(|(_, id)| *id).collect();
+        }
+
+        // both sides are ordered, merge them so results stay sorted by value
+        let mut result = Vec::with_capacity(main.len());
+        let mut main = main.iter().peekable();
+        let mut delta = self.delta.range(delta_span(min, max)).peekable();
+
+        loop {
+            let next = match (main.peek(), delta.peek()) {
+                (Some(a), Some(b)) => if *a <= *b { main.next() } else { delta.next() },
+                (Some(_), None) => main.next(),
+                (None, Some(_)) => delta.next(),
+                (None, None) => break,
+            };
+            result.extend(next.map(|(_, id)| *id));
+        }
+
+        result
+    }
+
+    pub fn search_exact(&self, value: K) -> Vec<RowId> {
+        self.search_range(value.clone(), value)
+    }
+
+    // how many entries fall in [min, max], without collecting them
+    pub fn count_range(&self, min: K, max: K) -> usize {
+        self.count_bounds(Bound::Included(&min), Bound::Included(&max))
+    }
+
+    pub fn count_bounds(&self, min: Bound<&K>, max: Bound<&K>) -> usize {
+        if is_empty_range(min, max) {
+            return 0;
+        }
+        self.main_span(min, max).len() + self.delta.range(delta_span(min, max)).count()
+    }
+
+    fn main_span(&self, min: Bound<&K>, max: Bound<&K>) -> std::ops::Range<usize> {
+        let start = match min {
+            Bound::Included(min) => self.entries.partition_point(|(v, _)| v < min),
+            Bound::Excluded(min) => self.entries.partition_point(|(v, _)| v <= min),
+            Bound::Unbounded => 0,
+        };
+        let end = match max {
+            Bound::Included(max) => self.entries.partition_point(|(v, _)| v <= max),
+            Bound::Excluded(max) => self.entries.partition_point(|(v, _)| v < max),
+            Bound::Unbounded => self.entries.len(),
+        };
+        start..end.max(start)
     }
 
-    pub fn remove(&mut self, value: i64, row_id: RowId) {
-        self.entries.retain(|(v, id)| !(*v == value && *id == row_id));
+    pub fn remove(&mut self, value: K, row_id: RowId) {
+        let entry = (value, row_id);
+        if self.delta.remove(&entry) {
+            return;
+        }
+        if let Ok(pos) = self.entries.binary_search(&entry) {
+            self.entries.remove(pos);
+        }
     }
 
     pub fn len(&self) -> usize {
-        self.entries.len()
+        self.entries.len() + self.delta.len()
     }
 
     pub fn is_empty(&self) -> bool {
-        self.entries.is_empty()
+        self.entries.is_empty() && self.delta.is_empty()
+    }
+}
+
+// bounds nothing can fall between, which BTreeSet::range would panic on
+fn is_empty_range<K: Ord>(min: Bound<&K>, max: Bound<&K>) -> bool {
+    match (min, max) {
+        (Bound::Included(min), Bound::Included(max)) => min > max,
+        (Bound::Included(min) | Bound::Excluded(min), Bound::Included(max) | Bound::Excluded(max)) => min >= max,
+        _ => false,
     }
 }
 
-impl Default for SortedIndex {
+type EntryBound<K> = Bound<(K, RowId)>;
+
+// the same bounds over (value, row id) pairs
+fn delta_span<K: Clone>(min: Bound<&K>, max: Bound<&K>) -> (EntryBound<K>, EntryBound<K>) {
+    let min = match min {
+        Bound::Included(min) => Bound::Included((min.clone(), RowId::MIN)),
+        Bound::Excluded(min) => Bound::Excluded((min.clone(), RowId::MAX)),
+        Bound::Unbounded => Bound::Unbounded,
+    };
+    let max = match max {
+        Bound::Included(max) => Bound::Included((max.clone(), RowId::MAX)),
+        Bound::Excluded(max) => Bound::Excluded((max.clone(), RowId::MIN)),
+        Bound::Unbounded => Bound::Unbounded,
+    };
+    (min, max)
+}
+
+impl<K: Ord + Clone> Default for SortedIndex<K> {
     fn default() -> Self {
         Self::new()
     }
@@ -306,12 +1515,12 @@ impl BloomFilter {
         let ln2 = std::f64::consts::LN_2;
         let ln2_sq = ln2 * ln2;
         let bits_needed = (-(expected_items as f64) * false_positive_rate.ln() / ln2_sq).ceil() as usize;
-        let num_bits = ((bits_needed + 63) / 64) * 64;
+        let num_bits = bits_needed.div_ceil(64) * 64;
         let num_hashes = ((num_bits as f64 / expected_items as f64) * ln2).ceil() as usize;
         
         Self {
             bits: vec![0; num_bits / 64],
-            num_hashes: num_hashes.max(1).min(16),
+            num_hashes: num_hashes.clamp(1, 16),
             num_bits,
         }
     }
@@ -379,6 +1588,65 @@ mod tests {
         assert!(results.contains(&2));
     }
 
+    // every value hashes to 0, so every lookup goes through a collision
+    #[derive(Default)]
+    struct ZeroHasher;
+
+    impl Hasher for ZeroHasher {
+        fn finish(&self) -> u64 {
+            0
+        }
+
+        fn write(&mut self, _bytes: &[u8]) {}
+    }
+
+    #[test]
+    fn test_hash_index_collisions() {
+        let mut index = HashIndex::with_hasher(BuildHasherDefault::<ZeroHasher>::default());
+        let values = [
+            Value::Null,
+            Value::Int(0),
+            Value::Float(0.0),
+            Value::Float(-0.0),
+            Value::String("a".into()),
+            Value::String("b".into()),
+            Value::Bytes(vec![b'a'].into_boxed_slice()),
+        ];
+
+        for (i, value) in values.iter().enumerate() {
+            index.insert(value, i as RowId);
+            index.insert(value, 100 + i as RowId);
+        }
+        assert_eq!(index.len(), values.len());
+
+        for (i, value) in values.iter().enumerate() {
+            assert_eq!(index.search(value), &[i as RowId, 100 + i as RowId]);
+        }
+        assert!(index.search(&Value::Int(1)).is_empty());
+
+        // emptying the inline key promotes a collided one without losing the rest
+        index.remove(&Value::Null, 0);
+        index.remove(&Value::Null, 100);
+        index.remove(&Value::String("a".into()), 4);
+        assert!(index.search(&Value::Null).is_empty());
+        assert_eq!(index.search(&Value::String("a".into())), &[104]);
+        assert_eq!(index.search(&Value::Int(0)), &[1, 101]);
+        assert_eq!(index.search(&Value::Bytes(vec![b'a'].into_boxed_slice())), &[6, 106]);
+        assert_eq!(index.len(), values.len() - 1);
+    }
+
+    #[test]
+    fn test_index_set() {
+        let set: IndexSet = [IndexKind::Exact, IndexKind::Range].into_iter().collect();
+        assert!(set.contains(IndexKind::Exact));
+        assert!(!set.contains(IndexKind::Prefix));
+        assert_eq!(set.iter().collect::<Vec<_>>(), vec![IndexKind::Exact, IndexKind::Range]);
+        assert_eq!(IndexSet::from_bits(set.bits()), Some(set));
+        assert_eq!(IndexSet::from_bits(0x80), None);
+        assert!(set.without(IndexKind::Exact).without(IndexKind::Range).is_empty());
+        assert_eq!(IndexKind::from_str("TRIE"), Some(IndexKind::Prefix));
+    }
+
     #[test]
     fn test_inverted_index() {
         let mut index = InvertedIndex::new();
@@ -403,6 +1671,121 @@ mod tests {
         
         let results = index.search_terms(&["rust", "programming"]);
         assert_eq!(results.len(), 2);
+
+        assert_eq!(index.search_any(&["python", "systems"]), vec![2, 3]);
+    }
+
+    #[test]
+    fn test_inverted_index_stats() {
+        let mut index = InvertedIndex::new();
+
+        index.index_text("the cat sat on the mat", 1);
+        index.index_text("the dog", 2);
+        assert_eq!(index.postings("the"), &[
+            Posting { row_id: 1, positions: vec![0, 4] },
+            Posting { row_id: 2, positions: vec![0] },
+        ]);
+        assert_eq!(index.doc_len(1), 6);
+        assert_eq!(index.doc_count(), 2);
+        assert_eq!(index.avg_doc_len(), 4.0);
+
+        index.remove_text("the cat sat on the mat", 1);
+        assert_eq!(index.search_term("the"), vec![2]);
+        assert!(index.search_term("cat").is_empty());
+        assert_eq!(index.doc_count(), 1);
+        assert_eq!(index.avg_doc_len(), 2.0);
+    }
+
+    #[test]
+    fn test_text_query_parse() {
+        let phrase = |words: &[&str]| words.iter().map(|w| Box::from(*w)).collect::<Vec<Box<str>>>();
+
+        let raw = Analyzer::raw();
+        let query = TextQuery::parse(r#"big "new york" apple NEAR/2 pie"#, &raw);
+        assert_eq!(query.clauses, vec![
+            TextClause::Near { left: phrase(&["apple"]), right: phrase(&["pie"]), distance: 2 },
+            TextClause::Phrase(phrase(&["big"])),
+            TextClause::Phrase(phrase(&["new", "york"])),
+        ]);
+        assert_eq!(query.terms().collect::<Vec<_>>(), vec!["apple", "pie", "big", "new", "york"]);
+
+        // bare NEAR takes the default, dangling ones and lowercase near are text
+        assert_eq!(
+            TextQuery::parse("a NEAR b", &raw).clauses,
+            vec![TextClause::Near { left: phrase(&["a"]), right: phrase(&["b"]), distance: DEFAULT_NEAR }]
+        );
+        assert_eq!(TextQuery::parse("NEAR/3 a near", &raw).clauses, vec![
+            TextClause::Phrase(phrase(&["NEAR", "3"])),
+            TextClause::Phrase(phrase(&["a"])),
+            TextClause::Phrase(phrase(&["near"])),
+        ]);
+
+        // an unclosed quote runs to the end, empty quotes vanish
+        assert_eq!(TextQuery::parse(r#""" "new york"#, &raw).clauses, vec![TextClause::Phrase(phrase(&["new", "york"]))]);
+        assert!(TextQuery::parse("  ", &raw).is_empty());
+
+        // words are analyzed, a phrase of nothing but stop words disappears
+        let query = TextQuery::parse(r#"Running "to the" NEAR/1 Dogs"#, &Analyzer::english());
+        assert_eq!(query.terms().collect::<Vec<_>>(), vec!["run", "dog"]);
+    }
+
+    #[test]
+    fn test_phrase_and_near() {
+        let mut index = InvertedIndex::new();
+        index.index_text("new york is big", 1);
+        index.index_text("york is new", 2);
+        index.index_text("buffalo buffalo buffalo", 3);
+        index.index_text("new shiny york", 4);
+
+        fn search(index: &InvertedIndex, q: &str) -> Vec<RowId> {
+            index.search_text(&index.parse_query(q), false)
+        }
+        assert_eq!(search(&index, "new york"), vec![1, 2, 4]);
+        assert_eq!(search(&index, r#""new york""#), vec![1]);
+        assert_eq!(search(&index, r#""york new""#), Vec::<RowId>::new());
+
+        // repeated tokens line up against their own positions
+        assert_eq!(search(&index, r#""buffalo buffalo buffalo""#), vec![3]);
+        assert!(search(&index, r#""buffalo buffalo buffalo buffalo""#).is_empty());
+        assert!(search(&index, "buffalo NEAR/0 buffalo").contains(&3));
+
+        // either order, distance counts the tokens in between
+        assert_eq!(search(&index, "new NEAR/0 york"), vec![1]);
+        assert_eq!(search(&index, "new NEAR/1 york"), vec![1, 2, 4]);
+        assert_eq!(search(&index, r#""new york" NEAR/1 big"#), vec![1]);
+        assert_eq!(index.search_text(&index.parse_query(r#""new york" "is new""#), true), vec![1, 2]);
+
+        // removal takes positions with it
+        index.remove_text("new york is big", 1);
+        assert!(search(&index, r#""new york""#).is_empty());
+        assert_eq!(search(&index, "new NEAR/1 york"), vec![2, 4]);
+        index.remove_text("buffalo buffalo buffalo", 3);
+        assert!(index.postings("buffalo").is_empty());
+        assert_eq!(index.doc_count(), 2);
+    }
+
+    #[test]
+    fn test_ngram_index() {
+        let mut index = NgramIndex::new();
+        index.insert("banana", 2);
+        index.insert("bandana", 1);
+        index.insert("an", 3);
+
+        // candidates only: bandana has every gram of "bana" without the substring
+        assert_eq!(index.candidates("bana", false), Some(vec![1, 2]));
+        assert_eq!(index.candidates("nana", false), Some(vec![2]));
+        assert_eq!(index.candidates("ana", true), Some(vec![1, 2]));
+        assert_eq!(index.candidates("an", true), Some(vec![3]));
+        assert_eq!(index.candidates("xyz", false), Some(vec![]));
+        assert_eq!(index.candidates("", false), None);
+        assert_eq!(index.estimate("ban", false), 2);
+        assert_eq!(index.estimate("b", false), usize::MAX);
+
+        index.remove("banana", 2);
+        assert_eq!(index.candidates("bana", false), Some(vec![1]));
+        index.remove("bandana", 1);
+        index.remove("an", 3);
+        assert!(index.is_empty());
     }
 
     #[test]
@@ -420,6 +1803,146 @@ mod tests {
         assert_eq!(results.len(), 1);
     }
 
+    #[test]
+    fn test_trie_compaction() {
+        let mut index = TrieIndex::new();
+        let words = ["romane", "romanus", "romulus", "rubens", "ruber", "rubicon", "rubicundus"];
+        for (id, word) in words.iter().enumerate() {
+            index.insert(word, id as RowId);
+        }
+        // root, r, om, an, e, us, ulus, ub, e, ns, r, ic, on, undus
+        assert_eq!(index.node_count(), 14);
+
+        // prefixes ending partway along an edge
+        let mut results = index.search_prefix("roma");
+        results.sort_unstable();
+        assert_eq!(results, vec![0, 1]);
+        assert_eq!(index.search_prefix("rubicu"), vec![6]);
+        assert!(index.search_prefix("romx").is_empty());
+        assert!(index.search_prefix("rubiconx").is_empty());
+
+        // a key ending partway along an edge splits it, one ending on a
+        // node just adds rows there
+        index.insert("rubi", 7);
+        assert_eq!(index.node_count(), 15);
+        index.insert("rub", 8);
+        assert_eq!(index.node_count(), 15);
+        let mut results = index.search_prefix("rubi");
+        results.sort_unstable();
+        assert_eq!(results, vec![5, 6, 7]);
+
+        // keys not in the tree, or rows not under the key, leave it alone
+        index.remove("rom", 0);
+        index.remove("romane", 1);
+        assert_eq!(index.node_count(), 15);
+
+        // "om" is left with only "an" below it and folds into "oman"
+        index.remove("romulus", 2);
+        assert_eq!(index.node_count(), 13);
+        assert_eq!(index.search_prefix("roman").len(), 2);
+
+        // and "i" back into "ic"
+        index.remove("rubi", 7);
+        index.remove("rub", 8);
+        assert_eq!(index.node_count(), 12);
+        for (id, word) in words.iter().enumerate() {
+            index.remove(word, id as RowId);
+        }
+        assert_eq!(index.node_count(), 1);
+        assert!(index.search_prefix("").is_empty());
+
+        // empty keys live on the root
+        index.insert("", 1);
+        index.insert("a", 2);
+        index.remove("", 1);
+        assert_eq!(index.search_prefix(""), vec![2]);
+        assert_eq!(index.node_count(), 2);
+    }
+
+    #[test]
+    fn test_trie_suggest() {
+        let mut index = TrieIndex::new();
+        for (id, city) in ["paris", "paris", "paris", "parma", "parma", "pisa", "porto"].iter().enumerate() {
+            index.insert(city, id as RowId);
+        }
+
+        let suggest = |index: &TrieIndex, prefix: &str, limit: usize| -> Vec<(String, usize)> {
+            index.suggest(prefix, limit).into_iter().map(|(v, n)| (v.to_string(), n)).collect()
+        };
+        assert_eq!(suggest(&index, "pa", 5), vec![("paris".into(), 3), ("parma".into(), 2)]);
+        assert_eq!(suggest(&index, "p", 3), vec![("paris".into(), 3), ("parma".into(), 2), ("pisa".into(), 1)]);
+        assert!(suggest(&index, "x", 3).is_empty());
+
+        let mut paths = index.completions("par");
+        paths.sort();
+        assert_eq!(paths, vec![("paris".to_string(), &[0, 1, 2][..]), ("parma".to_string(), &[3, 4][..])]);
+
+        // enough keys for the precomputed lists to kick in, checked against a
+        // plain count after every change
+        let mut index = TrieIndex::new();
+        let mut rows: Vec<(String, RowId)> = Vec::new();
+        let mut seed = 7u64;
+        for id in 0..2000 {
+            seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            if seed.is_multiple_of(4) && !rows.is_empty() {
+                let (key, row) = rows.swap_remove((seed >> 33) as usize % rows.len());
+                index.remove(&key, row);
+            } else {
+                let key = format!("k{}", (seed >> 40) % 40);
+                index.insert(&key, id);
+                rows.push((key, id));
+            }
+
+            if id % 50 == 0 {
+                for prefix in ["", "k", "k1", "k3"] {
+                    let mut counts: HashMap<&str, usize> = HashMap::new();
+                    for (key, _) in rows.iter().filter(|(k, _)| k.starts_with(prefix)) {
+                        *counts.entry(key.as_str()).or_default() += 1;
+                    }
+                    let mut expected: Vec<(String, usize)> = counts.into_iter().map(|(k, n)| (k.to_string(), n)).collect();
+                    expected.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+                    expected.truncate(SUGGEST_TOP_K);
+                    assert_eq!(suggest(&index, prefix, SUGGEST_TOP_K), expected);
+                }
+            }
+        }
+
+        // and nothing is left behind once every row is gone
+        for (key, row) in rows.drain(..) {
+            index.remove(&key, row);
+        }
+        assert_eq!(index.node_count(), 1);
+        assert!(suggest(&index, "", SUGGEST_TOP_K).is_empty());
+    }
+
+    #[test]
+    fn test_fuzzy_search() {
+        let mut trie = TrieIndex::new();
+        trie.insert("iphone", 1);
+        trie.insert("iphones", 2);
+        trie.insert("phone", 3);
+        trie.insert("ipod", 4);
+        trie.insert("naïve", 5);
+
+        let mut found = trie.search_fuzzy(&LevenshteinAutomaton::new("iphnoe", 2));
+        found.sort_unstable();
+        assert_eq!(found, vec![(1, 2)]);
+
+        let mut found = trie.search_fuzzy(&LevenshteinAutomaton::new("iphone", 1));
+        found.sort_unstable();
+        assert_eq!(found, vec![(1, 0), (2, 1), (3, 1)]);
+
+        // multi-byte chars count as one edit
+        assert_eq!(trie.search_fuzzy(&LevenshteinAutomaton::new("naive", 1)), vec![(5, 1)]);
+
+        let mut index = InvertedIndex::new();
+        index.index_text("wireless headphones", 1);
+        index.index_text("wired headset", 2);
+        let mut terms = index.fuzzy_terms(&LevenshteinAutomaton::new("wirless", 1));
+        terms.sort_unstable();
+        assert_eq!(terms, vec![("wireless", 1)]);
+    }
+
     #[test]
     fn test_sorted_index() {
         let mut index = SortedIndex::new();
@@ -433,6 +1956,62 @@ mod tests {
         assert_eq!(results.len(), 3);
     }
 
+    #[test]
+    fn test_sorted_index_delta_merge() {
+        let mut index = SortedIndex::with_capacity(0);
+
+        // out of order inserts go through the delta and get merged along the way
+        for i in 0..10_000i64 {
+            index.insert((i * 7919) % 10_000, i as RowId);
+        }
+        let id = index.search_exact(42)[0];
+        index.remove(42, id);
+        assert_eq!(index.len(), 9_999);
+
+        let results = index.search_range(40, 45);
+        let values: Vec<i64> = results.iter().map(|id| (*id as i64 * 7919) % 10_000).collect();
+        assert_eq!(values, vec![40, 41, 43, 44, 45]);
+        assert!(index.search_exact(42).is_empty());
+        assert!(index.search_range(5, 4).is_empty());
+
+        assert_eq!(index.count_range(40, 45), 5);
+
+        index.merge();
+        assert_eq!(index.search_range(40, 45), results);
+    }
+
+    #[test]
+    fn test_sorted_index_bounds() {
+        // bounds behave the same whether entries sit in the main run or the delta
+        let mut index = SortedIndex::with_capacity(0);
+        for (id, price) in [1.5, -0.0, 20.0, 9.99, 20.0].iter().enumerate() {
+            index.insert(FloatKey::new(*price).unwrap(), id as RowId);
+        }
+        for merged in [false, true] {
+            if merged {
+                index.merge();
+            }
+            let key = |f: f64| FloatKey::new(f).unwrap();
+            assert_eq!(index.search_bounds(Bound::Unbounded, Bound::Excluded(&key(20.0))), vec![1, 0, 3]);
+            assert_eq!(index.search_bounds(Bound::Excluded(&key(1.5)), Bound::Included(&key(20.0))), vec![3, 2, 4]);
+            assert_eq!(index.search_range(key(0.0), key(0.0)), vec![1]);
+            assert_eq!(index.count_bounds(Bound::Excluded(&key(9.99)), Bound::Unbounded), 2);
+            assert!(index.search_bounds(Bound::Excluded(&key(1.5)), Bound::Excluded(&key(1.5))).is_empty());
+            assert!(index.search_bounds(Bound::Included(&key(1.5)), Bound::Excluded(&key(1.5))).is_empty());
+        }
+        assert!(FloatKey::new(f64::NAN).is_none());
+
+        let mut index: SortedIndex<Box<str>> = SortedIndex::with_capacity(0);
+        for (id, date) in ["2024-03-01", "2023-12-31", "2024-01-15", "2024-02-01"].iter().enumerate() {
+            index.insert((*date).into(), id as RowId);
+        }
+        let (min, max): (Box<str>, Box<str>) = ("2024-01-01".into(), "2024-02-01".into());
+        assert_eq!(index.search_bounds(Bound::Included(&min), Bound::Excluded(&max)), vec![2]);
+        assert_eq!(index.search_bounds(Bound::Included(&min), Bound::Included(&max)), vec![2, 3]);
+        index.remove("2024-01-15".into(), 2);
+        assert_eq!(index.search_range(min, max), vec![3]);
+    }
+
     #[test]
     fn test_bloom_filter() {
         let mut bloom = BloomFilter::new(1000, 0.01);
@@ -1,6 +1,9 @@
 pub mod storage;
+pub mod analyzer;
+pub mod fuzzy;
 pub mod index;
 pub mod search;
+pub mod aggregate;
 pub mod http;
 pub mod query;
 pub mod table;
@@ -8,12 +11,17 @@ pub mod auth;
 pub mod log;
 pub mod config;
 pub mod sync;
+pub mod persist;
 
 pub use storage::*;
+pub use analyzer::*;
+pub use fuzzy::*;
 pub use index::*;
 pub use search::*;
+pub use aggregate::*;
 pub use table::*;
 pub use auth::*;
 pub use log::*;
 pub use config::*;
 pub use sync::*;
+pub use persist::*;
@@ -8,7 +8,13 @@ fn main() {
     let config = Config::from_env();
     let addr = config.address();
     
-    let server = HttpServer::with_config(config);
+    let server = match HttpServer::try_with_config(config) {
+        Ok(s) => s,
+        Err(e) => {
+            eprintln!("failed to load data: {}", e);
+            std::process::exit(1);
+        }
+    };
     
     if let Err(e) = server.run(&addr) {
         eprintln!("server error: {}", e);
@@ -0,0 +1,479 @@
+// little-endian binary encoding shared by the snapshot and log formats
+
+use crate::analyzer::{Analyzer, TokenFilter};
+use crate::index::{IndexKind, IndexSet};
+use crate::storage::Value;
+use crate::table::{Alteration, Column, ColumnType};
+
+use super::PersistError;
+
+// version of the encoding below, stamped into snapshot and log headers.
+// older versions stay readable:
+//   1  columns are name + type
+//   2  columns also carry their index set
+//   3  columns also carry their analyzer's filter chain
+//   4  substring index bit. `contains` used to run on the full-text index,
+//      so older string columns with one get a substring index to keep it
+//   5  columns also carry whether they're nullable, older ones all were
+//   6  columns also carry their default value
+//   7  columns also carry whether they're part of the primary key
+//   8  bool, timestamp, uuid and array values and column types
+//   9  patch log records, a row's changed columns only
+//  10  log headers carry a segment id, snapshots the log position each
+//      table was encoded at
+pub const FORMAT_VERSION: u32 = 10;
+
+// crc32 (ieee) lookup table, built at compile time
+const CRC_TABLE: [u32; 256] = {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut c = i as u32;
+        let mut k = 0;
+        while k < 8 {
+            c = if c & 1 != 0 { 0xEDB8_8320 ^ (c >> 1) } else { c >> 1 };
+            k += 1;
+        }
+        table[i] = c;
+        i += 1;
+    }
+    table
+};
+
+// incremental crc32 so large snapshots can be checksummed while streaming
+pub struct Crc32(u32);
+
+impl Crc32 {
+    pub fn new() -> Self {
+        Self(!0)
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        let mut crc = self.0;
+        for &byte in data {
+            crc = CRC_TABLE[((crc ^ byte as u32) & 0xFF) as usize] ^ (crc >> 8);
+        }
+        self.0 = crc;
+    }
+
+    pub fn finish(&self) -> u32 {
+        !self.0
+    }
+}
+
+impl Default for Crc32 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = Crc32::new();
+    crc.update(data);
+    crc.finish()
+}
+
+pub struct Encoder {
+    buf: Vec<u8>,
+}
+
+impl Encoder {
+    pub fn new() -> Self {
+        Self { buf: Vec::with_capacity(4096) }
+    }
+
+    pub fn into_inner(self) -> Vec<u8> {
+        self.buf
+    }
+
+    pub fn len(&self) -> usize {
+        self.buf.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buf.is_empty()
+    }
+
+    // hand back what has been encoded so far and start over
+    pub fn take(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.buf)
+    }
+
+    pub fn put_u8(&mut self, v: u8) {
+        self.buf.push(v);
+    }
+
+    pub fn put_u32(&mut self, v: u32) {
+        self.buf.extend_from_slice(&v.to_le_bytes());
+    }
+
+    pub fn put_u64(&mut self, v: u64) {
+        self.buf.extend_from_slice(&v.to_le_bytes());
+    }
+
+    pub fn put_bytes(&mut self, v: &[u8]) {
+        self.put_u32(v.len() as u32);
+        self.buf.extend_from_slice(v);
+    }
+
+    pub fn put_str(&mut self, v: &str) {
+        self.put_bytes(v.as_bytes());
+    }
+
+    pub fn put_value(&mut self, value: &Value) {
+        match value {
+            Value::Null => self.put_u8(0),
+            Value::Int(i) => {
+                self.put_u8(1);
+                self.put_u64(*i as u64);
+            }
+            Value::Float(f) => {
+                self.put_u8(2);
+                self.put_u64(f.to_bits());
+            }
+            Value::String(s) => {
+                self.put_u8(3);
+                self.put_str(s);
+            }
+            Value::Bytes(b) => {
+                self.put_u8(4);
+                self.put_bytes(b);
+            }
+            Value::Bool(b) => self.put_u8(if *b { 6 } else { 5 }),
+            Value::Timestamp(t) => {
+                self.put_u8(7);
+                self.put_u64(*t as u64);
+            }
+            Value::Uuid(u) => {
+                self.put_u8(8);
+                self.buf.extend_from_slice(u);
+            }
+            Value::Array(items) => {
+                self.put_u8(9);
+                self.put_values(items);
+            }
+        }
+    }
+
+    pub fn put_values(&mut self, values: &[Value]) {
+        self.put_u32(values.len() as u32);
+        for value in values {
+            self.put_value(value);
+        }
+    }
+
+    // an array is its tag followed by its element's
+    fn put_column_type(&mut self, col_type: ColumnType) {
+        self.put_u8(match col_type {
+            ColumnType::Int => 0,
+            ColumnType::Float => 1,
+            ColumnType::String => 2,
+            ColumnType::Bytes => 3,
+            ColumnType::Bool => 4,
+            ColumnType::Timestamp => 5,
+            ColumnType::Uuid => 6,
+            ColumnType::Array(element) => {
+                self.put_u8(7);
+                return self.put_column_type(*element);
+            }
+        });
+    }
+
+    pub fn put_column(&mut self, column: &Column) {
+        self.put_str(&column.name);
+        self.put_column_type(column.col_type);
+        self.put_u8(column.indexes.bits());
+        self.put_u8(column.analyzer.filters().len() as u8);
+        for filter in column.analyzer.filters() {
+            self.put_u8(filter.tag());
+        }
+        self.put_u8(column.nullable as u8);
+        self.put_value(&column.default);
+        self.put_u8(column.primary_key as u8);
+    }
+
+    pub fn put_alteration(&mut self, alteration: &Alteration) {
+        match alteration {
+            Alteration::AddColumn(column) => {
+                self.put_u8(0);
+                self.put_column(column);
+            }
+            Alteration::DropColumn(name) => {
+                self.put_u8(1);
+                self.put_str(name);
+            }
+            Alteration::RenameColumn { from, to } => {
+                self.put_u8(2);
+                self.put_str(from);
+                self.put_str(to);
+            }
+            Alteration::Retype { column, col_type } => {
+                self.put_u8(3);
+                self.put_str(column);
+                self.put_column_type(*col_type);
+            }
+        }
+    }
+}
+
+impl Default for Encoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub struct Decoder<'a> {
+    buf: &'a [u8],
+    pos: usize,
+    version: u32,
+}
+
+impl<'a> Decoder<'a> {
+    pub fn new(buf: &'a [u8]) -> Self {
+        Self::with_version(buf, FORMAT_VERSION)
+    }
+
+    // decode data written by an older format version
+    pub fn with_version(buf: &'a [u8], version: u32) -> Self {
+        Self { buf, pos: 0, version }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pos >= self.buf.len()
+    }
+
+    fn take(&mut self, n: usize) -> Result<&'a [u8], PersistError> {
+        if self.buf.len() - self.pos < n {
+            return Err(PersistError::Corrupt("unexpected end of data".to_string()));
+        }
+        let slice = &self.buf[self.pos..self.pos + n];
+        self.pos += n;
+        Ok(slice)
+    }
+
+    pub fn get_u8(&mut self) -> Result<u8, PersistError> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub fn get_u32(&mut self) -> Result<u32, PersistError> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    pub fn get_u64(&mut self) -> Result<u64, PersistError> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    pub fn get_bytes(&mut self) -> Result<&'a [u8], PersistError> {
+        let len = self.get_u32()? as usize;
+        self.take(len)
+    }
+
+    pub fn get_str(&mut self) -> Result<&'a str, PersistError> {
+        std::str::from_utf8(self.get_bytes()?)
+            .map_err(|_| PersistError::Corrupt("invalid utf-8 in string".to_string()))
+    }
+
+    pub fn get_value(&mut self) -> Result<Value, PersistError> {
+        match self.get_u8()? {
+            0 => Ok(Value::Null),
+            1 => Ok(Value::Int(self.get_u64()? as i64)),
+            2 => Ok(Value::Float(f64::from_bits(self.get_u64()?))),
+            3 => Ok(Value::String(self.get_str()?.into())),
+            4 => Ok(Value::Bytes(self.get_bytes()?.into())),
+            5 => Ok(Value::Bool(false)),
+            6 => Ok(Value::Bool(true)),
+            7 => Ok(Value::Timestamp(self.get_u64()? as i64)),
+            8 => Ok(Value::Uuid(self.take(16)?.try_into().unwrap())),
+            9 => Ok(Value::Array(self.get_values()?.into_boxed_slice())),
+            tag => Err(PersistError::Corrupt(format!("unknown value tag {}", tag))),
+        }
+    }
+
+    pub fn get_values(&mut self) -> Result<Vec<Value>, PersistError> {
+        let count = self.get_u32()? as usize;
+        let mut values = Vec::with_capacity(count.min(4096));
+        for _ in 0..count {
+            values.push(self.get_value()?);
+        }
+        Ok(values)
+    }
+
+    fn get_column_type(&mut self) -> Result<ColumnType, PersistError> {
+        match self.get_u8()? {
+            0 => Ok(ColumnType::Int),
+            1 => Ok(ColumnType::Float),
+            2 => Ok(ColumnType::String),
+            3 => Ok(ColumnType::Bytes),
+            4 => Ok(ColumnType::Bool),
+            5 => Ok(ColumnType::Timestamp),
+            6 => Ok(ColumnType::Uuid),
+            7 => ColumnType::array_of(self.get_column_type()?)
+                .ok_or_else(|| PersistError::Corrupt("nested array column".to_string())),
+            tag => Err(PersistError::Corrupt(format!("unknown column type {}", tag))),
+        }
+    }
+
+    pub fn get_column(&mut self) -> Result<Column, PersistError> {
+        let name = self.get_str()?;
+        let col_type = self.get_column_type()?;
+
+        let column = Column::new(name, col_type);
+        if self.version < 2 {
+            return Ok(column);
+        }
+        let bits = self.get_u8()?;
+        let indexes = IndexSet::from_bits(bits)
+            .ok_or_else(|| PersistError::Corrupt(format!("unknown index bits {:#x}", bits)))?;
+        let indexes = if self.version < 4 && col_type == ColumnType::String && indexes.contains(IndexKind::FullText) {
+            indexes.with(IndexKind::Substring)
+        } else {
+            indexes
+        };
+        let column = column.with_indexes(indexes);
+        if self.version < 3 {
+            return Ok(column);
+        }
+
+        let count = self.get_u8()?;
+        let mut filters = Vec::with_capacity(count as usize);
+        for _ in 0..count {
+            let tag = self.get_u8()?;
+            filters.push(TokenFilter::from_tag(tag)
+                .ok_or_else(|| PersistError::Corrupt(format!("unknown token filter {}", tag)))?);
+        }
+        let column = column.with_analyzer(Analyzer::new(filters));
+        if self.version < 5 {
+            return Ok(column);
+        }
+        let column = column.with_nullable(self.get_u8()? != 0);
+        if self.version < 6 {
+            return Ok(column);
+        }
+        let column = column.with_default(self.get_value()?);
+        if self.version < 7 {
+            return Ok(column);
+        }
+        Ok(column.with_primary_key(self.get_u8()? != 0))
+    }
+
+    pub fn get_alteration(&mut self) -> Result<Alteration, PersistError> {
+        match self.get_u8()? {
+            0 => Ok(Alteration::AddColumn(self.get_column()?)),
+            1 => Ok(Alteration::DropColumn(self.get_str()?.into())),
+            2 => Ok(Alteration::RenameColumn { from: self.get_str()?.into(), to: self.get_str()?.into() }),
+            3 => Ok(Alteration::Retype { column: self.get_str()?.into(), col_type: self.get_column_type()? }),
+            tag => Err(PersistError::Corrupt(format!("unknown alteration {}", tag))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_crc32_known_value() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+        assert_eq!(crc32(b""), 0);
+    }
+
+    #[test]
+    fn test_value_roundtrip() {
+        let values = vec![
+            Value::Null,
+            Value::Int(-42),
+            Value::Float(1.5),
+            Value::String("héllo".into()),
+            Value::Bytes(vec![0, 1, 255].into_boxed_slice()),
+            Value::Bool(true),
+            Value::Bool(false),
+            Value::Timestamp(-1_500_000),
+            Value::Uuid([7; 16]),
+            Value::Array(vec![Value::String("a".into()), Value::Array(Box::new([]))].into_boxed_slice()),
+        ];
+
+        let mut enc = Encoder::new();
+        enc.put_values(&values);
+        let buf = enc.into_inner();
+
+        let mut dec = Decoder::new(&buf);
+        assert_eq!(dec.get_values().unwrap(), values);
+        assert!(dec.is_empty());
+    }
+
+    #[test]
+    fn test_column_roundtrip() {
+        let column = Column::new("email", ColumnType::String)
+            .with_indexes(IndexSet::NONE.with(IndexKind::Prefix))
+            .with_analyzer(Analyzer::english())
+            .with_default(Value::String("nobody@example.com".into()))
+            .with_primary_key(true);
+        let mut enc = Encoder::new();
+        enc.put_column(&column);
+        let buf = enc.into_inner();
+
+        let decoded = Decoder::new(&buf).get_column().unwrap();
+        assert_eq!(&*decoded.name, "email");
+        assert_eq!(decoded.indexes, column.indexes);
+        assert_eq!(decoded.analyzer, Analyzer::english());
+        assert!(!decoded.nullable);
+        assert_eq!(decoded.default, column.default);
+        assert!(decoded.primary_key);
+
+        // version 6 had no primary keys
+        let v6 = &buf[..buf.len() - 1];
+        let mut dec = Decoder::with_version(v6, 6);
+        assert!(!dec.get_column().unwrap().primary_key);
+        assert!(dec.is_empty());
+
+        // version 5 had no default, those columns default to null
+        let mut enc = Encoder::new();
+        enc.put_value(&column.default);
+        let v5 = &v6[..v6.len() - enc.into_inner().len()];
+        let mut dec = Decoder::with_version(v5, 5);
+        assert_eq!(dec.get_column().unwrap().default, Value::Null);
+        assert!(dec.is_empty());
+
+        // version 4 had no nullable flag, every column took nulls
+        let mut dec = Decoder::with_version(&v5[..v5.len() - 1], 4);
+        assert!(dec.get_column().unwrap().nullable);
+        assert!(dec.is_empty());
+
+        // version 2 had no analyzer, those columns get the default
+        let v2 = &v5[..v5.len() - 6];
+        let mut dec = Decoder::with_version(v2, 2);
+        assert_eq!(dec.get_column().unwrap().analyzer, Analyzer::default());
+        assert!(dec.is_empty());
+
+        // before version 4 full-text columns served `contains` too
+        let old = Column::new("bio", ColumnType::String).with_indexes(IndexSet::NONE.with(IndexKind::FullText));
+        let mut enc = Encoder::new();
+        enc.put_column(&old);
+        let buf3 = enc.into_inner();
+        let decoded = Decoder::with_version(&buf3, 3).get_column().unwrap();
+        assert_eq!(decoded.indexes, old.indexes.with(IndexKind::Substring));
+        assert_eq!(Decoder::new(&buf3).get_column().unwrap().indexes, old.indexes);
+
+        // arrays carry their element type
+        let tags = Column::new("tags", ColumnType::array_of(ColumnType::Uuid).unwrap());
+        let mut enc = Encoder::new();
+        enc.put_column(&tags);
+        let decoded = Decoder::new(&enc.into_inner()).get_column().unwrap();
+        assert_eq!(decoded.col_type, ColumnType::Array(&ColumnType::Uuid));
+
+        // version 1 had no index set either
+        let mut dec = Decoder::with_version(&v2[..v2.len() - 1], 1);
+        assert_eq!(dec.get_column().unwrap().indexes, ColumnType::String.default_indexes());
+        assert!(dec.is_empty());
+    }
+
+    #[test]
+    fn test_truncated_input() {
+        let mut enc = Encoder::new();
+        enc.put_str("truncated");
+        let buf = enc.into_inner();
+
+        let mut dec = Decoder::new(&buf[..6]);
+        assert!(matches!(dec.get_str(), Err(PersistError::Corrupt(_))));
+    }
+}
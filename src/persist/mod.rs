@@ -0,0 +1,501 @@
+// persist module - keeps data around across restarts
+//
+// architecture:
+//   codec.rs     -> binary encoding of values, columns and checksums
+//   snapshot.rs  -> versioned, checksummed full dumps of the database
+//   wal.rs       -> write-ahead log of every write since the last snapshot
+//   Persistence  -> owns the data directory, loads snapshot + log at boot,
+//                   snapshots on demand and on a timer, retiring the log
+//                   segment each snapshot covers
+
+mod codec;
+mod snapshot;
+mod wal;
+
+pub use codec::{crc32, Crc32, Decoder, Encoder, FORMAT_VERSION};
+pub use snapshot::{
+    decode_snapshot, read_snapshot, write_locked, write_snapshot, write_tables, Snapshot, SnapshotInfo, SNAPSHOT_VERSION,
+};
+pub use wal::{retired_path, FsyncPolicy, Wal, WalEntry, WalPosition, WalRecord, WAL_VERSION};
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, OnceLock, RwLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::table::{Database, Table};
+use crate::{log_error, log_info, log_warn};
+
+pub const SNAPSHOT_FILE: &str = "quickset.snapshot";
+pub const WAL_FILE: &str = "quickset.wal";
+
+// error type for persistence operations
+#[derive(Debug)]
+pub enum PersistError {
+    Io(std::io::Error),
+    Corrupt(String),
+    UnsupportedVersion(u32),
+}
+
+impl std::fmt::Display for PersistError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "io error: {}", e),
+            Self::Corrupt(s) => write!(f, "corrupt data: {}", s),
+            Self::UnsupportedVersion(v) => write!(f, "unsupported format version {}", v),
+        }
+    }
+}
+
+impl From<std::io::Error> for PersistError {
+    fn from(e: std::io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+pub struct Persistence {
+    data_dir: PathBuf,
+    snapshot_interval: Duration,
+    wal_policy: Option<FsyncPolicy>,    // none = no write-ahead log
+    wal: OnceLock<Arc<Wal>>,            // opened by load()
+    snapshot_lock: Mutex<()>,           // one snapshot at a time
+    running: AtomicBool,
+}
+
+impl Persistence {
+    // open (and create if needed) the data directory
+    pub fn open(
+        data_dir: &str,
+        snapshot_interval_secs: u64,
+        wal_policy: Option<FsyncPolicy>,
+    ) -> Result<Self, PersistError> {
+        std::fs::create_dir_all(data_dir)?;
+        Ok(Self {
+            data_dir: PathBuf::from(data_dir),
+            snapshot_interval: Duration::from_secs(snapshot_interval_secs),
+            wal_policy,
+            wal: OnceLock::new(),
+            snapshot_lock: Mutex::new(()),
+            running: AtomicBool::new(false),
+        })
+    }
+
+    pub fn data_dir(&self) -> &Path {
+        &self.data_dir
+    }
+
+    pub fn snapshot_path(&self) -> PathBuf {
+        self.data_dir.join(SNAPSHOT_FILE)
+    }
+
+    pub fn wal_path(&self) -> PathBuf {
+        self.data_dir.join(WAL_FILE)
+    }
+
+    pub fn wal(&self) -> Option<&Arc<Wal>> {
+        self.wal.get()
+    }
+
+    // load the last snapshot, replay the log on top and attach it to the result
+    pub fn load(&self) -> Result<Database, PersistError> {
+        let Snapshot { mut db, log_segment, log_positions } = self.load_snapshot()?;
+
+        if let Some(policy) = self.wal_policy {
+            let start = Instant::now();
+            let mut covered = Covered { segment: log_segment, positions: log_positions };
+            let mut apply = |at: WalPosition, entry: WalEntry| {
+                if !covered.covers(at, &entry) {
+                    db.apply_wal_entry(entry);
+                }
+            };
+
+            // a segment retired by a snapshot that never finished comes first
+            let path = self.wal_path();
+            let retired = Wal::replay_retired(&path, &mut apply)?;
+            let fresh = log_segment.max(retired.map_or(0, |(segment, _)| segment)) + 1;
+            let (wal, replayed) = Wal::open(&path, policy, fresh, &mut apply)?;
+            let replayed = replayed + retired.map_or(0, |(_, count)| count);
+            if replayed > 0 {
+                log_info!("persist", "replayed {} log records in {}ms", replayed, start.elapsed().as_millis());
+            }
+
+            // records from an older format are folded into a fresh snapshot
+            // so the log can restart at the current version
+            if wal.version() != WAL_VERSION {
+                log_info!("persist", "upgrading log from format version {}", wal.version());
+                wal.rotate()?;
+                self.write(&db.tables(), Some(&wal))?;
+            }
+
+            let wal = Arc::new(wal);
+            db.set_wal(Arc::clone(&wal));
+            let _ = self.wal.set(wal);
+        }
+
+        Ok(db)
+    }
+
+    // the last snapshot, or an empty database on first boot
+    fn load_snapshot(&self) -> Result<Snapshot, PersistError> {
+        let start = Instant::now();
+        match read_snapshot(&self.snapshot_path())? {
+            Some(snapshot) => {
+                let rows: usize = snapshot.db.stats().iter().map(|s| s.row_count).sum();
+                log_info!(
+                    "persist",
+                    "loaded snapshot: {} tables, {} rows in {}ms",
+                    snapshot.db.table_names().len(),
+                    rows,
+                    start.elapsed().as_millis()
+                );
+                Ok(snapshot)
+            }
+            None => {
+                log_info!("persist", "no snapshot found in {}, starting empty", self.data_dir.display());
+                Ok(Snapshot { db: Database::new(), log_segment: 0, log_positions: HashMap::new() })
+            }
+        }
+    }
+
+    // write a snapshot without holding writers up. the catalog is only locked
+    // while the log rotates to a new segment, then each table is read locked
+    // just long enough to encode it. the retired segment is deleted once the
+    // snapshot is renamed into place
+    pub fn snapshot(&self, db: &RwLock<Database>) -> Result<SnapshotInfo, PersistError> {
+        let _guard = self.snapshot_lock.lock().unwrap();
+        let start = Instant::now();
+
+        let (tables, wal) = {
+            let db = db.read().unwrap();
+            if let Some(wal) = db.wal() {
+                wal.rotate()?;
+            }
+            (db.tables(), db.wal().cloned())
+        };
+        let info = self.write(&tables, wal.as_deref())?;
+
+        log_info!(
+            "persist",
+            "snapshot written: {} tables, {} rows, {} bytes in {}ms",
+            info.tables,
+            info.rows,
+            info.bytes,
+            start.elapsed().as_millis()
+        );
+        Ok(info)
+    }
+
+    fn write(&self, tables: &[Arc<RwLock<Table>>], wal: Option<&Wal>) -> Result<SnapshotInfo, PersistError> {
+        let info = write_locked(tables, wal, &self.snapshot_path())?;
+        if let Some(wal) = wal {
+            wal.remove_retired()?;
+        }
+        Ok(info)
+    }
+
+    // start periodic snapshots, plus the log flusher for interval fsync
+    pub fn start_background(self: Arc<Self>, db: Arc<RwLock<Database>>) {
+        if let Some(FsyncPolicy::Interval(interval)) = self.wal_policy {
+            let persist = Arc::clone(&self);
+            thread::spawn(move || loop {
+                thread::sleep(interval);
+                if let Some(wal) = persist.wal() {
+                    if let Err(e) = wal.sync() {
+                        log_error!("persist", "log fsync failed: {}", e);
+                    }
+                }
+            });
+        }
+
+        if self.snapshot_interval.is_zero() {
+            log_info!("persist", "periodic snapshots disabled (interval = 0)");
+            return;
+        }
+
+        if self.running.swap(true, Ordering::SeqCst) {
+            log_warn!("persist", "periodic snapshots already running");
+            return;
+        }
+
+        log_info!("persist", "snapshotting every {}s", self.snapshot_interval.as_secs());
+
+        thread::spawn(move || {
+            while self.running.load(Ordering::Relaxed) {
+                thread::sleep(self.snapshot_interval);
+
+                if !self.running.load(Ordering::Relaxed) {
+                    break;
+                }
+
+                if let Err(e) = self.snapshot(&db) {
+                    log_error!("persist", "periodic snapshot failed: {}", e);
+                }
+            }
+        });
+    }
+
+    // stop periodic snapshots
+    pub fn stop(&self) {
+        self.running.store(false, Ordering::SeqCst);
+    }
+}
+
+// which replayed records the snapshot already holds: every segment before its
+// own, and in its own each table's records before the position it was
+// encoded at
+struct Covered {
+    segment: u64,
+    positions: HashMap<Box<str>, u64>,
+}
+
+impl Covered {
+    fn covers(&mut self, at: WalPosition, entry: &WalEntry) -> bool {
+        if at.segment != self.segment {
+            return at.segment < self.segment;
+        }
+        match entry {
+            // the catalog was read as the segment started, so creates and
+            // drops in it all came later. records for the name from here on
+            // belong to a table the snapshot never saw
+            WalEntry::CreateTable { name, .. } | WalEntry::DropTable { name } => {
+                self.positions.remove(name.as_str());
+                false
+            }
+            _ => self.positions.get(entry.table()).is_some_and(|&position| at.record < position),
+        }
+    }
+}
+
+// fresh scratch directory for tests
+#[cfg(test)]
+pub(crate) fn test_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("quickset_test_{}_{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::Value;
+    use crate::table::{Alteration, Coercion, Column, ColumnType, TableError};
+
+    #[test]
+    fn test_persistence_load_empty_then_snapshot() {
+        let dir = test_dir("persistence");
+        let persist = Persistence::open(dir.to_str().unwrap(), 0, None).unwrap();
+
+        let db = RwLock::new(persist.load().unwrap());
+        assert!(db.read().unwrap().table_names().is_empty());
+
+        {
+            let mut db = db.write().unwrap();
+            db.create_table("t", vec![Column::new("v", ColumnType::Int)]).unwrap();
+            db.get_table_mut("t").unwrap().insert(vec![Value::Int(7)], Coercion::Strict).unwrap();
+        }
+        persist.snapshot(&db).unwrap();
+
+        let reloaded = persist.load().unwrap();
+        assert_eq!(reloaded.get_table("t").unwrap().search_exact(0, &Value::Int(7)), vec![1]);
+    }
+
+    #[test]
+    fn test_log_replayed_on_top_of_snapshot() {
+        let dir = test_dir("persistence_wal");
+        let open = || Persistence::open(dir.to_str().unwrap(), 0, Some(FsyncPolicy::Always)).unwrap();
+
+        {
+            let persist = open();
+            let db = RwLock::new(persist.load().unwrap());
+            let mut guard = db.write().unwrap();
+            guard.create_table("t", vec![Column::new("v", ColumnType::Int)]).unwrap();
+            let mut t = guard.get_table_mut("t").unwrap();
+            t.insert(vec![Value::Int(1)], Coercion::Strict).unwrap();
+            t.insert(vec![Value::Int(2)], Coercion::Strict).unwrap();
+            drop(t);
+            drop(guard);
+
+            persist.snapshot(&db).unwrap();
+            assert!(persist.wal().unwrap().is_empty());
+
+            // everything after the snapshot only lives in the log
+            let mut guard = db.write().unwrap();
+            let mut t = guard.get_table_mut("t").unwrap();
+            t.update(1, vec![Value::Int(9)], Coercion::Strict).unwrap();
+            t.patch(1, vec![(0, Value::Int(10))], Coercion::Strict).unwrap();
+            t.delete(2).unwrap();
+            t.insert_batch(vec![vec![Value::Int(3)], vec![Value::Int(4)]], Coercion::Strict);
+            t.update_many(&[3], vec![(0, Value::Int(30))], Coercion::Strict).unwrap();
+            drop(t);
+            guard.create_table("late", vec![Column::new("s", ColumnType::String)]).unwrap();
+            guard.get_table_mut("late").unwrap().insert(vec![Value::String("x".into())], Coercion::Strict).unwrap();
+            guard.drop_table("late").unwrap();
+            guard.create_table("late", vec![Column::new("s", ColumnType::String)]).unwrap();
+
+            let add = Alteration::AddColumn(Column::new("n", ColumnType::String).with_default(Value::String("7".into())));
+            guard.alter_table("t", &add).unwrap();
+            guard.alter_table("t", &Alteration::Retype { column: "n".into(), col_type: ColumnType::Int }).unwrap();
+            guard.alter_table("t", &Alteration::RenameColumn { from: "n".into(), to: "seven".into() }).unwrap();
+        }
+
+        // "crash": nothing flushed besides the log
+        let db = open().load().unwrap();
+        let t = db.get_table("t").unwrap();
+        assert_eq!(t.len(), 3);
+        assert_eq!(t.get(1).unwrap()[0], Value::Int(10));
+        assert!(t.get(2).is_none());
+        assert_eq!(t.search_exact(0, &Value::Int(4)), vec![4]);
+        assert_eq!(t.search_exact(0, &Value::Int(30)), vec![3]);
+        assert_eq!(t.next_id(), 5);
+        assert_eq!(&*t.columns()[1].name, "seven");
+        assert_eq!(t.search_range(1, 7, 7).len(), 3);
+        drop(t);
+        assert!(db.get_table("late").unwrap().is_empty());
+
+        // replayed state keeps logging
+        let db = RwLock::new(db);
+        db.write().unwrap().get_table_mut("t").unwrap().insert(vec![Value::Int(5), Value::Int(0)], Coercion::Strict).unwrap();
+        drop(db);
+        let db = open().load().unwrap();
+        assert_eq!(db.get_table("t").unwrap().search_exact(0, &Value::Int(5)), vec![5]);
+    }
+
+    #[test]
+    fn test_writes_during_snapshot() {
+        let dir = test_dir("persistence_segments");
+        let open = || Persistence::open(dir.to_str().unwrap(), 0, Some(FsyncPolicy::Always)).unwrap();
+
+        {
+            let persist = open();
+            let db = RwLock::new(persist.load().unwrap());
+            let mut guard = db.write().unwrap();
+            guard.create_table("t", vec![
+                Column::new("v", ColumnType::Int),
+                Column::new("x", ColumnType::String),
+            ]).unwrap();
+            guard.get_table_mut("t").unwrap().insert(vec![Value::Int(1), Value::String("a".into())], Coercion::Strict).unwrap();
+            drop(guard);
+
+            // what a snapshot does, with writes landing between the rotation
+            // and the table being encoded. replaying them on top again would
+            // swap the names back and clear the new v column
+            let wal = Arc::clone(persist.wal().unwrap());
+            assert!(wal.rotate().unwrap());
+            let guard = db.read().unwrap();
+            let rename = |from: &str, to: &str| Alteration::RenameColumn { from: from.into(), to: to.into() };
+            guard.alter_table("t", &rename("v", "tmp")).unwrap();
+            guard.alter_table("t", &rename("x", "v")).unwrap();
+            guard.alter_table("t", &rename("tmp", "x")).unwrap();
+            guard.alter_table("t", &Alteration::DropColumn("v".into())).unwrap();
+            let v = Column::new("v", ColumnType::Int).with_default(Value::Int(0));
+            guard.alter_table("t", &Alteration::AddColumn(v)).unwrap();
+            guard.get_table_mut("t").unwrap().insert(vec![Value::Int(2), Value::Int(5)], Coercion::Strict).unwrap();
+            let tables = guard.tables();
+            drop(guard);
+            write_locked(&tables, Some(&wal), &persist.snapshot_path()).unwrap();
+
+            // after the encoding writes only live in the log, and the crash
+            // left the retired segment behind
+            db.read().unwrap().get_table_mut("t").unwrap().insert(vec![Value::Int(3), Value::Int(6)], Coercion::Strict).unwrap();
+            assert!(retired_path(&persist.wal_path()).exists());
+        }
+
+        let check = |db: &Database| {
+            let t = db.get_table("t").unwrap();
+            assert_eq!(t.len(), 3);
+            let names: Vec<&str> = t.columns().iter().map(|c| &*c.name).collect();
+            assert_eq!(names, ["x", "v"]);
+            assert_eq!(t.columns()[1].col_type, ColumnType::Int);
+            assert_eq!(t.get(1).unwrap(), &[Value::Int(1), Value::Int(0)]);
+            assert_eq!(t.get(2).unwrap(), &[Value::Int(2), Value::Int(5)]);
+            assert_eq!(t.get(3).unwrap(), &[Value::Int(3), Value::Int(6)]);
+        };
+
+        // the retired segment is still there after a reload, until a
+        // snapshot finishes, and writes keep going to the current one
+        let persist = open();
+        let db = RwLock::new(persist.load().unwrap());
+        check(&db.read().unwrap());
+        assert!(!persist.wal().unwrap().rotate().unwrap());
+        db.read().unwrap().get_table_mut("t").unwrap().update(3, vec![Value::Int(3), Value::Int(6)], Coercion::Strict).unwrap();
+        drop(db);
+        check(&open().load().unwrap());
+
+        let persist = open();
+        let db = RwLock::new(persist.load().unwrap());
+        persist.snapshot(&db).unwrap();
+        assert!(!retired_path(&persist.wal_path()).exists());
+        check(&open().load().unwrap());
+    }
+
+    #[test]
+    fn test_old_log_version_upgraded() {
+        let dir = test_dir("persistence_upgrade");
+
+        // a version 1 log holding one create table, columns without index bits
+        let mut enc = Encoder::new();
+        enc.put_u8(1);
+        enc.put_str("t");
+        enc.put_u32(1);
+        enc.put_str("v");
+        enc.put_u8(0);
+        let payload = enc.into_inner();
+
+        let mut bytes = b"QSWALLOG".to_vec();
+        bytes.extend_from_slice(&1u32.to_le_bytes());
+        bytes.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&crc32(&payload).to_le_bytes());
+        bytes.extend_from_slice(&payload);
+        std::fs::write(dir.join(WAL_FILE), bytes).unwrap();
+
+        let persist = Persistence::open(dir.to_str().unwrap(), 0, Some(FsyncPolicy::Always)).unwrap();
+        let db = persist.load().unwrap();
+        assert_eq!(db.get_table("t").unwrap().columns()[0].indexes, ColumnType::Int.default_indexes());
+        assert_eq!(persist.wal().unwrap().version(), WAL_VERSION);
+        assert!(persist.wal().unwrap().is_empty());
+        assert!(persist.snapshot_path().exists());
+
+        db.get_table_mut("t").unwrap().insert(vec![Value::Int(1)], Coercion::Strict).unwrap();
+        let db = Persistence::open(dir.to_str().unwrap(), 0, Some(FsyncPolicy::Always)).unwrap().load().unwrap();
+        assert_eq!(db.get_table("t").unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_failed_append_leaves_table_alone() {
+        let dir = test_dir("persistence_failed_append");
+
+        // appends to a log still holding an older version's records are refused
+        let path = dir.join(WAL_FILE);
+        let mut bytes = b"QSWALLOG".to_vec();
+        bytes.extend_from_slice(&1u32.to_le_bytes());
+        std::fs::write(&path, bytes).unwrap();
+        let (wal, _) = Wal::open(&path, FsyncPolicy::Always, 1, |_, _| {}).unwrap();
+
+        let mut table = Table::new("t", vec![
+            Column::new("id", ColumnType::Int).with_primary_key(true),
+            Column::new("v", ColumnType::String),
+        ]);
+        let id = table.insert(vec![Value::Int(1), Value::String("a".into())], Coercion::Strict).unwrap();
+        table.set_wal(Some(Arc::new(wal)));
+
+        assert_eq!(table.insert(vec![Value::Int(2), Value::String("b".into())], Coercion::Strict), Err(TableError::Log));
+        assert_eq!(table.insert_batch(vec![vec![Value::Int(2), Value::String("b".into())]], Coercion::Strict), vec![Err(TableError::Log)]);
+        assert_eq!(table.upsert(vec![Value::Int(1), Value::String("b".into())], Coercion::Strict), Err(TableError::Log));
+        assert_eq!(table.update(id, vec![Value::Int(1), Value::String("b".into())], Coercion::Strict), Err(TableError::Log));
+        assert_eq!(table.patch(id, vec![(1, Value::String("b".into()))], Coercion::Strict), Err(TableError::Log));
+        assert_eq!(table.update_many(&[id], vec![(1, Value::String("b".into()))], Coercion::Strict), Err(TableError::Log));
+        assert_eq!(table.alter(&Alteration::DropColumn("v".into())), Err(TableError::Log));
+        assert_eq!(table.delete(id), Err(TableError::Log));
+        assert_eq!(table.delete_many(&[id]), Err(TableError::Log));
+
+        assert_eq!(table.len(), 1);
+        assert_eq!(table.columns().len(), 2);
+        assert_eq!(table.get(id).unwrap(), &[Value::Int(1), Value::String("a".into())]);
+        assert_eq!(table.search_exact(1, &Value::String("a".into())), vec![id]);
+        assert!(table.search_exact(1, &Value::String("b".into())).is_empty());
+        assert_eq!(table.insert(vec![Value::Int(2), Value::String("b".into())], Coercion::Strict), Err(TableError::Log));
+    }
+}
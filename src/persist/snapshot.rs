@@ -0,0 +1,354 @@
+// snapshot file format
+//
+//   magic    8 bytes  "QSNAPSHT"
+//   version  u32
+//   crc32    u32      checksum of the payload
+//   length   u64      payload length in bytes
+//   payload:
+//     log segment u64, table count u32, then per table:
+//       name, log position u64, column count u32, columns (name + type tag +
+//       index bits + analyzer filters + nullable flag + default value + key
+//       flag), next_id u64, row count u64, rows (id u64 + values)
+//
+// the payload is streamed to a temp file and renamed into place so a crash
+// mid-write never clobbers the previous snapshot.
+//
+// tables are encoded one at a time while writers carry on, so each records
+// how many records of the log segment were appended when it was encoded.
+// replay skips that table's records before the position; earlier segments
+// are covered whole
+
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+
+use crate::storage::RowId;
+use crate::table::{Database, Table};
+
+use super::codec::{Crc32, Decoder, Encoder, FORMAT_VERSION};
+use super::{PersistError, Wal};
+
+pub const SNAPSHOT_MAGIC: &[u8; 8] = b"QSNAPSHT";
+pub const SNAPSHOT_VERSION: u32 = FORMAT_VERSION;
+
+const HEADER_LEN: usize = 8 + 4 + 4 + 8;
+const FLUSH_THRESHOLD: usize = 1 << 20;
+
+// first version carrying log positions
+const POSITION_VERSION: u32 = 10;
+
+// what a snapshot wrote, reported back to the admin endpoint
+#[derive(Debug, Clone)]
+pub struct SnapshotInfo {
+    pub tables: usize,
+    pub rows: usize,
+    pub bytes: u64,
+}
+
+// a decoded snapshot and where in the log it leaves off
+pub struct Snapshot {
+    pub db: Database,
+    pub log_segment: u64,                      // 0 = written without a log
+    pub log_positions: HashMap<Box<str>, u64>, // per table, records of that segment it holds
+}
+
+// streams encoded chunks to disk while keeping a running checksum
+struct PayloadWriter {
+    out: BufWriter<File>,
+    crc: Crc32,
+    len: u64,
+}
+
+impl PayloadWriter {
+    fn write(&mut self, chunk: &[u8]) -> Result<(), PersistError> {
+        self.crc.update(chunk);
+        self.len += chunk.len() as u64;
+        self.out.write_all(chunk)?;
+        Ok(())
+    }
+
+    fn flush_if_full(&mut self, enc: &mut Encoder) -> Result<(), PersistError> {
+        if enc.len() >= FLUSH_THRESHOLD {
+            self.write(&enc.take())?;
+        }
+        Ok(())
+    }
+}
+
+fn encode_table(table: &Table, position: u64, enc: &mut Encoder, out: &mut PayloadWriter) -> Result<usize, PersistError> {
+    enc.put_str(table.name());
+    enc.put_u64(position);
+    enc.put_u32(table.columns().len() as u32);
+    for column in table.columns() {
+        enc.put_column(column);
+    }
+    enc.put_u64(table.next_id());
+
+    // write rows in id order so identical data always produces identical files
+    let mut ids: Vec<RowId> = table.rows().map(|(id, _)| id).collect();
+    ids.sort_unstable();
+
+    enc.put_u64(ids.len() as u64);
+    for id in &ids {
+        if let Some(values) = table.get(*id) {
+            enc.put_u64(*id);
+            enc.put_values(values);
+        }
+        out.flush_if_full(enc)?;
+    }
+    Ok(ids.len())
+}
+
+// write a snapshot of every table to `path`, read locking all of them meanwhile
+pub fn write_snapshot(db: &Database, path: &Path) -> Result<SnapshotInfo, PersistError> {
+    let guards = db.read_all();
+    let tables: Vec<&Table> = guards.iter().map(|t| &**t).collect();
+    write_tables(&tables, path)
+}
+
+// write a snapshot of the given tables to `path`. the caller holds their locks
+pub fn write_tables(tables: &[&Table], path: &Path) -> Result<SnapshotInfo, PersistError> {
+    write_with(path, 0, tables.len(), |enc, out| {
+        let mut rows = 0;
+        for table in tables {
+            rows += encode_table(table, 0, enc, out)?;
+        }
+        Ok(rows)
+    })
+}
+
+// write a snapshot of the given tables to `path`, read locking each one only
+// while it's encoded. with a log, each table notes how far into the current
+// segment it had got; the caller keeps the log from rotating meanwhile
+pub fn write_locked(tables: &[Arc<RwLock<Table>>], log: Option<&Wal>, path: &Path) -> Result<SnapshotInfo, PersistError> {
+    let segment = log.map_or(0, |wal| wal.segment());
+    write_with(path, segment, tables.len(), |enc, out| {
+        let mut rows = 0;
+        for table in tables {
+            let table = table.read().unwrap();
+            // writes are logged under the table's write lock, so none of its
+            // records can be in flight while we hold the read lock
+            let position = log.map_or(0, |wal| wal.records());
+            rows += encode_table(&table, position, enc, out)?;
+        }
+        Ok(rows)
+    })
+}
+
+fn write_with(
+    path: &Path,
+    segment: u64,
+    table_count: usize,
+    encode: impl FnOnce(&mut Encoder, &mut PayloadWriter) -> Result<usize, PersistError>,
+) -> Result<SnapshotInfo, PersistError> {
+    let tmp_path = path.with_extension("tmp");
+    let file = File::create(&tmp_path)?;
+
+    let mut out = PayloadWriter {
+        out: BufWriter::with_capacity(FLUSH_THRESHOLD, file),
+        crc: Crc32::new(),
+        len: 0,
+    };
+
+    // header is patched once the payload checksum is known
+    out.out.write_all(&[0u8; HEADER_LEN])?;
+
+    let mut enc = Encoder::new();
+    enc.put_u64(segment);
+    enc.put_u32(table_count as u32);
+    let rows = encode(&mut enc, &mut out)?;
+    out.write(&enc.take())?;
+
+    let mut header = Vec::with_capacity(HEADER_LEN);
+    header.extend_from_slice(SNAPSHOT_MAGIC);
+    header.extend_from_slice(&SNAPSHOT_VERSION.to_le_bytes());
+    header.extend_from_slice(&out.crc.finish().to_le_bytes());
+    header.extend_from_slice(&out.len.to_le_bytes());
+
+    let mut file = out.out.into_inner().map_err(|e| PersistError::Io(e.into_error()))?;
+    file.seek(SeekFrom::Start(0))?;
+    file.write_all(&header)?;
+    file.sync_all()?;
+    drop(file);
+
+    fs::rename(&tmp_path, path)?;
+    if let Some(dir) = path.parent() {
+        // make the rename itself durable
+        if let Ok(dir) = File::open(dir) {
+            let _ = dir.sync_all();
+        }
+    }
+
+    Ok(SnapshotInfo {
+        tables: table_count,
+        rows,
+        bytes: HEADER_LEN as u64 + out.len,
+    })
+}
+
+// decode a full snapshot file, verifying magic, version and checksum first
+pub fn decode_snapshot(bytes: &[u8]) -> Result<Snapshot, PersistError> {
+    if bytes.len() < HEADER_LEN || &bytes[..8] != SNAPSHOT_MAGIC {
+        return Err(PersistError::Corrupt("not a quickset snapshot".to_string()));
+    }
+
+    let mut header = Decoder::new(&bytes[8..HEADER_LEN]);
+    let version = header.get_u32()?;
+    if version == 0 || version > SNAPSHOT_VERSION {
+        return Err(PersistError::UnsupportedVersion(version));
+    }
+    let checksum = header.get_u32()?;
+    let len = header.get_u64()? as usize;
+
+    let payload = &bytes[HEADER_LEN..];
+    if payload.len() != len {
+        return Err(PersistError::Corrupt(format!(
+            "payload length mismatch: header says {} bytes, found {}",
+            len,
+            payload.len()
+        )));
+    }
+
+    let mut crc = Crc32::new();
+    crc.update(payload);
+    if crc.finish() != checksum {
+        return Err(PersistError::Corrupt("checksum mismatch".to_string()));
+    }
+
+    let mut dec = Decoder::with_version(payload, version);
+    let mut db = Database::new();
+    let mut log_positions = HashMap::new();
+
+    let log_segment = if version < POSITION_VERSION { 0 } else { dec.get_u64()? };
+    let table_count = dec.get_u32()?;
+    for _ in 0..table_count {
+        let name = dec.get_str()?.to_string();
+        if version >= POSITION_VERSION {
+            log_positions.insert(name.clone().into_boxed_str(), dec.get_u64()?);
+        }
+        let column_count = dec.get_u32()?;
+        let mut columns = Vec::with_capacity(column_count as usize);
+        for _ in 0..column_count {
+            columns.push(dec.get_column()?);
+        }
+        let next_id = dec.get_u64()?;
+
+        let row_count = dec.get_u64()? as usize;
+        let mut rows = Vec::with_capacity(row_count.min(1 << 24));
+        for _ in 0..row_count {
+            let id = dec.get_u64()?;
+            let values = dec.get_values()?;
+            if values.len() != columns.len() {
+                return Err(PersistError::Corrupt(format!(
+                    "row {} in table {} has {} values, expected {}",
+                    id,
+                    name,
+                    values.len(),
+                    columns.len()
+                )));
+            }
+            rows.push((id, values));
+        }
+
+        db.insert_table(Table::restore(&name, columns, rows, next_id))
+            .map_err(|e| PersistError::Corrupt(format!("table {}: {}", name, e)))?;
+    }
+
+    if !dec.is_empty() {
+        return Err(PersistError::Corrupt("trailing data after last table".to_string()));
+    }
+
+    Ok(Snapshot { db, log_segment, log_positions })
+}
+
+// load the snapshot at `path`, `None` if there isn't one yet
+pub fn read_snapshot(path: &Path) -> Result<Option<Snapshot>, PersistError> {
+    match fs::read(path) {
+        Ok(bytes) => decode_snapshot(&bytes).map(Some),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::IndexSet;
+    use crate::persist::test_dir;
+    use crate::storage::Value;
+    use crate::table::{Coercion, Column, ColumnType};
+
+    fn sample_db() -> Database {
+        let mut db = Database::new();
+        db.create_table("users", vec![
+            Column::new("name", ColumnType::String),
+            Column::new("age", ColumnType::Int),
+            Column::new("score", ColumnType::Float).with_indexes(IndexSet::NONE),
+        ]).unwrap();
+
+        let mut users = db.get_table_mut("users").unwrap();
+        users.insert(vec![Value::String("alice".into()), Value::Int(30), Value::Float(1.5)], Coercion::Strict).unwrap();
+        let bob = users.insert(vec![Value::String("bob".into()), Value::Int(25), Value::Null], Coercion::Strict).unwrap();
+        users.insert(vec![Value::String("carol".into()), Value::Int(41), Value::Float(-2.0)], Coercion::Strict).unwrap();
+        users.delete(bob).unwrap();
+        drop(users);
+        db
+    }
+
+    #[test]
+    fn test_snapshot_roundtrip() {
+        let dir = test_dir("snapshot_roundtrip");
+        let path = dir.join("quickset.snapshot");
+
+        let info = write_snapshot(&sample_db(), &path).unwrap();
+        assert_eq!(info.tables, 1);
+        assert_eq!(info.rows, 2);
+
+        let db = read_snapshot(&path).unwrap().unwrap().db;
+        let users = db.get_table("users").unwrap();
+        assert_eq!(users.len(), 2);
+        assert_eq!(users.next_id(), 4);
+        assert_eq!(users.columns()[2].col_type, ColumnType::Float);
+        assert!(users.columns()[2].indexes.is_empty());
+
+        // indexes are rebuilt on load
+        let ids = users.search_exact_by_name("name", &Value::String("carol".into()));
+        assert_eq!(ids, vec![3]);
+        assert_eq!(users.get(3).unwrap()[2], Value::Float(-2.0));
+        assert!(users.search_exact_by_name("name", &Value::String("bob".into())).is_empty());
+    }
+
+    #[test]
+    fn test_missing_snapshot() {
+        let dir = test_dir("snapshot_missing");
+        assert!(read_snapshot(&dir.join("nope.snapshot")).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_corrupt_snapshot_fails() {
+        let dir = test_dir("snapshot_corrupt");
+        let path = dir.join("quickset.snapshot");
+        write_snapshot(&sample_db(), &path).unwrap();
+
+        let mut bytes = fs::read(&path).unwrap();
+        let last = bytes.len() - 1;
+        bytes[last] ^= 0xFF;
+        assert!(matches!(decode_snapshot(&bytes), Err(PersistError::Corrupt(_))));
+
+        bytes.truncate(HEADER_LEN + 3);
+        assert!(matches!(decode_snapshot(&bytes), Err(PersistError::Corrupt(_))));
+    }
+
+    #[test]
+    fn test_unsupported_version() {
+        let dir = test_dir("snapshot_version");
+        let path = dir.join("quickset.snapshot");
+        write_snapshot(&sample_db(), &path).unwrap();
+
+        let mut bytes = fs::read(&path).unwrap();
+        bytes[8..12].copy_from_slice(&99u32.to_le_bytes());
+        assert!(matches!(decode_snapshot(&bytes), Err(PersistError::UnsupportedVersion(99))));
+    }
+}
@@ -0,0 +1,666 @@
+// write-ahead log
+//
+//   header: magic "QSWALLOG" + version u32 + segment u64 (from version 10)
+//   then frames: length u32 | crc32 u32 | record
+//
+// every write is appended here before it's applied in memory, and so before
+// the client gets an answer. on boot the log is replayed on top of the last
+// snapshot. a snapshot starts by rotating to a new segment: the current file
+// is renamed to quickset.wal.old and deleted once the snapshot is in place.
+// a torn frame at the tail (crash mid-append) ends the replay and is cut off.
+
+use std::ffi::OsString;
+use std::fs::{self, File, OpenOptions};
+use std::io::{Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::storage::{RowId, Value};
+use crate::table::{Alteration, Column};
+use crate::{log_error, log_warn};
+
+use super::codec::{crc32, Decoder, Encoder, FORMAT_VERSION};
+use super::PersistError;
+
+pub const WAL_MAGIC: &[u8; 8] = b"QSWALLOG";
+pub const WAL_VERSION: u32 = FORMAT_VERSION;
+
+const HEADER_LEN: usize = 8 + 4 + 8;
+const LEGACY_HEADER_LEN: usize = 8 + 4;
+const FRAME_HEADER_LEN: usize = 4 + 4;
+
+// first version whose header carries a segment id. older logs count as
+// segment 1, following any snapshot they could have been written next to
+const SEGMENT_VERSION: u32 = 10;
+const LEGACY_SEGMENT: u64 = 1;
+
+fn header_len(version: u32) -> usize {
+    if version < SEGMENT_VERSION {
+        LEGACY_HEADER_LEN
+    } else {
+        HEADER_LEN
+    }
+}
+
+// the segment a snapshot retired, kept next to the log until the snapshot is in place
+pub fn retired_path(path: &Path) -> PathBuf {
+    with_suffix(path, ".old")
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = OsString::from(path.as_os_str());
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+// where a replayed entry sits: its segment and how many records of that
+// segment came before it
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct WalPosition {
+    pub segment: u64,
+    pub record: u64,
+}
+
+// when appended records are forced to disk
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FsyncPolicy {
+    Always,             // fsync before every write is acknowledged
+    Interval(Duration), // fsync at most every interval, by a background flusher
+    Never,              // leave it to the os
+}
+
+impl FsyncPolicy {
+    // "always", "never" or a number of milliseconds
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(s: &str) -> Option<Self> {
+        match s.to_lowercase().as_str() {
+            "always" | "every" | "1" | "true" => Some(Self::Always),
+            "never" | "os" | "none" => Some(Self::Never),
+            other => {
+                let ms = other.trim_end_matches("ms").parse::<u64>().ok()?;
+                if ms == 0 {
+                    Some(Self::Always)
+                } else {
+                    Some(Self::Interval(Duration::from_millis(ms)))
+                }
+            }
+        }
+    }
+}
+
+// a logged operation, borrowed from the caller on the write path
+pub enum WalRecord<'a> {
+    CreateTable { name: &'a str, columns: &'a [Column] },
+    DropTable { name: &'a str },
+    Insert { table: &'a str, row_id: RowId, values: &'a [Value] },
+    Update { table: &'a str, row_id: RowId, values: &'a [Value] },
+    Delete { table: &'a str, row_id: RowId },
+    AlterTable { table: &'a str, alteration: &'a Alteration },
+    Patch { table: &'a str, row_id: RowId, changes: &'a [(usize, Value)] },
+}
+
+// a logged operation read back during replay
+#[derive(Debug)]
+pub enum WalEntry {
+    CreateTable { name: String, columns: Vec<Column> },
+    DropTable { name: String },
+    Insert { table: String, row_id: RowId, values: Vec<Value> },
+    Update { table: String, row_id: RowId, values: Vec<Value> },
+    Delete { table: String, row_id: RowId },
+    AlterTable { table: String, alteration: Alteration },
+    Patch { table: String, row_id: RowId, changes: Vec<(usize, Value)> },
+}
+
+impl WalRecord<'_> {
+    fn encode(&self, enc: &mut Encoder) {
+        match self {
+            Self::CreateTable { name, columns } => {
+                enc.put_u8(1);
+                enc.put_str(name);
+                enc.put_u32(columns.len() as u32);
+                for column in columns.iter() {
+                    enc.put_column(column);
+                }
+            }
+            Self::DropTable { name } => {
+                enc.put_u8(2);
+                enc.put_str(name);
+            }
+            Self::Insert { table, row_id, values } => {
+                enc.put_u8(3);
+                enc.put_str(table);
+                enc.put_u64(*row_id);
+                enc.put_values(values);
+            }
+            Self::Update { table, row_id, values } => {
+                enc.put_u8(4);
+                enc.put_str(table);
+                enc.put_u64(*row_id);
+                enc.put_values(values);
+            }
+            Self::Delete { table, row_id } => {
+                enc.put_u8(5);
+                enc.put_str(table);
+                enc.put_u64(*row_id);
+            }
+            Self::AlterTable { table, alteration } => {
+                enc.put_u8(6);
+                enc.put_str(table);
+                enc.put_alteration(alteration);
+            }
+            Self::Patch { table, row_id, changes } => {
+                enc.put_u8(7);
+                enc.put_str(table);
+                enc.put_u64(*row_id);
+                enc.put_u32(changes.len() as u32);
+                for (column, value) in changes.iter() {
+                    enc.put_u32(*column as u32);
+                    enc.put_value(value);
+                }
+            }
+        }
+    }
+}
+
+impl WalEntry {
+    // the table the entry creates, drops or writes to
+    pub fn table(&self) -> &str {
+        match self {
+            Self::CreateTable { name, .. } | Self::DropTable { name } => name,
+            Self::Insert { table, .. }
+            | Self::Update { table, .. }
+            | Self::Delete { table, .. }
+            | Self::AlterTable { table, .. }
+            | Self::Patch { table, .. } => table,
+        }
+    }
+
+    fn decode(dec: &mut Decoder) -> Result<Self, PersistError> {
+        let entry = match dec.get_u8()? {
+            1 => {
+                let name = dec.get_str()?.to_string();
+                let count = dec.get_u32()?;
+                let mut columns = Vec::with_capacity(count as usize);
+                for _ in 0..count {
+                    columns.push(dec.get_column()?);
+                }
+                Self::CreateTable { name, columns }
+            }
+            2 => Self::DropTable { name: dec.get_str()?.to_string() },
+            3 => Self::Insert {
+                table: dec.get_str()?.to_string(),
+                row_id: dec.get_u64()?,
+                values: dec.get_values()?,
+            },
+            4 => Self::Update {
+                table: dec.get_str()?.to_string(),
+                row_id: dec.get_u64()?,
+                values: dec.get_values()?,
+            },
+            5 => Self::Delete {
+                table: dec.get_str()?.to_string(),
+                row_id: dec.get_u64()?,
+            },
+            6 => Self::AlterTable {
+                table: dec.get_str()?.to_string(),
+                alteration: dec.get_alteration()?,
+            },
+            7 => {
+                let table = dec.get_str()?.to_string();
+                let row_id = dec.get_u64()?;
+                let count = dec.get_u32()?;
+                let mut changes = Vec::with_capacity(count.min(4096) as usize);
+                for _ in 0..count {
+                    changes.push((dec.get_u32()? as usize, dec.get_value()?));
+                }
+                Self::Patch { table, row_id, changes }
+            }
+            tag => return Err(PersistError::Corrupt(format!("unknown log record {}", tag))),
+        };
+        Ok(entry)
+    }
+}
+
+struct WalWriter {
+    file: File,
+    last_sync: Instant,
+    dirty: bool,
+    version: u32,   // format of the records already in the file
+    segment: u64,
+    records: u64,   // appended to this segment so far
+    poisoned: bool, // a failed append couldn't be cut back off, nothing more is appended
+    #[cfg(test)]
+    fail_after: Option<usize>,  // the next append writes this many bytes, then fails
+}
+
+pub struct Wal {
+    path: PathBuf,
+    policy: FsyncPolicy,
+    writer: Mutex<WalWriter>,
+}
+
+impl Wal {
+    // open the log at `path`, handing every intact entry to `apply` in order.
+    // a new log starts as segment `segment`
+    pub fn open(
+        path: &Path,
+        policy: FsyncPolicy,
+        segment: u64,
+        mut apply: impl FnMut(WalPosition, WalEntry),
+    ) -> Result<(Self, usize), PersistError> {
+        let mut file = OpenOptions::new().read(true).write(true).create(true).truncate(false).open(path)?;
+        let bytes = fs::read(path)?;
+
+        let mut replayed = 0;
+        let mut version = WAL_VERSION;
+        let mut segment = segment;
+        if bytes.is_empty() {
+            Self::write_header(&mut file, segment)?;
+        } else {
+            (version, segment) = Self::read_header(path, &bytes)?;
+
+            let (valid_len, count) = Self::replay(&bytes, version, segment, &mut apply);
+            replayed = count;
+            if valid_len < bytes.len() {
+                log_warn!(
+                    "persist",
+                    "log {} has {} bytes of torn or corrupt data after {} records, discarding them",
+                    path.display(),
+                    bytes.len() - valid_len,
+                    count
+                );
+                file.set_len(valid_len as u64)?;
+                file.sync_all()?;
+            }
+        }
+
+        file.seek(SeekFrom::End(0))?;
+
+        let wal = Self {
+            path: path.to_path_buf(),
+            policy,
+            writer: Mutex::new(WalWriter {
+                file,
+                last_sync: Instant::now(),
+                dirty: false,
+                version,
+                segment,
+                records: replayed as u64,
+                poisoned: false,
+                #[cfg(test)]
+                fail_after: None,
+            }),
+        };
+        Ok((wal, replayed))
+    }
+
+    // replay the segment a snapshot retired next to the log at `path`, if
+    // it's still there. returns its segment id and how many entries it held
+    pub fn replay_retired(
+        path: &Path,
+        mut apply: impl FnMut(WalPosition, WalEntry),
+    ) -> Result<Option<(u64, usize)>, PersistError> {
+        let path = retired_path(path);
+        let bytes = match fs::read(&path) {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+
+        let (version, segment) = Self::read_header(&path, &bytes)?;
+        let (_, count) = Self::replay(&bytes, version, segment, &mut apply);
+        Ok(Some((segment, count)))
+    }
+
+    fn read_header(path: &Path, bytes: &[u8]) -> Result<(u32, u64), PersistError> {
+        let not_a_log = || PersistError::Corrupt(format!("{} is not a quickset log", path.display()));
+        if bytes.len() < LEGACY_HEADER_LEN || &bytes[..8] != WAL_MAGIC {
+            return Err(not_a_log());
+        }
+        let version = u32::from_le_bytes(bytes[8..12].try_into().unwrap());
+        if version == 0 || version > WAL_VERSION {
+            return Err(PersistError::UnsupportedVersion(version));
+        }
+        if version < SEGMENT_VERSION {
+            return Ok((version, LEGACY_SEGMENT));
+        }
+        if bytes.len() < HEADER_LEN {
+            return Err(not_a_log());
+        }
+        Ok((version, u64::from_le_bytes(bytes[12..20].try_into().unwrap())))
+    }
+
+    // walk the frames, returning the length of the intact prefix and how many were applied
+    fn replay(
+        bytes: &[u8],
+        version: u32,
+        segment: u64,
+        apply: &mut impl FnMut(WalPosition, WalEntry),
+    ) -> (usize, usize) {
+        let mut pos = header_len(version);
+        let mut count = 0;
+
+        while bytes.len() - pos >= FRAME_HEADER_LEN {
+            let len = u32::from_le_bytes(bytes[pos..pos + 4].try_into().unwrap()) as usize;
+            let checksum = u32::from_le_bytes(bytes[pos + 4..pos + 8].try_into().unwrap());
+            let start = pos + FRAME_HEADER_LEN;
+
+            if bytes.len() - start < len {
+                break;
+            }
+            let payload = &bytes[start..start + len];
+            if crc32(payload) != checksum {
+                break;
+            }
+
+            let mut dec = Decoder::with_version(payload, version);
+            match WalEntry::decode(&mut dec) {
+                Ok(entry) if dec.is_empty() => apply(WalPosition { segment, record: count as u64 }, entry),
+                _ => break,
+            }
+
+            count += 1;
+            pos = start + len;
+        }
+
+        (pos, count)
+    }
+
+    fn write_header(file: &mut File, segment: u64) -> Result<(), PersistError> {
+        let mut header = Vec::with_capacity(HEADER_LEN);
+        header.extend_from_slice(WAL_MAGIC);
+        header.extend_from_slice(&WAL_VERSION.to_le_bytes());
+        header.extend_from_slice(&segment.to_le_bytes());
+        file.write_all(&header)?;
+        file.sync_all()?;
+        Ok(())
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    // format version of the records in the file. new records can only be
+    // appended once an older log has been snapshotted and rotated away
+    pub fn version(&self) -> u32 {
+        self.writer.lock().unwrap().version
+    }
+
+    pub fn segment(&self) -> u64 {
+        self.writer.lock().unwrap().segment
+    }
+
+    // records in the current segment, the position the next one is replayed at
+    pub fn records(&self) -> u64 {
+        self.writer.lock().unwrap().records
+    }
+
+    pub fn policy(&self) -> FsyncPolicy {
+        self.policy
+    }
+
+    // append records as one write, syncing according to the policy
+    pub fn append(&self, records: &[WalRecord<'_>]) -> Result<(), PersistError> {
+        if records.is_empty() {
+            return Ok(());
+        }
+
+        let mut buf = Vec::new();
+        let mut enc = Encoder::new();
+        for record in records {
+            record.encode(&mut enc);
+            let payload = enc.take();
+            buf.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+            buf.extend_from_slice(&crc32(&payload).to_le_bytes());
+            buf.extend_from_slice(&payload);
+        }
+
+        let mut writer = self.writer.lock().unwrap();
+        if writer.version != WAL_VERSION {
+            return Err(PersistError::UnsupportedVersion(writer.version));
+        }
+        if writer.poisoned {
+            return Err(PersistError::Corrupt(format!("{} has a failed append that couldn't be undone", self.path.display())));
+        }
+
+        // a failed write or sync is cut back off, so the half written frame
+        // can't hide the records after it and a write the client was told
+        // failed doesn't come back on replay
+        let start = writer.file.stream_position()?;
+        if let Err(e) = self.write_frames(&mut writer, &buf) {
+            let undone = writer.file.set_len(start).and_then(|_| writer.file.seek(SeekFrom::End(0)));
+            if let Err(undo) = undone {
+                log_error!("persist", "couldn't undo a failed append to {}, refusing further appends: {}", self.path.display(), undo);
+                writer.poisoned = true;
+            }
+            return Err(e);
+        }
+        writer.records += records.len() as u64;
+        Ok(())
+    }
+
+    fn write_frames(&self, writer: &mut WalWriter, buf: &[u8]) -> Result<(), PersistError> {
+        #[cfg(test)]
+        if let Some(n) = writer.fail_after.take() {
+            writer.file.write_all(&buf[..n.min(buf.len())])?;
+            return Err(std::io::Error::other("injected failure").into());
+        }
+
+        writer.file.write_all(buf)?;
+        writer.dirty = true;
+
+        match self.policy {
+            FsyncPolicy::Always => Self::sync_writer(writer),
+            FsyncPolicy::Interval(interval) if writer.last_sync.elapsed() >= interval => Self::sync_writer(writer),
+            _ => Ok(()),
+        }
+    }
+
+    fn sync_writer(writer: &mut WalWriter) -> Result<(), PersistError> {
+        writer.file.sync_data()?;
+        writer.dirty = false;
+        writer.last_sync = Instant::now();
+        Ok(())
+    }
+
+    // force anything appended so far to disk
+    pub fn sync(&self) -> Result<(), PersistError> {
+        let mut writer = self.writer.lock().unwrap();
+        if writer.dirty {
+            Self::sync_writer(&mut writer)?;
+        }
+        Ok(())
+    }
+
+    // start a new segment, moving the current one aside to the retired path
+    // until a snapshot covers it. a segment still retired from a snapshot
+    // that never finished stays put and the current one carries on instead,
+    // returning false
+    pub fn rotate(&self) -> Result<bool, PersistError> {
+        let mut writer = self.writer.lock().unwrap();
+        let retired = retired_path(&self.path);
+        if retired.exists() {
+            return Ok(false);
+        }
+
+        let segment = writer.segment + 1;
+        let fresh = with_suffix(&self.path, ".new");
+        let mut file = OpenOptions::new().read(true).write(true).create(true).truncate(true).open(&fresh)?;
+        Self::write_header(&mut file, segment)?;
+        if writer.dirty {
+            Self::sync_writer(&mut writer)?;
+        }
+
+        fs::rename(&self.path, &retired)?;
+        fs::rename(&fresh, &self.path)?;
+        if let Some(dir) = self.path.parent() {
+            // make the renames themselves durable
+            if let Ok(dir) = File::open(dir) {
+                let _ = dir.sync_all();
+            }
+        }
+
+        writer.file = file;
+        writer.version = WAL_VERSION;
+        writer.segment = segment;
+        writer.records = 0;
+        writer.poisoned = false;
+        writer.dirty = false;
+        writer.last_sync = Instant::now();
+        Ok(true)
+    }
+
+    // delete the retired segment once a snapshot holding it is in place
+    pub fn remove_retired(&self) -> Result<(), PersistError> {
+        match fs::remove_file(retired_path(&self.path)) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+
+    // bytes of records currently in the log
+    pub fn len(&self) -> u64 {
+        let writer = self.writer.lock().unwrap();
+        let header = header_len(writer.version) as u64;
+        writer.file.metadata().map(|m| m.len().saturating_sub(header)).unwrap_or(0)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::persist::test_dir;
+    use crate::table::ColumnType;
+
+    fn collect(path: &Path) -> (Wal, Vec<WalEntry>) {
+        let mut entries = Vec::new();
+        let (wal, _) = Wal::open(path, FsyncPolicy::Always, 1, |_, e| entries.push(e)).unwrap();
+        (wal, entries)
+    }
+
+    #[test]
+    fn test_fsync_policy_parsing() {
+        assert_eq!(FsyncPolicy::from_str("always"), Some(FsyncPolicy::Always));
+        assert_eq!(FsyncPolicy::from_str("NEVER"), Some(FsyncPolicy::Never));
+        assert_eq!(FsyncPolicy::from_str("250"), Some(FsyncPolicy::Interval(Duration::from_millis(250))));
+        assert_eq!(FsyncPolicy::from_str("100ms"), Some(FsyncPolicy::Interval(Duration::from_millis(100))));
+        assert_eq!(FsyncPolicy::from_str("sometimes"), None);
+    }
+
+    #[test]
+    fn test_append_and_replay() {
+        let dir = test_dir("wal_replay");
+        let path = dir.join("quickset.wal");
+        let columns = vec![Column::new("v", ColumnType::Int)];
+
+        {
+            let (wal, entries) = collect(&path);
+            assert!(entries.is_empty());
+            wal.append(&[
+                WalRecord::CreateTable { name: "t", columns: &columns },
+                WalRecord::Insert { table: "t", row_id: 1, values: &[Value::Int(5)] },
+            ]).unwrap();
+            wal.append(&[WalRecord::Delete { table: "t", row_id: 1 }]).unwrap();
+            wal.append(&[WalRecord::Patch { table: "t", row_id: 2, changes: &[(0, Value::Int(6))] }]).unwrap();
+        }
+
+        let (_, entries) = collect(&path);
+        assert_eq!(entries.len(), 4);
+        assert!(matches!(&entries[0], WalEntry::CreateTable { name, columns } if name == "t" && columns.len() == 1));
+        assert!(matches!(&entries[1], WalEntry::Insert { row_id: 1, values, .. } if values == &[Value::Int(5)]));
+        assert!(matches!(&entries[2], WalEntry::Delete { row_id: 1, .. }));
+        assert!(matches!(&entries[3], WalEntry::Patch { row_id: 2, changes, .. } if changes == &[(0, Value::Int(6))]));
+    }
+
+    #[test]
+    fn test_torn_tail_is_discarded() {
+        let dir = test_dir("wal_torn");
+        let path = dir.join("quickset.wal");
+
+        {
+            let (wal, _) = collect(&path);
+            wal.append(&[WalRecord::DropTable { name: "a" }]).unwrap();
+            wal.append(&[WalRecord::DropTable { name: "b" }]).unwrap();
+        }
+
+        // chop the last frame in half, as a crash mid-write would
+        let len = std::fs::metadata(&path).unwrap().len();
+        OpenOptions::new().write(true).open(&path).unwrap().set_len(len - 3).unwrap();
+
+        let (wal, entries) = collect(&path);
+        assert_eq!(entries.len(), 1);
+
+        // the log stays appendable after the torn frame is cut off
+        wal.append(&[WalRecord::DropTable { name: "c" }]).unwrap();
+        drop(wal);
+        let (_, entries) = collect(&path);
+        assert!(matches!(&entries[1], WalEntry::DropTable { name } if name == "c"));
+    }
+
+    #[test]
+    fn test_failed_append_is_undone() {
+        let dir = test_dir("wal_failed_append");
+        let path = dir.join("quickset.wal");
+
+        {
+            let (wal, _) = collect(&path);
+            wal.append(&[WalRecord::DropTable { name: "a" }]).unwrap();
+            wal.writer.lock().unwrap().fail_after = Some(5);
+            assert!(wal.append(&[WalRecord::DropTable { name: "b" }]).is_err());
+            // a whole frame written but not synced goes too
+            wal.writer.lock().unwrap().fail_after = Some(usize::MAX);
+            assert!(wal.append(&[WalRecord::DropTable { name: "b" }]).is_err());
+            wal.append(&[WalRecord::DropTable { name: "c" }]).unwrap();
+        }
+
+        // the failed record is gone and the one after it still replays
+        let (_, entries) = collect(&path);
+        assert_eq!(entries.len(), 2);
+        assert!(matches!(&entries[0], WalEntry::DropTable { name } if name == "a"));
+        assert!(matches!(&entries[1], WalEntry::DropTable { name } if name == "c"));
+    }
+
+    #[test]
+    fn test_rotate() {
+        let dir = test_dir("wal_rotate");
+        let path = dir.join("quickset.wal");
+
+        let (wal, _) = collect(&path);
+        wal.append(&[WalRecord::DropTable { name: "a" }]).unwrap();
+        assert_eq!((wal.segment(), wal.records()), (1, 1));
+        assert!(wal.rotate().unwrap());
+        assert!(wal.is_empty());
+        assert_eq!((wal.segment(), wal.records()), (2, 0));
+        wal.append(&[WalRecord::DropTable { name: "b" }]).unwrap();
+
+        // a second rotation waits for the retired segment to go
+        assert!(!wal.rotate().unwrap());
+        drop(wal);
+
+        let mut seen = Vec::new();
+        let retired = Wal::replay_retired(&path, |at, e| seen.push((at, e.table().to_string()))).unwrap();
+        assert_eq!(retired, Some((1, 1)));
+        let (wal, _) = Wal::open(&path, FsyncPolicy::Always, 9, |at, e| seen.push((at, e.table().to_string()))).unwrap();
+        assert_eq!(seen, vec![
+            (WalPosition { segment: 1, record: 0 }, "a".to_string()),
+            (WalPosition { segment: 2, record: 0 }, "b".to_string()),
+        ]);
+        assert_eq!((wal.segment(), wal.records()), (2, 1));
+
+        wal.remove_retired().unwrap();
+        assert_eq!(Wal::replay_retired(&path, |_, _| {}).unwrap(), None);
+    }
+
+    #[test]
+    fn test_not_a_log() {
+        let dir = test_dir("wal_garbage");
+        let path = dir.join("quickset.wal");
+        std::fs::write(&path, b"definitely not a log").unwrap();
+        assert!(Wal::open(&path, FsyncPolicy::Never, 1, |_, _| {}).is_err());
+    }
+}
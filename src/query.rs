@@ -1,12 +1,152 @@
 use serde::{Deserialize, Serialize};
-use crate::storage::Value;
-use crate::table::ColumnType;
+use std::collections::BTreeMap;
+use std::ops::Bound;
+use crate::aggregate::{Aggregation, AggregationResult, DEFAULT_TERMS_SIZE};
+use crate::analyzer::{Analyzer, TokenFilter};
+use crate::fuzzy::MAX_EDITS;
+use crate::index::{IndexKind, IndexSet};
+use crate::search::{Facet, Query, SearchError, SearchType, SortKey, DEFAULT_FACET_SIZE};
+use crate::storage::{format_timestamp, format_uuid, RowId, Value};
+use crate::table::{Alteration, Coercion, Column, ColumnType, Table};
 
 #[derive(Debug, Deserialize)]
 pub struct CreateTableRequest {
     pub name: String,
     pub columns: Vec<ColumnDef>,
     pub capacity: Option<usize>,
+    pub primary_key: Option<KeyColumnsDef>,
+}
+
+impl CreateTableRequest {
+    pub fn to_columns(&self) -> Result<Vec<Column>, String> {
+        let mut columns = self.columns
+            .iter()
+            .map(|c| c.to_column().map_err(|e| format!("column {}: {}", c.name, e)))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let names = match &self.primary_key {
+            Some(KeyColumnsDef::One(name)) => std::slice::from_ref(name),
+            Some(KeyColumnsDef::List(names)) => names.as_slice(),
+            None => &[],
+        };
+        for name in names {
+            let column = columns
+                .iter_mut()
+                .find(|c| &*c.name == name.as_str())
+                .ok_or_else(|| format!("primary key column not found: {}", name))?;
+            // -0.0 and 0.0 would be two keys, nan none at all
+            if column.col_type == ColumnType::Float {
+                return Err(format!("primary key column {} can't be a float", name));
+            }
+            if let ColumnType::Array(_) = column.col_type {
+                return Err(format!("primary key column {} can't be an array", name));
+            }
+            *column = column.clone().with_primary_key(true);
+        }
+        Ok(columns)
+    }
+}
+
+// one schema change: `"add"` a column, `"drop"` or `"rename"` one, or
+// `"retype"` it
+#[derive(Debug, Deserialize)]
+pub struct AlterTableRequest {
+    pub table: String,
+    #[serde(flatten)]
+    pub change: AlterDef,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AlterDef {
+    Add(ColumnDef),
+    Drop(String),
+    Rename {
+        from: String,
+        to: String,
+    },
+    Retype {
+        column: String,
+        #[serde(rename = "type")]
+        col_type: String,
+    },
+}
+
+impl AlterDef {
+    pub fn to_alteration(&self) -> Result<Alteration, String> {
+        Ok(match self {
+            AlterDef::Add(column) => {
+                Alteration::AddColumn(column.to_column().map_err(|e| format!("column {}: {}", column.name, e))?)
+            }
+            AlterDef::Drop(column) => Alteration::DropColumn(column.as_str().into()),
+            AlterDef::Rename { from, to } => Alteration::RenameColumn { from: from.as_str().into(), to: to.as_str().into() },
+            AlterDef::Retype { column, col_type } => Alteration::Retype {
+                column: column.as_str().into(),
+                col_type: parse_column_type(col_type).ok_or("invalid column type")?,
+            },
+        })
+    }
+}
+
+// `"primary_key": "email"`, or a list of columns for a composite key
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum KeyColumnsDef {
+    One(String),
+    List(Vec<String>),
+}
+
+// a row addressed by its primary key: the value itself for a single column
+// key, or an object with every key column for a composite one
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum KeyDef {
+    Object(BTreeMap<String, JsonValue>),
+    Value(JsonValue),
+}
+
+impl KeyDef {
+    pub fn to_key(&self, table: &Table) -> Result<Vec<Value>, String> {
+        let key = table.primary_key();
+        if key.is_empty() {
+            return Err("table has no primary key".to_string());
+        }
+        match self {
+            KeyDef::Value(value) if key.len() == 1 => Ok(vec![key_value(&table.columns()[key[0]], value)]),
+            KeyDef::Value(_) => Err("composite keys must be objects".to_string()),
+            KeyDef::Object(fields) => {
+                if let Some(name) = fields.keys().find(|name| table.column_index(name).is_none_or(|c| !key.contains(&c))) {
+                    return Err(format!("{} is not a primary key column", name));
+                }
+                key.iter()
+                    .map(|&c| {
+                        let column = &table.columns()[c];
+                        fields.get(&*column.name)
+                            .map(|v| key_value(column, v))
+                            .ok_or_else(|| format!("key is missing column {}", column.name))
+                    })
+                    .collect()
+            }
+        }
+    }
+}
+
+// a key value as its column stores it, a uuid key can be given as text
+fn key_value(column: &Column, value: &JsonValue) -> Value {
+    let value = value.to_value();
+    column.col_type.check(value.clone(), Coercion::Strict).unwrap_or(value)
+}
+
+// row ids plus the rows holding `keys`, keys no row holds are skipped like
+// ids that don't exist
+pub fn resolve_rows(ids: &[RowId], keys: &[KeyDef], table: &Table) -> Result<Vec<RowId>, String> {
+    let mut row_ids = ids.to_vec();
+    for key in keys {
+        if let Some(id) = table.find_key(&key.to_key(table)?) {
+            row_ids.push(id);
+        }
+    }
+    Ok(row_ids)
 }
 
 #[derive(Debug, Deserialize)]
@@ -14,51 +154,527 @@ pub struct ColumnDef {
     pub name: String,
     #[serde(rename = "type")]
     pub col_type: String,
+    pub indexes: Option<IndexesDef>,    // none = the type's defaults
+    pub analyzer: Option<AnalyzerDef>,  // none = standard, string columns only
+    #[serde(default = "nullable_default")]
+    pub nullable: bool,
+    pub default: Option<JsonValue>, // for rows inserted as objects that leave the column out
+}
+
+fn nullable_default() -> bool {
+    true
+}
+
+// `array<T>` or `T[]` for an array of any other type
+pub fn parse_column_type(name: &str) -> Option<ColumnType> {
+    let name = name.trim().to_lowercase();
+    let element = name
+        .strip_prefix("array<")
+        .and_then(|rest| rest.strip_suffix('>'))
+        .or_else(|| name.strip_suffix("[]"));
+    if let Some(element) = element {
+        return ColumnType::array_of(parse_column_type(element)?);
+    }
+    match name.as_str() {
+        "int" | "integer" | "i64" => Some(ColumnType::Int),
+        "float" | "double" | "f64" => Some(ColumnType::Float),
+        "string" | "text" | "varchar" => Some(ColumnType::String),
+        "bytes" | "blob" | "binary" => Some(ColumnType::Bytes),
+        "bool" | "boolean" => Some(ColumnType::Bool),
+        "timestamp" | "datetime" => Some(ColumnType::Timestamp),
+        "uuid" => Some(ColumnType::Uuid),
+        _ => None,
+    }
 }
 
 impl ColumnDef {
     pub fn to_column_type(&self) -> Option<ColumnType> {
-        match self.col_type.to_lowercase().as_str() {
-            "int" | "integer" | "i64" => Some(ColumnType::Int),
-            "float" | "double" | "f64" => Some(ColumnType::Float),
-            "string" | "text" | "varchar" => Some(ColumnType::String),
-            "bytes" | "blob" | "binary" => Some(ColumnType::Bytes),
-            _ => None,
+        parse_column_type(&self.col_type)
+    }
+
+    pub fn to_column(&self) -> Result<Column, String> {
+        let col_type = self.to_column_type().ok_or("invalid column type")?;
+        let mut column = Column::new(&self.name, col_type).with_nullable(self.nullable);
+        if let Some(indexes) = &self.indexes {
+            column = column.with_indexes(indexes.to_index_set(col_type)?);
+        }
+        if let Some(analyzer) = &self.analyzer {
+            if col_type.element() != ColumnType::String {
+                return Err(format!("analyzer not supported on {} columns", col_type.name()));
+            }
+            column = column.with_analyzer(analyzer.to_analyzer()?);
+        }
+        if let Some(default) = &self.default {
+            let default = column.check(default.to_value(), Coercion::Strict)
+                .map_err(|_| format!("default must be {}", col_type.name()))?;
+            column = column.with_default(default);
+        }
+        Ok(column)
+    }
+}
+
+// `"analyzer": "english"` for a preset, or a filter chain like
+// `["lowercase", "stem"]`, applied in order
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum AnalyzerDef {
+    Preset(String),
+    Filters(Vec<String>),
+}
+
+impl AnalyzerDef {
+    pub fn to_analyzer(&self) -> Result<Analyzer, String> {
+        match self {
+            Self::Preset(name) => Analyzer::from_name(name)
+                .or_else(|| TokenFilter::from_str(name).map(|f| Analyzer::new(vec![f])))
+                .ok_or_else(|| format!("unknown analyzer '{}'", name)),
+            Self::Filters(names) => names.iter()
+                .map(|name| TokenFilter::from_str(name).ok_or_else(|| format!("unknown token filter '{}'", name)))
+                .collect::<Result<Vec<_>, _>>()
+                .map(Analyzer::new),
+        }
+    }
+}
+
+// `"indexes": ["exact", "prefix"]`, or a single name, or "none" / "all"
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum IndexesDef {
+    One(String),
+    List(Vec<String>),
+}
+
+impl IndexesDef {
+    // "all" means everything the column type supports
+    pub fn to_index_set(&self, col_type: ColumnType) -> Result<IndexSet, String> {
+        let names = match self {
+            Self::One(name) => std::slice::from_ref(name),
+            Self::List(names) => names.as_slice(),
+        };
+
+        let mut set = IndexSet::NONE;
+        for name in names {
+            match name.to_lowercase().as_str() {
+                "none" => {}
+                "all" => {
+                    set = IndexKind::ALL.iter().filter(|k| col_type.supports(**k)).fold(set, |s, k| s.with(*k));
+                }
+                other => {
+                    let kind = IndexKind::from_str(other).ok_or_else(|| format!("unknown index '{}'", name))?;
+                    if !col_type.supports(kind) {
+                        return Err(format!("{} index not supported on {} columns", kind, col_type.name()));
+                    }
+                    set = set.with(kind);
+                }
+            }
         }
+        Ok(set)
     }
 }
 
 #[derive(Debug, Deserialize)]
 pub struct InsertRequest {
     pub table: String,
-    pub rows: Vec<Vec<JsonValue>>,
+    pub rows: Vec<RowDef>,
+    #[serde(default)]
+    pub coerce: bool,   // convert mismatched values where lossless instead of refusing the row
+}
+
+// a row as values in column order, or as an object keyed by column name
+// where left out columns get their default
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum RowDef {
+    Values(Vec<JsonValue>),
+    Object(BTreeMap<String, JsonValue>),
+}
+
+impl RowDef {
+    pub fn to_values(&self, table: &Table) -> Result<Vec<Value>, String> {
+        match self {
+            RowDef::Values(values) => Ok(values.iter().map(|v| v.to_value()).collect()),
+            RowDef::Object(fields) => {
+                let mut values: Vec<Value> = table.columns().iter().map(|c| c.default.clone()).collect();
+                for (name, value) in fields {
+                    let column = table.column_index(name).ok_or_else(|| format!("unknown column '{}'", name))?;
+                    values[column] = value.to_value();
+                }
+                Ok(values)
+            }
+        }
+    }
+}
+
+// how rows come back: `values` in column order, or an object keyed by column name
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RowFormat {
+    #[default]
+    Array,
+    Object,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct SearchRequest {
     pub table: String,
+    #[serde(flatten)]
+    pub clause: QueryClause,
+    #[serde(default)]
+    pub order_by: Vec<OrderBy>,
+    pub limit: Option<usize>,
+    pub offset: Option<usize>,
+    #[serde(default)]
+    pub aggregations: BTreeMap<String, AggregationDef>,
+    #[serde(default)]
+    pub facets: Vec<FacetDef>,
+    #[serde(default)]
+    pub format: RowFormat,
+}
+
+impl SearchRequest {
+    // the search to run. aggregations or facets on their own run over every row
+    pub fn to_query(&self, table: &Table) -> Result<Query, String> {
+        if self.clause.is_empty() && !(self.aggregations.is_empty() && self.facets.is_empty()) {
+            return Ok(Query::bool(Vec::new(), Vec::new(), Vec::new()));
+        }
+        self.clause.to_query(table)
+    }
+
+    pub fn to_aggregations(&self, table: &Table) -> Result<Vec<(String, Aggregation)>, String> {
+        resolve_aggregations(&self.aggregations, table)
+    }
+
+    // facets by column name
+    pub fn to_facets(&self, table: &Table) -> Result<Vec<(String, Facet)>, String> {
+        self.facets
+            .iter()
+            .map(|def| {
+                let (name, size) = match def {
+                    FacetDef::Column(name) => (name, None),
+                    FacetDef::Sized { column, size } => (column, *size),
+                };
+                let column = table.column_index(name).ok_or_else(|| format!("facet column not found: {}", name))?;
+                Ok((name.clone(), Facet { column, size: size.unwrap_or(DEFAULT_FACET_SIZE) }))
+            })
+            .collect()
+    }
+}
+
+// a facet is a column name, or {"column": ..., "size": n} for more or fewer
+// than the default 10 values
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum FacetDef {
+    Column(String),
+    Sized { column: String, size: Option<usize> },
+}
+
+// one entry under `aggregations`, like {"type": "avg", "column": "price"} or
+// {"type": "terms", "column": "status", "size": 5, "aggregations": {...}}
+#[derive(Debug, Deserialize)]
+pub struct AggregationDef {
+    #[serde(rename = "type")]
+    pub agg_type: String,
+    pub column: Option<String>,
+    pub size: Option<usize>,    // terms: how many buckets, default 10
+    #[serde(default)]
+    pub aggregations: BTreeMap<String, AggregationDef>,     // terms: run again inside every bucket
+}
+
+fn resolve_aggregations(defs: &BTreeMap<String, AggregationDef>, table: &Table) -> Result<Vec<(String, Aggregation)>, String> {
+    defs.iter()
+        .map(|(name, def)| Ok((name.clone(), def.to_aggregation(name, table)?)))
+        .collect()
+}
+
+impl AggregationDef {
+    pub fn to_aggregation(&self, name: &str, table: &Table) -> Result<Aggregation, String> {
+        let column = match &self.column {
+            Some(column) => Some(
+                table.column_index(column).ok_or_else(|| format!("aggregation column not found: {}", column))?,
+            ),
+            None => None,
+        };
+        let kind = self.agg_type.to_lowercase();
+        if kind != "terms" && !self.aggregations.is_empty() {
+            return Err(format!("{}: only terms aggregations take nested aggregations", name));
+        }
+
+        // sum and avg need numbers, min and max anything that orders
+        let needs = |allowed: &[ColumnType], what: &str| match column {
+            Some(c) if allowed.contains(&table.columns()[c].col_type) => Ok(c),
+            Some(_) => Err(format!("{}: {} needs {} column", name, kind, what)),
+            None => Err(format!("{}: {} needs a column", name, kind)),
+        };
+        let numeric = [ColumnType::Int, ColumnType::Float];
+        let ordered = [ColumnType::Int, ColumnType::Float, ColumnType::String, ColumnType::Timestamp];
+
+        match kind.as_str() {
+            "count" => Ok(Aggregation::Count(column)),
+            "sum" => Ok(Aggregation::Sum(needs(&numeric, "an int or float")?)),
+            "avg" => Ok(Aggregation::Avg(needs(&numeric, "an int or float")?)),
+            "min" => Ok(Aggregation::Min(needs(&ordered, "an int, float, string or timestamp")?)),
+            "max" => Ok(Aggregation::Max(needs(&ordered, "an int, float, string or timestamp")?)),
+            "terms" => Ok(Aggregation::Terms {
+                column: column.ok_or_else(|| format!("{}: terms needs a column", name))?,
+                size: self.size.unwrap_or(DEFAULT_TERMS_SIZE),
+                aggregations: resolve_aggregations(&self.aggregations, table)?,
+            }),
+            _ => Err(format!("{}: unknown aggregation '{}', expected count/sum/avg/min/max/terms", name, self.agg_type)),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct OrderBy {
     pub column: String,
+    #[serde(default)]
+    pub direction: SortDirection,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SortDirection {
+    #[default]
+    #[serde(alias = "ASC")]
+    Asc,
+    #[serde(alias = "DESC")]
+    Desc,
+}
+
+impl OrderBy {
+    pub fn to_sort_key(&self, table: &Table) -> Result<SortKey, String> {
+        let column = table
+            .column_index(&self.column)
+            .ok_or_else(|| format!("order_by column not found: {}", self.column))?;
+        Ok(SortKey { column, descending: self.direction == SortDirection::Desc })
+    }
+}
+
+// one node of a search: a single column search, a bool node with nested
+// clauses, or both (the column search then acts as one more `must`)
+#[derive(Debug, Default, Deserialize)]
+pub struct QueryClause {
+    pub column: Option<String>,
     #[serde(rename = "type")]
-    pub search_type: String,
+    pub search_type: Option<String>,
     pub value: Option<JsonValue>,
     pub prefix: Option<String>,
+    pub suffix: Option<String>,
     pub query: Option<String>,
-    pub min: Option<i64>,
-    pub max: Option<i64>,
+    pub operator: Option<String>,   // fulltext: "and" (default) or "or"
+    pub fuzziness: Option<u32>,     // fuzzy: max edits, 0-2, default 1
+    pub min: Option<JsonValue>,     // range: numbers, or strings on string columns
+    pub max: Option<JsonValue>,
+    #[serde(default)]
+    pub min_exclusive: bool,
+    #[serde(default)]
+    pub max_exclusive: bool,
+    #[serde(default)]
+    pub must: Vec<QueryClause>,
+    #[serde(default)]
+    pub should: Vec<QueryClause>,
+    #[serde(default)]
+    pub must_not: Vec<QueryClause>,
+    pub minimum_should_match: Option<usize>,
+}
+
+impl QueryClause {
+    fn is_bool(&self) -> bool {
+        !self.must.is_empty() || !self.should.is_empty() || !self.must_not.is_empty()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.column.is_none() && self.search_type.is_none() && !self.is_bool()
+    }
+
+    // resolve column names and check every clause can use an index
+    pub fn to_query(&self, table: &Table) -> Result<Query, String> {
+        let term = match &self.column {
+            Some(column) => Some(self.to_term(column, table)?),
+            None if self.search_type.is_some() => return Err("column required".to_string()),
+            None => None,
+        };
+
+        if !self.is_bool() {
+            return term.ok_or_else(|| "query needs a column and type, or must/should/must_not clauses".to_string());
+        }
+
+        let nested = |clauses: &[QueryClause]| {
+            clauses.iter().map(|c| c.to_query(table)).collect::<Result<Vec<_>, _>>()
+        };
+        let mut must = nested(&self.must)?;
+        must.extend(term);
+
+        let mut query = Query::bool(must, nested(&self.should)?, nested(&self.must_not)?);
+        if let (Query::Bool { minimum_should_match, .. }, Some(n)) = (&mut query, self.minimum_should_match) {
+            *minimum_should_match = n;
+        }
+        Ok(query)
+    }
+
+    fn to_term(&self, column: &str, table: &Table) -> Result<Query, String> {
+        let col_idx = table.column_index(column).ok_or_else(|| format!("column not found: {}", column))?;
+
+        let search = match self.search_type.as_deref() {
+            Some("exact") => {
+                let value = self.value.as_ref().ok_or("value required for exact search")?;
+                SearchType::Exact(search_value(column, table.columns()[col_idx].col_type, value)?)
+            }
+            Some("prefix") => SearchType::Prefix(self.prefix.clone().ok_or("prefix required")?),
+            Some("fulltext") => {
+                let query = self.query.clone().ok_or("query required")?;
+                match self.operator.as_deref().map(str::to_lowercase).as_deref() {
+                    None | Some("and") => SearchType::FullText(query),
+                    Some("or") => SearchType::FullTextAny(query),
+                    Some(other) => return Err(format!("invalid operator '{}', expected and/or", other)),
+                }
+            }
+            Some("range") => {
+                let col_type = table.columns()[col_idx].col_type;
+                SearchType::Range {
+                    min: range_bound(column, col_type, self.min.as_ref(), self.min_exclusive)?,
+                    max: range_bound(column, col_type, self.max.as_ref(), self.max_exclusive)?,
+                }
+            }
+            Some("contains") => SearchType::Contains(self.query.clone().ok_or("query required")?),
+            Some("suffix") => SearchType::Suffix(self.suffix.clone().ok_or("suffix required")?),
+            // a value matches whole values, a query matches full-text terms
+            Some("fuzzy") => {
+                let max_edits = self.fuzziness.unwrap_or(1);
+                if max_edits > MAX_EDITS {
+                    return Err(SearchError::TooManyEdits(max_edits).to_string());
+                }
+                match (&self.value, &self.query) {
+                    (Some(JsonValue::String(value)), _) => SearchType::Fuzzy { value: value.clone(), max_edits },
+                    (Some(_), _) => return Err("fuzzy value must be a string".to_string()),
+                    (None, Some(query)) => SearchType::FuzzyText { query: query.clone(), max_edits },
+                    (None, None) => return Err("value or query required for fuzzy search".to_string()),
+                }
+            }
+            Some(_) => return Err("invalid search type".to_string()),
+            None => return Err(format!("type required for column {}", column)),
+        };
+
+        let kind = search.index_kind();
+        if !table.has_index(col_idx, kind) {
+            return Err(format!("{}: {}", column, SearchError::NotIndexed(kind)));
+        }
+        Ok(Query::term(col_idx, search))
+    }
+}
+
+// a value to look for, as the column (or each element of an array column)
+// stores it, so timestamps and uuids can be given as text. anything that
+// doesn't convert is searched for as it came
+fn search_value(column: &str, col_type: ColumnType, value: &JsonValue) -> Result<Value, String> {
+    let value = value.to_value();
+    match col_type.element() {
+        ColumnType::Timestamp | ColumnType::Uuid => {
+            let col_type = col_type.element();
+            col_type.check(value, Coercion::Strict).map_err(|_| format!("{}: value must be a {}", column, col_type.name()))
+        }
+        element => Ok(element.check(value.clone(), Coercion::Strict).unwrap_or(value)),
+    }
+}
+
+// one end of a range, checked against what the column's range index orders
+fn range_bound(column: &str, col_type: ColumnType, value: Option<&JsonValue>, exclusive: bool) -> Result<Bound<Value>, String> {
+    let value = match value {
+        Some(value) => value.to_value(),
+        None => return Ok(Bound::Unbounded),
+    };
+    let (value, expected) = match col_type.element() {
+        ColumnType::String => (matches!(value, Value::String(_)).then_some(value), "strings"),
+        ColumnType::Timestamp => (ColumnType::Timestamp.check(value, Coercion::Strict).ok(), "timestamps"),
+        _ => (matches!(value, Value::Int(_) | Value::Float(_)).then_some(value), "numbers"),
+    };
+    let value = value.ok_or_else(|| format!("{}: {}", column, SearchError::BoundType(expected)))?;
+    Ok(if exclusive { Bound::Excluded(value) } else { Bound::Included(value) })
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SuggestRequest {
+    pub table: String,
+    pub column: String,
+    #[serde(default)]
+    pub prefix: String,
     pub limit: Option<usize>,
-    pub offset: Option<usize>,
+    pub weight: Option<String>,     // numeric column to rank by instead of row counts
 }
 
 #[derive(Debug, Deserialize)]
 pub struct GetRequest {
     pub table: String,
+    #[serde(default)]
     pub ids: Vec<u64>,
+    #[serde(default)]
+    pub keys: Vec<KeyDef>,
+    #[serde(default)]
+    pub format: RowFormat,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct DeleteRequest {
     pub table: String,
+    #[serde(default)]
     pub ids: Vec<u64>,
+    #[serde(default)]
+    pub keys: Vec<KeyDef>,
+}
+
+// every row matching a `/search` style query
+#[derive(Debug, Deserialize)]
+pub struct DeleteByQueryRequest {
+    pub table: String,
+    #[serde(flatten)]
+    pub clause: QueryClause,
+}
+
+// `set` the named columns on every row matching a query, the rest keep their values
+#[derive(Debug, Deserialize)]
+pub struct UpdateByQueryRequest {
+    pub table: String,
+    #[serde(flatten)]
+    pub clause: QueryClause,
+    pub set: BTreeMap<String, JsonValue>,
+    #[serde(default)]
+    pub coerce: bool,
+}
+
+impl UpdateByQueryRequest {
+    pub fn to_changes(&self, table: &Table) -> Result<Vec<(usize, Value)>, String> {
+        resolve_changes(&self.set, table)
+    }
+}
+
+// `set` the named columns of one row, only their indexes are touched
+#[derive(Debug, Deserialize)]
+pub struct PatchRequest {
+    pub table: String,
+    pub id: u64,
+    pub set: BTreeMap<String, JsonValue>,
+    #[serde(default)]
+    pub coerce: bool,
+}
+
+impl PatchRequest {
+    pub fn to_changes(&self, table: &Table) -> Result<Vec<(usize, Value)>, String> {
+        resolve_changes(&self.set, table)
+    }
+}
+
+// column names resolved to positions, values not yet checked
+fn resolve_changes(set: &BTreeMap<String, JsonValue>, table: &Table) -> Result<Vec<(usize, Value)>, String> {
+    if set.is_empty() {
+        return Err("set needs at least one column".to_string());
+    }
+    set.iter()
+        .map(|(name, value)| {
+            let column = table.column_index(name).ok_or_else(|| format!("column not found: {}", name))?;
+            Ok((column, value.to_value()))
+        })
+        .collect()
 }
 
 #[derive(Debug, Deserialize)]
@@ -66,30 +682,37 @@ pub struct UpdateRequest {
     pub table: String,
     pub id: u64,
     pub values: Vec<JsonValue>,
+    #[serde(default)]
+    pub coerce: bool,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
 #[serde(untagged)]
 pub enum JsonValue {
     Null,
+    Bool(bool),
     Int(i64),
     Float(f64),
     String(String),
-    Bytes(Vec<u8>),
+    Bytes(Vec<u8>),     // a list of small ints reads as bytes, array columns take it too
+    Array(Vec<JsonValue>),
 }
 
 impl JsonValue {
     pub fn to_value(&self) -> Value {
         match self {
             JsonValue::Null => Value::Null,
+            JsonValue::Bool(b) => Value::Bool(*b),
             JsonValue::Int(i) => Value::Int(*i),
             JsonValue::Float(f) => Value::Float(*f),
             JsonValue::String(s) => Value::String(s.clone().into_boxed_str()),
             JsonValue::Bytes(b) => Value::Bytes(b.clone().into_boxed_slice()),
+            JsonValue::Array(items) => Value::Array(items.iter().map(JsonValue::to_value).collect()),
         }
     }
 }
 
+// timestamps go out as rfc 3339 and uuids hyphenated, the way they're taken in
 impl From<&Value> for JsonValue {
     fn from(v: &Value) -> Self {
         match v {
@@ -98,6 +721,10 @@ impl From<&Value> for JsonValue {
             Value::Float(f) => JsonValue::Float(*f),
             Value::String(s) => JsonValue::String(s.to_string()),
             Value::Bytes(b) => JsonValue::Bytes(b.to_vec()),
+            Value::Bool(b) => JsonValue::Bool(*b),
+            Value::Timestamp(t) => JsonValue::String(format_timestamp(*t)),
+            Value::Uuid(u) => JsonValue::String(format_uuid(u)),
+            Value::Array(items) => JsonValue::Array(items.iter().map(JsonValue::from).collect()),
         }
     }
 }
@@ -135,16 +762,124 @@ pub struct InsertResponse {
     pub count: usize,
 }
 
+#[derive(Debug, Serialize)]
+pub struct UpsertResponse {
+    pub ids: Vec<u64>,
+    pub inserted: usize,
+    pub updated: usize,
+}
+
 #[derive(Debug, Serialize)]
 pub struct SearchResponse {
     pub rows: Vec<RowResponse>,
     pub total: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub aggregations: Option<BTreeMap<String, AggregationResponse>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub facets: Option<BTreeMap<String, Vec<FacetCount>>>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FacetCount {
+    pub value: JsonValue,
+    pub count: usize,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+pub enum AggregationResponse {
+    Value { value: JsonValue },
+    Terms { buckets: Vec<BucketResponse>, other_count: usize },
+}
+
+#[derive(Debug, Serialize)]
+pub struct BucketResponse {
+    pub key: JsonValue,
+    pub count: usize,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub aggregations: BTreeMap<String, AggregationResponse>,
+}
+
+impl AggregationResponse {
+    pub fn from_results(results: &[(String, AggregationResult)]) -> BTreeMap<String, AggregationResponse> {
+        results.iter().map(|(name, result)| (name.clone(), Self::from(result))).collect()
+    }
+}
+
+impl From<&AggregationResult> for AggregationResponse {
+    fn from(result: &AggregationResult) -> Self {
+        match result {
+            AggregationResult::Value(value) => Self::Value { value: JsonValue::from(value) },
+            AggregationResult::Terms { buckets, other } => Self::Terms {
+                buckets: buckets
+                    .iter()
+                    .map(|b| BucketResponse {
+                        key: JsonValue::from(&b.key),
+                        count: b.count,
+                        aggregations: Self::from_results(&b.aggregations),
+                    })
+                    .collect(),
+                other_count: *other,
+            },
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct SuggestResponse {
+    pub suggestions: Vec<SuggestionResponse>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SuggestionResponse {
+    pub value: String,
+    pub count: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub weight: Option<f64>,
 }
 
 #[derive(Debug, Serialize)]
 pub struct RowResponse {
     pub id: u64,
-    pub values: Vec<JsonValue>,
+    pub values: RowValues,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub score: Option<f64>,     // bm25 relevance, full-text searches only
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub distance: Option<u32>,  // edit distance, fuzzy searches only
+}
+
+impl RowResponse {
+    pub fn new(id: u64, values: &[Value], columns: &[Column], format: RowFormat) -> Self {
+        let values = match format {
+            RowFormat::Array => RowValues::Array(values.iter().map(JsonValue::from).collect()),
+            RowFormat::Object => RowValues::Object(
+                columns.iter().zip(values).map(|(c, v)| (c.name.to_string(), JsonValue::from(v))).collect(),
+            ),
+        };
+        Self { id, values, score: None, distance: None }
+    }
+}
+
+#[derive(Debug)]
+pub enum RowValues {
+    Array(Vec<JsonValue>),
+    Object(Vec<(String, JsonValue)>),   // kept in column order
+}
+
+impl Serialize for RowValues {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeMap;
+        match self {
+            RowValues::Array(values) => values.serialize(serializer),
+            RowValues::Object(fields) => {
+                let mut map = serializer.serialize_map(Some(fields.len()))?;
+                for (name, value) in fields {
+                    map.serialize_entry(name, value)?;
+                }
+                map.end()
+            }
+        }
+    }
 }
 
 #[derive(Debug, Serialize)]
@@ -159,6 +894,14 @@ pub struct StatsResponse {
     pub tables: Vec<TableInfo>,
 }
 
+#[derive(Debug, Serialize)]
+pub struct SnapshotResponse {
+    pub tables: usize,
+    pub rows: usize,
+    pub bytes: u64,
+    pub duration_ms: u64,
+}
+
 // sync-related request/response types
 #[derive(Debug, Deserialize)]
 pub struct SyncConfigRequest {
@@ -186,6 +929,7 @@ pub struct SyncColumnRequest {
     pub target: String,
     #[serde(rename = "type")]
     pub col_type: String,
+    pub indexes: Option<IndexesDef>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -220,6 +964,7 @@ pub struct SyncTableResult {
     pub table: String,
     pub success: bool,
     pub rows_synced: usize,
+    pub rows_failed: usize,
     pub duration_ms: u64,
     pub error: Option<String>,
 }
@@ -244,16 +989,61 @@ mod tests {
         let col = ColumnDef {
             name: "test".to_string(),
             col_type: "STRING".to_string(),
+            indexes: None,
+            analyzer: None,
+            nullable: true,
+            default: None,
         };
         assert_eq!(col.to_column_type(), Some(ColumnType::String));
 
         let col = ColumnDef {
             name: "test".to_string(),
             col_type: "int".to_string(),
+            indexes: None,
+            analyzer: None,
+            nullable: true,
+            default: None,
         };
         assert_eq!(col.to_column_type(), Some(ColumnType::Int));
     }
 
+    #[test]
+    fn test_column_indexes_parsing() {
+        let parse = |json: &str| serde_json::from_str::<ColumnDef>(json).unwrap().to_column();
+
+        let col = parse(r#"{"name": "email", "type": "string", "indexes": ["exact", "prefix"]}"#).unwrap();
+        assert_eq!(col.indexes, IndexSet::NONE.with(IndexKind::Exact).with(IndexKind::Prefix));
+
+        let col = parse(r#"{"name": "blob", "type": "string", "indexes": "none"}"#).unwrap();
+        assert!(col.indexes.is_empty());
+
+        let col = parse(r#"{"name": "age", "type": "int", "indexes": "all"}"#).unwrap();
+        assert_eq!(col.indexes, IndexSet::NONE.with(IndexKind::Exact).with(IndexKind::Range));
+
+        let col = parse(r#"{"name": "age", "type": "int"}"#).unwrap();
+        assert_eq!(col.indexes, ColumnType::Int.default_indexes());
+
+        assert!(parse(r#"{"name": "age", "type": "int", "indexes": ["prefix"]}"#).is_err());
+        assert!(parse(r#"{"name": "age", "type": "int", "indexes": ["btree"]}"#).is_err());
+    }
+
+    #[test]
+    fn test_column_analyzer_parsing() {
+        let parse = |json: &str| serde_json::from_str::<ColumnDef>(json).unwrap().to_column();
+
+        assert_eq!(parse(r#"{"name": "bio", "type": "text"}"#).unwrap().analyzer, Analyzer::standard());
+        assert_eq!(parse(r#"{"name": "bio", "type": "text", "analyzer": "english"}"#).unwrap().analyzer, Analyzer::english());
+        assert_eq!(parse(r#"{"name": "bio", "type": "text", "analyzer": "stem"}"#).unwrap().analyzer.filters(), &[TokenFilter::Stem]);
+        assert_eq!(
+            parse(r#"{"name": "bio", "type": "text", "analyzer": ["lowercase", "stop"]}"#).unwrap().analyzer.filters(),
+            &[TokenFilter::Lowercase, TokenFilter::StopWords]
+        );
+
+        assert!(parse(r#"{"name": "bio", "type": "text", "analyzer": "klingon"}"#).is_err());
+        assert!(parse(r#"{"name": "bio", "type": "text", "analyzer": ["lowercase", "soundex"]}"#).is_err());
+        assert!(parse(r#"{"name": "age", "type": "int", "analyzer": "english"}"#).is_err());
+    }
+
     #[test]
     fn test_api_response() {
         let resp: ApiResponse<i32> = ApiResponse::ok(42);
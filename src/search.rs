@@ -1,14 +1,109 @@
-use crate::index::{BloomFilter, HashIndex, InvertedIndex, SortedIndex, TrieIndex};
-use crate::storage::{RowId, Value};
+use std::collections::HashMap;
+use std::ops::Bound;
 
+use crate::aggregate;
+use crate::analyzer::Analyzer;
+use crate::fuzzy::{LevenshteinAutomaton, MAX_EDITS};
+use crate::index::{BloomFilter, FloatKey, HashIndex, IndexKind, IndexSet, InvertedIndex, NgramIndex, SortedIndex, TrieIndex};
+use crate::storage::{RowId, Storage, Value};
+use crate::table::{Column, ColumnType};
+
+#[derive(Clone, Debug)]
 pub enum SearchType {
     Exact(Value),
     Prefix(String),
-    FullText(String),
-    Range { min: i64, max: i64 },
-    Contains(String),
+    FullText(String),       // rows matching every term, "phrase" and NEAR/n clause
+    FullTextAny(String),    // rows matching at least one, meant to be ranked
+    Range { min: Bound<Value>, max: Bound<Value> },
+    Contains(String),       // substring anywhere in the value
+    Suffix(String),
+    Fuzzy { value: String, max_edits: u32 },        // whole values within max_edits
+    FuzzyText { query: String, max_edits: u32 },    // every term close to an indexed one
+}
+
+impl SearchType {
+    // a range with both ends included
+    pub fn between(min: Value, max: Value) -> Self {
+        Self::Range { min: Bound::Included(min), max: Bound::Included(max) }
+    }
+
+    // the index a search of this type runs against
+    pub fn index_kind(&self) -> IndexKind {
+        match self {
+            Self::Exact(_) => IndexKind::Exact,
+            Self::Prefix(_) | Self::Fuzzy { .. } => IndexKind::Prefix,
+            Self::FullText(_) | Self::FullTextAny(_) | Self::FuzzyText { .. } => IndexKind::FullText,
+            Self::Range { .. } => IndexKind::Range,
+            Self::Contains(_) | Self::Suffix(_) => IndexKind::Substring,
+        }
+    }
+}
+
+// a search over one or more columns. bool nodes combine their children like
+// elasticsearch's bool query: every `must`, at least `minimum_should_match` of
+// the `should`s and none of the `must_not`s
+pub enum Query {
+    Term { column: usize, search: SearchType },
+    Bool {
+        must: Vec<Query>,
+        should: Vec<Query>,
+        must_not: Vec<Query>,
+        minimum_should_match: usize,
+    },
+}
+
+impl Query {
+    pub fn term(column: usize, search: SearchType) -> Self {
+        Self::Term { column, search }
+    }
+
+    // a bool node with elasticsearch's default: one `should` has to match
+    // unless there are `must`s to carry the query
+    pub fn bool(must: Vec<Query>, should: Vec<Query>, must_not: Vec<Query>) -> Self {
+        let minimum_should_match = usize::from(must.is_empty() && !should.is_empty());
+        Self::Bool { must, should, must_not, minimum_should_match }
+    }
+}
+
+// what the engine needs from the rows behind its indexes: every id, for
+// queries with nothing positive to start from, and values, to check
+// candidates the indexes can't decide on their own
+pub trait RowSource {
+    fn row_ids(&self) -> Vec<RowId>;
+    fn value(&self, row_id: RowId, column: usize) -> Option<&Value>;
+}
+
+impl RowSource for Storage {
+    fn row_ids(&self) -> Vec<RowId> {
+        self.iter().map(|r| r.id).collect()
+    }
+
+    fn value(&self, row_id: RowId, column: usize) -> Option<&Value> {
+        self.get(row_id).and_then(|r| r.columns.get(column))
+    }
 }
 
+// one `order_by` entry, resolved to a column position
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SortKey {
+    pub column: usize,
+    pub descending: bool,
+}
+
+// one requested facet, resolved to a column position
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Facet {
+    pub column: usize,
+    pub size: usize,    // values to return
+}
+
+// values a facet returns when the request doesn't say
+pub const DEFAULT_FACET_SIZE: usize = 10;
+
+// past this many distinct values, counting a facet off the hash index's
+// posting lists costs more than reading the matched rows
+const FACET_INDEX_LIMIT: usize = 1024;
+
 pub struct SearchResult {
     pub row_ids: Vec<RowId>,
     pub total: usize,
@@ -42,175 +137,859 @@ impl SearchResult {
     }
 }
 
+// a completed value for a suggest prefix
+#[derive(Debug, Clone, PartialEq)]
+pub struct Suggestion {
+    pub value: String,
+    pub count: usize,           // rows holding the value
+    pub weight: Option<f64>,    // the weight column summed over them, when ranking by one
+}
+
+// okapi bm25 relevance. k1 caps how much repeating a term helps, b how much
+// longer than average text is penalised
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Bm25 {
+    pub k1: f64,
+    pub b: f64,
+}
+
+impl Default for Bm25 {
+    fn default() -> Self {
+        Self { k1: 1.2, b: 0.75 }
+    }
+}
+
+impl Bm25 {
+    // add every row's score for `terms` to `scores`
+    pub fn score_terms(&self, index: &InvertedIndex, terms: &[&str], scores: &mut HashMap<RowId, f64>) {
+        let docs = index.doc_count() as f64;
+        let avg_len = index.avg_doc_len().max(1.0);
+
+        for term in terms {
+            let postings = index.postings(term);
+            if postings.is_empty() {
+                continue;
+            }
+            let n = postings.len() as f64;
+            let idf = (1.0 + (docs - n + 0.5) / (n + 0.5)).ln();
+
+            for posting in postings {
+                let tf = posting.tf() as f64;
+                let len = index.doc_len(posting.row_id) as f64;
+                let norm = self.k1 * (1.0 - self.b + self.b * len / avg_len);
+                *scores.entry(posting.row_id).or_default() += idf * tf * (self.k1 + 1.0) / (tf + norm);
+            }
+        }
+    }
+}
+
+// why a search couldn't run
+#[derive(Debug, Clone, PartialEq)]
+pub enum SearchError {
+    ColumnOutOfRange(usize),
+    NotIndexed(IndexKind),
+    TooManyEdits(u32),
+    BoundType(&'static str),    // what the column's range bounds have to be
+    WeightWithoutPrefix,        // a weighted suggest would sum over the whole column
+}
+
+impl std::fmt::Display for SearchError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::ColumnOutOfRange(c) => write!(f, "column {} out of range", c),
+            Self::NotIndexed(kind) => write!(f, "column has no {} index", kind),
+            Self::TooManyEdits(n) => write!(f, "max edit distance is {}, got {}", MAX_EDITS, n),
+            Self::BoundType(expected) => write!(f, "range bounds must be {}", expected),
+            Self::WeightWithoutPrefix => write!(f, "a weighted suggest needs a prefix"),
+        }
+    }
+}
+
+impl std::error::Error for SearchError {}
+
+// the indexes kept for one column, only the declared ones are allocated
+#[derive(Default)]
+struct ColumnIndexes {
+    hash: Option<HashIndex>,
+    bloom: Option<BloomFilter>,
+    inverted: Option<InvertedIndex>,
+    trie: Option<TrieIndex>,
+    sorted: Option<RangeIndex>,
+    ngram: Option<NgramIndex>,
+}
+
+// an array's elements, each once, or any other value on its own
+pub(crate) fn elements(value: &Value) -> impl Iterator<Item = &Value> {
+    let values = match value {
+        Value::Array(items) => &items[..],
+        value => std::slice::from_ref(value),
+    };
+    values.iter().enumerate().filter(move |(i, v)| !values[..*i].contains(v)).map(|(_, v)| v)
+}
+
+// the text in a value, an array's strings in order
+fn texts(value: &Value) -> impl Iterator<Item = &str> {
+    let values = match value {
+        Value::Array(items) => &items[..],
+        value => std::slice::from_ref(value),
+    };
+    values.iter().filter_map(Value::as_str)
+}
+
+impl ColumnIndexes {
+    // arrays are indexed element by element, so a search matching any one
+    // of them finds the row. full text takes the whole array as one document
+    #[inline(always)]
+    fn insert(&mut self, value: &Value, row_id: RowId) {
+        for value in elements(value) {
+            self.insert_one(value, row_id);
+        }
+        if let Some(inverted) = &mut self.inverted {
+            if texts(value).next().is_some() {
+                inverted.index_texts(texts(value), row_id);
+            }
+        }
+    }
+
+    #[inline(always)]
+    fn remove(&mut self, value: &Value, row_id: RowId) {
+        for value in elements(value) {
+            self.remove_one(value, row_id);
+        }
+        if let Some(inverted) = &mut self.inverted {
+            if texts(value).next().is_some() {
+                inverted.remove_texts(texts(value), row_id);
+            }
+        }
+    }
+
+    #[inline(always)]
+    fn insert_one(&mut self, value: &Value, row_id: RowId) {
+        if let Some(hash) = &mut self.hash {
+            hash.insert(value, row_id);
+        }
+        if let Some(sorted) = &mut self.sorted {
+            sorted.insert(value, row_id);
+        }
+
+        if let Value::String(s) = value {
+            if let Some(trie) = &mut self.trie {
+                trie.insert(s, row_id);
+            }
+            if let Some(ngram) = &mut self.ngram {
+                ngram.insert(s, row_id);
+            }
+            if let Some(bloom) = &mut self.bloom {
+                bloom.insert(s.as_bytes());
+            }
+        }
+    }
+
+    #[inline(always)]
+    fn remove_one(&mut self, value: &Value, row_id: RowId) {
+        if let Some(hash) = &mut self.hash {
+            hash.remove(value, row_id);
+        }
+        if let Some(sorted) = &mut self.sorted {
+            sorted.remove(value, row_id);
+        }
+
+        if let Value::String(s) = value {
+            if let Some(trie) = &mut self.trie {
+                trie.remove(s, row_id);
+            }
+            if let Some(ngram) = &mut self.ngram {
+                ngram.remove(s, row_id);
+            }
+        }
+    }
+
+    fn new(indexes: IndexSet, col_type: ColumnType, analyzer: &Analyzer, capacity: Option<usize>) -> Self {
+        let exact = indexes.contains(IndexKind::Exact);
+        Self {
+            hash: exact.then(|| match capacity {
+                Some(c) => HashIndex::with_capacity(c),
+                None => HashIndex::new(),
+            }),
+            // only strings go through the bloom filter, other columns would
+            // carry a megabyte of it for nothing
+            bloom: (exact && col_type.element() == ColumnType::String).then(|| match capacity {
+                Some(c) => BloomFilter::new(c, 0.001),
+                None => BloomFilter::new(1_000_000, 0.01),
+            }),
+            inverted: indexes.contains(IndexKind::FullText).then(|| match capacity {
+                Some(c) => InvertedIndex::with_capacity(c / 10),
+                None => InvertedIndex::new(),
+            }.with_analyzer(analyzer.clone())),
+            trie: indexes.contains(IndexKind::Prefix).then(TrieIndex::new),
+            sorted: indexes.contains(IndexKind::Range).then(|| RangeIndex::new(col_type, capacity)),
+            ngram: indexes.contains(IndexKind::Substring).then(NgramIndex::new),
+        }
+    }
+}
+
+// the range index for a column, keyed the way its values order: ints and
+// timestamps as they are, floats by `FloatKey` (nan rows never match),
+// strings byte by byte. arrays get the index of their element type
+enum RangeIndex {
+    Int(SortedIndex<i64>),
+    Float(SortedIndex<FloatKey>),
+    String(SortedIndex<Box<str>>),
+}
+
+impl RangeIndex {
+    fn new(col_type: ColumnType, capacity: Option<usize>) -> Self {
+        fn sorted<K: Ord + Clone>(capacity: Option<usize>) -> SortedIndex<K> {
+            match capacity {
+                Some(c) => SortedIndex::with_capacity(c),
+                None => SortedIndex::new(),
+            }
+        }
+
+        match col_type.element() {
+            ColumnType::Float => Self::Float(sorted(capacity)),
+            ColumnType::String => Self::String(sorted(capacity)),
+            _ => Self::Int(sorted(capacity)),
+        }
+    }
+
+    fn insert(&mut self, value: &Value, row_id: RowId) {
+        match (self, value) {
+            (Self::Int(index), Value::Int(i) | Value::Timestamp(i)) => index.insert(*i, row_id),
+            (Self::Float(index), _) => {
+                if let Some(key) = float_key(value) {
+                    index.insert(key, row_id);
+                }
+            }
+            (Self::String(index), Value::String(s)) => index.insert(s.clone(), row_id),
+            _ => {}
+        }
+    }
+
+    fn remove(&mut self, value: &Value, row_id: RowId) {
+        match (self, value) {
+            (Self::Int(index), Value::Int(i) | Value::Timestamp(i)) => index.remove(*i, row_id),
+            (Self::Float(index), _) => {
+                if let Some(key) = float_key(value) {
+                    index.remove(key, row_id);
+                }
+            }
+            (Self::String(index), Value::String(s)) => index.remove(s.clone(), row_id),
+            _ => {}
+        }
+    }
+
+    // rows between the bounds, by value. bounds are converted to the
+    // column's keys first, numbers for numeric columns and strings for strings
+    // sorted by row id, an array in range on several elements shows up once
+    fn search(&self, min: Bound<&Value>, max: Bound<&Value>) -> Result<Vec<RowId>, SearchError> {
+        let mut ids = match self {
+            Self::Int(index) => both(int_bound(min, true)?, int_bound(max, false)?)
+                .map_or_else(Vec::new, |(min, max)| index.search_bounds(min.as_ref(), max.as_ref())),
+            Self::Float(index) => both(float_bound(min)?, float_bound(max)?)
+                .map_or_else(Vec::new, |(min, max)| index.search_bounds(min.as_ref(), max.as_ref())),
+            Self::String(index) => {
+                let (min, max) = (str_bound(min)?, str_bound(max)?);
+                index.search_bounds(min.as_ref(), max.as_ref())
+            }
+        };
+        ids.sort_unstable();
+        ids.dedup();
+        Ok(ids)
+    }
+
+    fn count(&self, min: Bound<&Value>, max: Bound<&Value>) -> Result<usize, SearchError> {
+        Ok(match self {
+            Self::Int(index) => both(int_bound(min, true)?, int_bound(max, false)?)
+                .map_or(0, |(min, max)| index.count_bounds(min.as_ref(), max.as_ref())),
+            Self::Float(index) => both(float_bound(min)?, float_bound(max)?)
+                .map_or(0, |(min, max)| index.count_bounds(min.as_ref(), max.as_ref())),
+            Self::String(index) => {
+                let (min, max) = (str_bound(min)?, str_bound(max)?);
+                index.count_bounds(min.as_ref(), max.as_ref())
+            }
+        })
+    }
+}
+
+// ints go in float columns as the float they stand for
+fn float_key(value: &Value) -> Option<FloatKey> {
+    match value {
+        Value::Int(i) => FloatKey::new(*i as f64),
+        Value::Float(f) => FloatKey::new(*f),
+        _ => None,
+    }
+}
+
+fn both<T>(min: Option<T>, max: Option<T>) -> Option<(T, T)> {
+    Some((min?, max?))
+}
+
+// a numeric bound over ints. floats round inward, so a min of 2.5 starts at 3
+// and a max of 2.5 stops at 2. `None` when no int can satisfy it
+fn int_bound(bound: Bound<&Value>, is_min: bool) -> Result<Option<Bound<i64>>, SearchError> {
+    const LIMIT: f64 = 9_223_372_036_854_775_808.0; // 2^63
+    let f = match bound {
+        Bound::Unbounded => return Ok(Some(Bound::Unbounded)),
+        Bound::Included(Value::Int(i) | Value::Timestamp(i)) => return Ok(Some(Bound::Included(*i))),
+        Bound::Excluded(Value::Int(i) | Value::Timestamp(i)) => return Ok(Some(Bound::Excluded(*i))),
+        Bound::Included(Value::Float(f)) | Bound::Excluded(Value::Float(f)) => *f,
+        _ => return Err(SearchError::BoundType("numbers")),
+    };
+    if f.is_nan() {
+        return Ok(None);
+    }
+
+    // past either end of i64 a bound either lets everything through or nothing
+    let (beyond, open) = if is_min { (f >= LIMIT, f < -LIMIT) } else { (f < -LIMIT, f >= LIMIT) };
+    if beyond {
+        return Ok(None);
+    }
+    if open {
+        return Ok(Some(Bound::Unbounded));
+    }
+
+    let whole = if is_min { f.ceil() } else { f.floor() };
+    Ok(Some(match bound {
+        Bound::Excluded(_) if whole == f => Bound::Excluded(whole as i64),
+        _ => Bound::Included(whole as i64),
+    }))
+}
+
+// `None` for a nan bound, which nothing is between
+fn float_bound(bound: Bound<&Value>) -> Result<Option<Bound<FloatKey>>, SearchError> {
+    let key = match bound {
+        Bound::Unbounded => return Ok(Some(Bound::Unbounded)),
+        Bound::Included(value) | Bound::Excluded(value) => match value {
+            Value::Int(_) | Value::Float(_) => float_key(value),
+            _ => return Err(SearchError::BoundType("numbers")),
+        },
+    };
+    Ok(key.map(|key| match bound {
+        Bound::Excluded(_) => Bound::Excluded(key),
+        _ => Bound::Included(key),
+    }))
+}
+
+fn str_bound(bound: Bound<&Value>) -> Result<Bound<Box<str>>, SearchError> {
+    match bound {
+        Bound::Unbounded => Ok(Bound::Unbounded),
+        Bound::Included(Value::String(s)) => Ok(Bound::Included(s.clone())),
+        Bound::Excluded(Value::String(s)) => Ok(Bound::Excluded(s.clone())),
+        _ => Err(SearchError::BoundType("strings")),
+    }
+}
+
+fn require<T>(index: &Option<T>, kind: IndexKind) -> Result<&T, SearchError> {
+    index.as_ref().ok_or(SearchError::NotIndexed(kind))
+}
+
+#[inline(always)]
+fn exact(hash: &HashIndex, bloom: Option<&BloomFilter>, value: &Value) -> Vec<RowId> {
+    // use bloom filter for early rejection on strings
+    if let (Value::String(s), Some(bloom)) = (value, bloom) {
+        if !bloom.may_contain(s.as_bytes()) {
+            return Vec::new();
+        }
+    }
+    hash.search(value).to_vec()
+}
+
 pub struct SearchEngine {
-    hash_indexes: Vec<HashIndex>,
-    inverted_indexes: Vec<InvertedIndex>,
-    trie_indexes: Vec<TrieIndex>,
-    sorted_indexes: Vec<SortedIndex>,
-    bloom_filters: Vec<BloomFilter>,
+    columns: Vec<ColumnIndexes>,
 }
 
 impl SearchEngine {
+    // every index on every column, text through the default analyzer and
+    // ranges over ints
     pub fn new(num_columns: usize) -> Self {
         Self {
-            hash_indexes: (0..num_columns).map(|_| HashIndex::new()).collect(),
-            inverted_indexes: (0..num_columns).map(|_| InvertedIndex::new()).collect(),
-            trie_indexes: (0..num_columns).map(|_| TrieIndex::new()).collect(),
-            sorted_indexes: (0..num_columns).map(|_| SortedIndex::new()).collect(),
-            bloom_filters: (0..num_columns)
-                .map(|_| BloomFilter::new(1_000_000, 0.01))
+            columns: (0..num_columns)
+                .map(|_| ColumnIndexes::new(IndexSet::all(), ColumnType::Int, &Analyzer::default(), None))
                 .collect(),
         }
     }
 
     pub fn with_capacity(num_columns: usize, capacity: usize) -> Self {
         Self {
-            hash_indexes: (0..num_columns)
-                .map(|_| HashIndex::with_capacity(capacity))
-                .collect(),
-            inverted_indexes: (0..num_columns)
-                .map(|_| InvertedIndex::with_capacity(capacity / 10))
-                .collect(),
-            trie_indexes: (0..num_columns).map(|_| TrieIndex::new()).collect(),
-            sorted_indexes: (0..num_columns)
-                .map(|_| SortedIndex::with_capacity(capacity))
-                .collect(),
-            bloom_filters: (0..num_columns)
-                .map(|_| BloomFilter::new(capacity, 0.001))
+            columns: (0..num_columns)
+                .map(|_| ColumnIndexes::new(IndexSet::all(), ColumnType::Int, &Analyzer::default(), Some(capacity)))
                 .collect(),
         }
     }
 
+    // only the indexes each column declares
+    pub fn for_columns(columns: &[Column], capacity: Option<usize>) -> Self {
+        Self {
+            columns: columns.iter().map(|c| ColumnIndexes::new(c.indexes, c.col_type, &c.analyzer, capacity)).collect(),
+        }
+    }
+
     #[inline(always)]
     pub fn index_row(&mut self, row_id: RowId, columns: &[Value]) {
-        for (col_idx, value) in columns.iter().enumerate() {
-            if col_idx >= self.hash_indexes.len() {
-                break;
+        for (indexes, value) in self.columns.iter_mut().zip(columns) {
+            indexes.insert(value, row_id);
+        }
+    }
+
+    #[inline(always)]
+    pub fn remove_row(&mut self, row_id: RowId, columns: &[Value]) {
+        for (indexes, value) in self.columns.iter_mut().zip(columns) {
+            indexes.remove(value, row_id);
+        }
+    }
+
+    // empty indexes for a column added at `position`, filled in with `index_value`
+    pub fn insert_column(&mut self, position: usize, column: &Column, capacity: Option<usize>) {
+        let indexes = ColumnIndexes::new(column.indexes, column.col_type, &column.analyzer, capacity);
+        self.columns.insert(position, indexes);
+    }
+
+    // drops a column's indexes, the columns after it shift down one
+    pub fn remove_column(&mut self, position: usize) {
+        self.columns.remove(position);
+    }
+
+    pub fn index_value(&mut self, column: usize, row_id: RowId, value: &Value) {
+        if let Some(indexes) = self.columns.get_mut(column) {
+            indexes.insert(value, row_id);
+        }
+    }
+
+    pub fn remove_value(&mut self, column: usize, row_id: RowId, value: &Value) {
+        if let Some(indexes) = self.columns.get_mut(column) {
+            indexes.remove(value, row_id);
+        }
+    }
+
+    // `rows` backs the indexes: substring candidates are checked against it
+    #[inline(always)]
+    pub fn search(&self, column: usize, search_type: SearchType, rows: &dyn RowSource) -> Result<SearchResult, SearchError> {
+        let indexes = self.columns.get(column).ok_or(SearchError::ColumnOutOfRange(column))?;
+
+        let row_ids = match search_type {
+            SearchType::Exact(ref value) => {
+                exact(require(&indexes.hash, IndexKind::Exact)?, indexes.bloom.as_ref(), value)
+            }
+            SearchType::Prefix(ref prefix) => {
+                // an array shows up once per element that matches
+                let mut ids = require(&indexes.trie, IndexKind::Prefix)?.search_prefix(prefix);
+                ids.sort_unstable();
+                ids.dedup();
+                ids
+            }
+            SearchType::FullText(ref text) => {
+                let inverted = require(&indexes.inverted, IndexKind::FullText)?;
+                inverted.search_text(&inverted.parse_query(text), false)
+            }
+            SearchType::FullTextAny(ref text) => {
+                let inverted = require(&indexes.inverted, IndexKind::FullText)?;
+                inverted.search_text(&inverted.parse_query(text), true)
+            }
+            SearchType::Range { ref min, ref max } => {
+                require(&indexes.sorted, IndexKind::Range)?.search(min.as_ref(), max.as_ref())?
+            }
+            SearchType::Contains(ref pattern) => {
+                let ngram = require(&indexes.ngram, IndexKind::Substring)?;
+                verify(ngram.candidates(pattern, false), rows, column, |s| s.contains(pattern.as_str()))
+            }
+            SearchType::Suffix(ref suffix) => {
+                let ngram = require(&indexes.ngram, IndexKind::Substring)?;
+                verify(ngram.candidates(suffix, true), rows, column, |s| s.ends_with(suffix.as_str()))
+            }
+            SearchType::Fuzzy { .. } | SearchType::FuzzyText { .. } => {
+                self.fuzzy(column, &search_type)?.into_iter().map(|(id, _)| id).collect()
             }
+        };
+
+        Ok(SearchResult::new(row_ids))
+    }
+
+    // row ids matching `query`, sorted and without duplicates
+    pub fn search_query(&self, query: &Query, rows: &dyn RowSource) -> Result<Vec<RowId>, SearchError> {
+        match query {
+            Query::Term { column, search } => {
+                let mut ids = self.search(*column, search.clone(), rows)?.row_ids;
+                ids.sort_unstable();
+                ids.dedup();
+                Ok(ids)
+            }
+            Query::Bool { must, should, must_not, minimum_should_match } => {
+                // cheapest first, so later clauses only narrow an already small set
+                let mut must: Vec<&Query> = must.iter().collect();
+                must.sort_by_cached_key(|q| self.estimate(q));
 
-            self.hash_indexes[col_idx].insert(value, row_id);
+                let mut result: Option<Vec<RowId>> = None;
+                for clause in must {
+                    let ids = self.search_query(clause, rows)?;
+                    let narrowed = match result {
+                        Some(current) => intersect(&current, &ids),
+                        None => ids,
+                    };
+                    if narrowed.is_empty() {
+                        return Ok(narrowed);
+                    }
+                    result = Some(narrowed);
+                }
 
-            match value {
-                Value::String(s) => {
-                    self.inverted_indexes[col_idx].index_text(s, row_id);
-                    self.trie_indexes[col_idx].insert(s, row_id);
-                    self.bloom_filters[col_idx].insert(s.as_bytes());
+                if *minimum_should_match > 0 {
+                    let sets = should.iter()
+                        .map(|q| self.search_query(q, rows))
+                        .collect::<Result<Vec<_>, _>>()?;
+                    let matched = at_least(&sets, *minimum_should_match);
+                    result = Some(match result {
+                        Some(current) => intersect(&current, &matched),
+                        None => matched,
+                    });
                 }
-                Value::Int(i) => {
-                    self.sorted_indexes[col_idx].insert(*i, row_id);
+
+                let mut result = match result {
+                    Some(ids) => ids,
+                    None => {
+                        let mut ids = rows.row_ids();
+                        ids.sort_unstable();
+                        ids
+                    }
+                };
+
+                for clause in must_not {
+                    if result.is_empty() {
+                        break;
+                    }
+                    result = subtract(&result, &self.search_query(clause, rows)?);
                 }
-                _ => {}
+                Ok(result)
             }
         }
     }
 
-    #[inline(always)]
-    pub fn remove_row(&mut self, row_id: RowId, columns: &[Value]) {
-        for (col_idx, value) in columns.iter().enumerate() {
-            if col_idx >= self.hash_indexes.len() {
-                break;
+    // the most common values starting with `prefix`, or with a weight column
+    // the values whose rows add up to the most. counting comes precomputed in
+    // the trie, weights mean summing over every completion, so they're
+    // refused without a prefix rather than walking the whole column. nan
+    // weights are skipped like nulls, as in aggregations
+    pub fn suggest(
+        &self,
+        column: usize,
+        prefix: &str,
+        limit: usize,
+        weight: Option<usize>,
+        rows: &dyn RowSource,
+    ) -> Result<Vec<Suggestion>, SearchError> {
+        let indexes = self.columns.get(column).ok_or(SearchError::ColumnOutOfRange(column))?;
+        let trie = require(&indexes.trie, IndexKind::Prefix)?;
+
+        let weight = match weight {
+            Some(weight) => weight,
+            None => {
+                return Ok(trie
+                    .suggest(prefix, limit)
+                    .into_iter()
+                    .map(|(value, count)| Suggestion { value: value.to_string(), count, weight: None })
+                    .collect());
             }
+        };
+
+        if prefix.is_empty() {
+            return Err(SearchError::WeightWithoutPrefix);
+        }
+
+        let mut suggestions: Vec<Suggestion> = trie
+            .completions(prefix)
+            .into_iter()
+            .map(|(value, ids)| {
+                let total = ids
+                    .iter()
+                    .map(|id| match rows.value(*id, weight) {
+                        Some(Value::Int(n)) => *n as f64,
+                        Some(Value::Float(f)) if !f.is_nan() => *f,
+                        _ => 0.0,
+                    })
+                    .sum();
+                Suggestion { value, count: ids.len(), weight: Some(total) }
+            })
+            .collect();
+        suggestions.sort_unstable_by(|a, b| {
+            b.weight.unwrap_or(0.0).total_cmp(&a.weight.unwrap_or(0.0)).then_with(|| a.value.cmp(&b.value))
+        });
+        suggestions.truncate(limit);
+        Ok(suggestions)
+    }
+
+    // rows whose whole value is within `max_edits` of `value`, with their
+    // distance, by row id
+    pub fn search_fuzzy(&self, column: usize, value: &str, max_edits: u32) -> Result<Vec<(RowId, u32)>, SearchError> {
+        self.fuzzy(column, &SearchType::Fuzzy { value: value.to_string(), max_edits })
+    }
+
+    // rows where every term of `query` is within `max_edits` of a term in the
+    // column, with the distances summed over the terms, by row id
+    pub fn search_fuzzy_text(&self, column: usize, query: &str, max_edits: u32) -> Result<Vec<(RowId, u32)>, SearchError> {
+        self.fuzzy(column, &SearchType::FuzzyText { query: query.to_string(), max_edits })
+    }
 
-            self.hash_indexes[col_idx].remove(value, row_id);
+    fn fuzzy(&self, column: usize, search_type: &SearchType) -> Result<Vec<(RowId, u32)>, SearchError> {
+        let indexes = self.columns.get(column).ok_or(SearchError::ColumnOutOfRange(column))?;
 
-            match value {
-                Value::String(s) => {
-                    self.inverted_indexes[col_idx].remove_text(s, row_id);
-                    self.trie_indexes[col_idx].remove(s, row_id);
+        let mut found = match search_type {
+            SearchType::Fuzzy { value, max_edits } => {
+                let trie = require(&indexes.trie, IndexKind::Prefix)?;
+                trie.search_fuzzy(&automaton(value, *max_edits)?)
+            }
+            SearchType::FuzzyText { query, max_edits } => {
+                let inverted = require(&indexes.inverted, IndexKind::FullText)?;
+                let mut result: Option<HashMap<RowId, u32>> = None;
+                for term in inverted.analyzer().analyze(query) {
+                    // closest spelling per row for this term
+                    let mut rows: HashMap<RowId, u32> = HashMap::new();
+                    for (similar, distance) in inverted.fuzzy_terms(&automaton(&term, *max_edits)?) {
+                        for posting in inverted.postings(similar) {
+                            let best = rows.entry(posting.row_id).or_insert(distance);
+                            *best = (*best).min(distance);
+                        }
+                    }
+                    result = Some(match result {
+                        None => rows,
+                        Some(mut current) => {
+                            current.retain(|id, total| match rows.get(id) {
+                                Some(d) => {
+                                    *total += d;
+                                    true
+                                }
+                                None => false,
+                            });
+                            current
+                        }
+                    });
                 }
-                Value::Int(i) => {
-                    self.sorted_indexes[col_idx].remove(*i, row_id);
+                result.unwrap_or_default().into_iter().collect()
+            }
+            _ => return Ok(Vec::new()),
+        };
+
+        found.sort_unstable();
+        found.dedup_by_key(|(id, _)| *id);
+        Ok(found)
+    }
+
+    // edit distances for the fuzzy clauses that decide what matches, summed
+    // per row. none when the query has no such clause
+    pub fn distances(&self, query: &Query) -> Option<HashMap<RowId, u32>> {
+        let mut distances = HashMap::new();
+        self.add_distances(query, &mut distances).then_some(distances)
+    }
+
+    fn add_distances(&self, query: &Query, distances: &mut HashMap<RowId, u32>) -> bool {
+        match query {
+            Query::Term { column, search: search @ (SearchType::Fuzzy { .. } | SearchType::FuzzyText { .. }) } => {
+                match self.fuzzy(*column, search) {
+                    Ok(found) => {
+                        for (id, distance) in found {
+                            *distances.entry(id).or_default() += distance;
+                        }
+                        true
+                    }
+                    Err(_) => false,
                 }
-                _ => {}
+            }
+            Query::Term { .. } => false,
+            Query::Bool { must, should, .. } => {
+                must.iter().chain(should).fold(false, |found, q| self.add_distances(q, distances) | found)
             }
         }
     }
 
-    #[inline(always)]
-    pub fn search(&mut self, column: usize, search_type: SearchType) -> SearchResult {
-        if column >= self.hash_indexes.len() {
-            return SearchResult::empty();
-        }
+    // bm25 scores for the full-text clauses that decide what matches, i.e.
+    // everything outside `must_not`. none when the query has no such clause
+    pub fn scores(&self, query: &Query, bm25: &Bm25) -> Option<HashMap<RowId, f64>> {
+        let mut scores = HashMap::new();
+        self.add_scores(query, bm25, &mut scores).then_some(scores)
+    }
 
-        let row_ids = match search_type {
-            SearchType::Exact(ref value) => {
-                // use bloom filter for early rejection on strings
-                if let Value::String(s) = value {
-                    if !self.bloom_filters[column].may_contain(s.as_bytes()) {
-                        return SearchResult::empty();
+    fn add_scores(&self, query: &Query, bm25: &Bm25, scores: &mut HashMap<RowId, f64>) -> bool {
+        match query {
+            Query::Term { column, search: SearchType::FullText(text) | SearchType::FullTextAny(text) } => {
+                match self.columns.get(*column).and_then(|c| c.inverted.as_ref()) {
+                    Some(inverted) => {
+                        let query = inverted.parse_query(text);
+                        let terms: Vec<&str> = query.terms().collect();
+                        bm25.score_terms(inverted, &terms, scores);
+                        true
                     }
+                    None => false,
                 }
-                self.hash_indexes[column].search(value).to_vec()
             }
-            SearchType::Prefix(ref prefix) => {
-                self.trie_indexes[column].search_prefix(prefix)
+            Query::Term { .. } => false,
+            Query::Bool { must, should, .. } => {
+                // no short circuit, every clause adds its share
+                must.iter().chain(should).fold(false, |found, q| self.add_scores(q, bm25, scores) | found)
             }
-            SearchType::FullText(ref text) => {
-                let terms: Vec<&str> = text.split_whitespace().collect();
-                if terms.len() == 1 {
-                    self.inverted_indexes[column].search_term(terms[0]).to_vec()
-                } else {
-                    self.inverted_indexes[column].search_terms(&terms)
-                }
+        }
+    }
+
+    // rough number of rows a query matches, read off the indexes without
+    // collecting anything. usize::MAX when there's no cheap answer
+    fn estimate(&self, query: &Query) -> usize {
+        let (column, search) = match query {
+            Query::Term { column, search } => (*column, search),
+            Query::Bool { must, .. } => {
+                return must.iter().map(|q| self.estimate(q)).min().unwrap_or(usize::MAX);
             }
+        };
+        let indexes = match self.columns.get(column) {
+            Some(indexes) => indexes,
+            None => return 0,
+        };
+
+        match search {
+            SearchType::Exact(value) => indexes.hash.as_ref().map_or(0, |h| h.search(value).len()),
+            SearchType::FullText(text) => indexes.inverted.as_ref().map_or(0, |inv| {
+                inv.parse_query(text).terms().map(|t| inv.postings(t).len()).min().unwrap_or(0)
+            }),
+            SearchType::FullTextAny(text) => indexes.inverted.as_ref().map_or(0, |inv| {
+                inv.parse_query(text).terms().map(|t| inv.postings(t).len()).sum()
+            }),
+            SearchType::Contains(pattern) => indexes.ngram.as_ref().map_or(0, |n| n.estimate(pattern, false)),
+            SearchType::Suffix(suffix) => indexes.ngram.as_ref().map_or(0, |n| n.estimate(suffix, true)),
             SearchType::Range { min, max } => {
-                self.sorted_indexes[column].search_range(min, max)
-            }
-            SearchType::Contains(ref substr) => {
-                // fallback to inverted index term search
-                self.inverted_indexes[column].search_term(substr).to_vec()
+                indexes.sorted.as_ref().map_or(0, |s| s.count(min.as_ref(), max.as_ref()).unwrap_or(0))
             }
+            SearchType::Prefix(_) | SearchType::Fuzzy { .. } | SearchType::FuzzyText { .. } => usize::MAX,
+        }
+    }
+
+    // the most common values of a column among `matched` (sorted row ids)
+    // with their counts, ties going to the smaller value, nulls left out.
+    // columns with an exact index and few enough values are counted off its
+    // posting lists, anything else reads the matched rows
+    pub fn facet(&self, facet: Facet, matched: &[RowId], rows: &dyn RowSource) -> Vec<(Value, usize)> {
+        let hash = self.columns
+            .get(facet.column)
+            .and_then(|c| c.hash.as_ref())
+            .filter(|hash| hash.len() <= FACET_INDEX_LIMIT);
+
+        let mut counts: Vec<(&Value, usize)> = match hash {
+            Some(hash) => hash
+                .entries()
+                .filter(|(value, _)| !matches!(value, Value::Null))
+                .map(|(value, ids)| (value, ids.iter().filter(|id| matched.binary_search(id).is_ok()).count()))
+                .filter(|(_, count)| *count > 0)
+                .collect(),
+            None => aggregate::value_counts(facet.column, matched, rows),
         };
+        counts.sort_unstable_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.sort_cmp(b.0)));
+        counts.truncate(facet.size);
+        counts.into_iter().map(|(value, count)| (value.clone(), count)).collect()
+    }
 
-        SearchResult::new(row_ids)
+    // whether `column` keeps an index of this kind
+    pub fn has_index(&self, column: usize, kind: IndexKind) -> bool {
+        self.columns.get(column).is_some_and(|c| match kind {
+            IndexKind::Exact => c.hash.is_some(),
+            IndexKind::Prefix => c.trie.is_some(),
+            IndexKind::FullText => c.inverted.is_some(),
+            IndexKind::Range => c.sorted.is_some(),
+            IndexKind::Substring => c.ngram.is_some(),
+        })
     }
 
+    // the shortcuts below return nothing for a column without the index
+
     #[inline(always)]
     pub fn search_exact(&self, column: usize, value: &Value) -> SearchResult {
-        if column >= self.hash_indexes.len() {
-            return SearchResult::empty();
+        match self.columns.get(column) {
+            Some(ColumnIndexes { hash: Some(hash), bloom, .. }) => SearchResult::new(exact(hash, bloom.as_ref(), value)),
+            _ => SearchResult::empty(),
         }
-        
-        if let Value::String(s) = value {
-            if !self.bloom_filters[column].may_contain(s.as_bytes()) {
-                return SearchResult::empty();
-            }
+    }
+
+    // the ids holding exactly `value`, borrowed straight from the hash index
+    pub fn exact_ids(&self, column: usize, value: &Value) -> &[RowId] {
+        match self.columns.get(column).and_then(|c| c.hash.as_ref()) {
+            Some(hash) => hash.search(value),
+            None => &[],
         }
-        
-        SearchResult::new(self.hash_indexes[column].search(value).to_vec())
     }
 
     #[inline(always)]
     pub fn search_prefix(&self, column: usize, prefix: &str) -> SearchResult {
-        if column >= self.trie_indexes.len() {
-            return SearchResult::empty();
+        match self.columns.get(column).and_then(|c| c.trie.as_ref()) {
+            Some(trie) => SearchResult::new(trie.search_prefix(prefix)),
+            None => SearchResult::empty(),
         }
-        SearchResult::new(self.trie_indexes[column].search_prefix(prefix))
     }
 
     #[inline(always)]
     pub fn search_fulltext(&self, column: usize, query: &str) -> SearchResult {
-        if column >= self.inverted_indexes.len() {
-            return SearchResult::empty();
-        }
-        
-        let terms: Vec<&str> = query.split_whitespace().collect();
-        let row_ids = if terms.len() == 1 {
-            self.inverted_indexes[column].search_term(terms[0]).to_vec()
-        } else {
-            self.inverted_indexes[column].search_terms(&terms)
+        let inverted = match self.columns.get(column).and_then(|c| c.inverted.as_ref()) {
+            Some(inverted) => inverted,
+            None => return SearchResult::empty(),
         };
-        
-        SearchResult::new(row_ids)
+
+        SearchResult::new(inverted.search_text(&inverted.parse_query(query), false))
     }
 
     #[inline(always)]
-    pub fn search_range(&mut self, column: usize, min: i64, max: i64) -> SearchResult {
-        if column >= self.sorted_indexes.len() {
-            return SearchResult::empty();
+    pub fn search_range(&self, column: usize, min: i64, max: i64) -> SearchResult {
+        let (min, max) = (Value::Int(min), Value::Int(max));
+        match self.columns.get(column).and_then(|c| c.sorted.as_ref()) {
+            Some(sorted) => match sorted.search(Bound::Included(&min), Bound::Included(&max)) {
+                Ok(ids) => SearchResult::new(ids),
+                Err(_) => SearchResult::empty(),
+            },
+            None => SearchResult::empty(),
+        }
+    }
+}
+
+fn automaton(text: &str, max_edits: u32) -> Result<LevenshteinAutomaton, SearchError> {
+    if max_edits > MAX_EDITS {
+        return Err(SearchError::TooManyEdits(max_edits));
+    }
+    Ok(LevenshteinAutomaton::new(text, max_edits))
+}
+
+// keep the candidates whose string value, or any string in their array,
+// passes `check`, in row id order. no candidates means every row is one
+fn verify(candidates: Option<Vec<RowId>>, rows: &dyn RowSource, column: usize, check: impl Fn(&str) -> bool) -> Vec<RowId> {
+    let mut ids = candidates.unwrap_or_else(|| {
+        let mut ids = rows.row_ids();
+        ids.sort_unstable();
+        ids
+    });
+    ids.retain(|id| rows.value(*id, column).is_some_and(|v| elements(v).any(|v| matches!(v, Value::String(s) if check(s)))));
+    ids
+}
+
+// set operations over sorted, deduplicated row ids
+
+fn intersect(a: &[RowId], b: &[RowId]) -> Vec<RowId> {
+    let (small, large) = if a.len() <= b.len() { (a, b) } else { (b, a) };
+    small.iter().copied().filter(|id| large.binary_search(id).is_ok()).collect()
+}
+
+fn subtract(a: &[RowId], b: &[RowId]) -> Vec<RowId> {
+    a.iter().copied().filter(|id| b.binary_search(id).is_err()).collect()
+}
+
+// ids present in at least `n` of the sets
+fn at_least(sets: &[Vec<RowId>], n: usize) -> Vec<RowId> {
+    if n > sets.len() {
+        return Vec::new();
+    }
+    let mut all: Vec<RowId> = sets.iter().flatten().copied().collect();
+    all.sort_unstable();
+
+    let mut result = Vec::new();
+    let mut i = 0;
+    while i < all.len() {
+        let run = all[i..].iter().take_while(|id| **id == all[i]).count();
+        if run >= n {
+            result.push(all[i]);
         }
-        SearchResult::new(self.sorted_indexes[column].search_range(min, max))
+        i += run;
     }
+    result
 }
 
 impl Default for SearchEngine {
@@ -223,6 +1002,16 @@ impl Default for SearchEngine {
 mod tests {
     use super::*;
 
+    // index `rows` and keep them in storage under the same ids, 1 and up
+    fn indexed(engine: &mut SearchEngine, rows: Vec<Vec<Value>>) -> Storage {
+        let mut storage = Storage::with_capacity(rows.len());
+        for values in rows {
+            let id = storage.insert(values.clone());
+            engine.index_row(id, &values);
+        }
+        storage
+    }
+
     #[test]
     fn test_search_engine_exact() {
         let mut engine = SearchEngine::new(2);
@@ -271,6 +1060,278 @@ mod tests {
         assert!(result.row_ids.contains(&2));
     }
 
+    #[test]
+    fn test_float_and_string_ranges() {
+        let columns = vec![
+            Column::new("price", ColumnType::Float),
+            Column::new("sku", ColumnType::String).with_indexes(IndexSet::NONE.with(IndexKind::Range)),
+            Column::new("qty", ColumnType::Int),
+        ];
+        let mut engine = SearchEngine::for_columns(&columns, None);
+        let rows = indexed(&mut engine, vec![
+            vec![Value::Float(9.5), Value::String("B-200".into()), Value::Int(3)],
+            vec![Value::Int(20), Value::String("A-100".into()), Value::Int(5)],
+            vec![Value::Float(f64::NAN), Value::String("B-100".into()), Value::Int(8)],
+            vec![Value::Float(20.5), Value::String("C-1".into()), Value::Int(10)],
+        ]);
+        let range = |engine: &SearchEngine, column, min, max| engine.search(column, SearchType::Range { min, max }, &rows).map(|r| r.row_ids);
+
+        // ints in a float column count as floats, nan is never in range
+        assert_eq!(range(&engine, 0, Bound::Included(Value::Int(9)), Bound::Included(Value::Float(20.0))), Ok(vec![1, 2]));
+        assert_eq!(range(&engine, 0, Bound::Excluded(Value::Int(20)), Bound::Unbounded), Ok(vec![4]));
+        assert_eq!(range(&engine, 0, Bound::Unbounded, Bound::Unbounded), Ok(vec![1, 2, 4]));
+        assert_eq!(range(&engine, 0, Bound::Included(Value::Float(f64::NAN)), Bound::Unbounded), Ok(vec![]));
+
+        assert_eq!(range(&engine, 1, Bound::Included(Value::String("B".into())), Bound::Excluded(Value::String("C".into()))), Ok(vec![1, 3]));
+        assert_eq!(range(&engine, 1, Bound::Included(Value::Int(1)), Bound::Unbounded), Err(SearchError::BoundType("strings")));
+        assert_eq!(range(&engine, 0, Bound::Included(Value::String("1".into())), Bound::Unbounded), Err(SearchError::BoundType("numbers")));
+
+        // float bounds on ints round inward
+        assert_eq!(range(&engine, 2, Bound::Included(Value::Float(3.5)), Bound::Included(Value::Float(9.9))), Ok(vec![2, 3]));
+        assert_eq!(range(&engine, 2, Bound::Excluded(Value::Float(5.0)), Bound::Excluded(Value::Float(10.0))), Ok(vec![3]));
+        assert_eq!(range(&engine, 2, Bound::Included(Value::Float(1e300)), Bound::Unbounded), Ok(vec![]));
+        assert_eq!(range(&engine, 2, Bound::Included(Value::Float(-1e300)), Bound::Excluded(Value::Float(4.0))), Ok(vec![1]));
+
+        engine.remove_row(2, &[Value::Int(20), Value::String("A-100".into()), Value::Int(5)]);
+        assert_eq!(range(&engine, 0, Bound::Unbounded, Bound::Included(Value::Int(20))), Ok(vec![1]));
+        assert_eq!(range(&engine, 1, Bound::Unbounded, Bound::Excluded(Value::String("B".into()))), Ok(vec![]));
+    }
+
+    #[test]
+    fn test_facets() {
+        // the same values with and without an exact index to count from
+        let columns = vec![
+            Column::new("brand", ColumnType::String),
+            Column::new("brand_scanned", ColumnType::String).with_indexes(IndexSet::NONE),
+            Column::new("price", ColumnType::Int),
+        ];
+        let mut engine = SearchEngine::for_columns(&columns, None);
+        let brands = ["acme", "globex", "acme", "initech", "globex", "acme", "initech"];
+        let rows = indexed(&mut engine, brands.iter().enumerate().map(|(i, brand)| {
+            let brand = if i == 6 { Value::Null } else { Value::String((*brand).into()) };
+            vec![brand.clone(), brand, Value::Int(i as i64)]
+        }).collect());
+
+        let matched = engine.search_query(&Query::term(2, SearchType::between(Value::Int(1), Value::Int(6))), &rows).unwrap();
+        for column in [0, 1] {
+            let counts = engine.facet(Facet { column, size: 10 }, &matched, &rows);
+            assert_eq!(counts, vec![
+                (Value::String("acme".into()), 2),
+                (Value::String("globex".into()), 2),
+                (Value::String("initech".into()), 1),
+            ]);
+            assert_eq!(engine.facet(Facet { column, size: 1 }, &matched, &rows), vec![(Value::String("acme".into()), 2)]);
+            assert!(engine.facet(Facet { column, size: 10 }, &[], &rows).is_empty());
+        }
+    }
+
+    #[test]
+    fn test_declared_indexes_only() {
+        let columns = vec![
+            Column::new("email", ColumnType::String).with_indexes(IndexSet::NONE.with(IndexKind::Exact)),
+            Column::new("age", ColumnType::Int).with_indexes(IndexSet::NONE),
+        ];
+        let mut engine = SearchEngine::for_columns(&columns, None);
+        let rows = indexed(&mut engine, vec![vec![Value::String("a@b.c".into()), Value::Int(30)]]);
+
+        assert!(engine.has_index(0, IndexKind::Exact));
+        assert!(!engine.has_index(0, IndexKind::Prefix));
+        assert!(!engine.has_index(1, IndexKind::Range));
+
+        let found = engine.search(0, SearchType::Exact(Value::String("a@b.c".into())), &rows).unwrap();
+        assert_eq!(found.row_ids, vec![1]);
+        assert_eq!(
+            engine.search(0, SearchType::Prefix("a".into()), &rows).err(),
+            Some(SearchError::NotIndexed(IndexKind::Prefix))
+        );
+        assert_eq!(
+            engine.search(1, SearchType::between(Value::Int(0), Value::Int(100)), &rows).err(),
+            Some(SearchError::NotIndexed(IndexKind::Range))
+        );
+        assert_eq!(
+            engine.search(0, SearchType::Contains("a".into()), &rows).err(),
+            Some(SearchError::NotIndexed(IndexKind::Substring))
+        );
+        assert_eq!(engine.search(2, SearchType::Contains("a".into()), &rows).err(), Some(SearchError::ColumnOutOfRange(2)));
+    }
+
+    #[test]
+    fn test_bloom_only_for_strings() {
+        let columns = vec![
+            Column::new("email", ColumnType::String),
+            Column::new("tags", ColumnType::Array(&ColumnType::String)),
+            Column::new("age", ColumnType::Int),
+            Column::new("id", ColumnType::Uuid),
+        ];
+        let engine = SearchEngine::for_columns(&columns, None);
+        let blooms: Vec<bool> = engine.columns.iter().map(|c| c.bloom.is_some()).collect();
+        assert_eq!(blooms, vec![true, true, false, false]);
+        assert!(engine.has_index(2, IndexKind::Exact));
+    }
+
+    #[test]
+    fn test_weighted_suggest() {
+        let columns = vec![Column::new("term", ColumnType::String), Column::new("clicks", ColumnType::Float)];
+        let mut engine = SearchEngine::for_columns(&columns, None);
+        let row = |term: &str, clicks: f64| vec![Value::String(term.into()), Value::Float(clicks)];
+        let rows = indexed(&mut engine, vec![row("rust", 1.0), row("rust", f64::NAN), row("ruby", 3.0), row("rune", 2.0)]);
+
+        // a nan weight leaves the rest of the value's sum alone
+        let found = engine.suggest(0, "ru", 10, Some(1), &rows).unwrap();
+        let ranked: Vec<(&str, f64)> = found.iter().map(|s| (s.value.as_str(), s.weight.unwrap())).collect();
+        assert_eq!(ranked, vec![("ruby", 3.0), ("rune", 2.0), ("rust", 1.0)]);
+        assert_eq!(found[2].count, 2);
+
+        assert_eq!(engine.suggest(0, "", 10, Some(1), &rows).err(), Some(SearchError::WeightWithoutPrefix));
+        assert_eq!(engine.suggest(0, "", 10, None, &rows).unwrap().len(), 3);
+    }
+
+    #[test]
+    fn test_bool_query() {
+        let mut engine = SearchEngine::new(3);
+        let rows = [
+            ("alice", 30, "active"),
+            ("alan", 45, "active"),
+            ("albert", 25, "banned"),
+            ("bob", 35, "active"),
+        ];
+        let all_rows = indexed(&mut engine, rows.iter().map(|(name, age, status)| vec![
+            Value::String((*name).into()),
+            Value::Int(*age),
+            Value::String((*status).into()),
+        ]).collect());
+
+        // name prefix "al" and age 20..40 and not banned
+        let query = Query::bool(
+            vec![
+                Query::term(0, SearchType::Prefix("al".into())),
+                Query::term(1, SearchType::between(Value::Int(20), Value::Int(40))),
+            ],
+            vec![],
+            vec![Query::term(2, SearchType::Exact(Value::String("banned".into())))],
+        );
+        assert_eq!(engine.search_query(&query, &all_rows).unwrap(), vec![1]);
+
+        // should alone means any of them, must_not alone starts from every row
+        let query = Query::bool(vec![], vec![
+            Query::term(0, SearchType::Exact(Value::String("bob".into()))),
+            Query::term(1, SearchType::between(Value::Int(40), Value::Int(50))),
+        ], vec![]);
+        assert_eq!(engine.search_query(&query, &all_rows).unwrap(), vec![2, 4]);
+
+        let query = Query::bool(vec![], vec![], vec![Query::term(2, SearchType::Exact(Value::String("active".into())))]);
+        assert_eq!(engine.search_query(&query, &all_rows).unwrap(), vec![3]);
+
+        let query = Query::Bool {
+            must: vec![],
+            should: vec![
+                Query::term(0, SearchType::Prefix("al".into())),
+                Query::term(1, SearchType::between(Value::Int(30), Value::Int(100))),
+                Query::term(2, SearchType::Exact(Value::String("active".into()))),
+            ],
+            must_not: vec![],
+            minimum_should_match: 3,
+        };
+        assert_eq!(engine.search_query(&query, &all_rows).unwrap(), vec![1, 2]);
+    }
+
+    #[test]
+    fn test_bm25_scores() {
+        let mut engine = SearchEngine::new(2);
+        let docs = [
+            "rust rust rust",
+            "rust is a systems language with a long description",
+            "python",
+            "rust and python",
+        ];
+        let all_rows = indexed(&mut engine, docs.iter().enumerate().map(|(i, text)| {
+            vec![Value::String((*text).into()), Value::Int(i as i64)]
+        }).collect());
+
+        // repeating a term in short text beats one mention in long text
+        let query = Query::term(0, SearchType::FullText("rust".into()));
+        let scores = engine.scores(&query, &Bm25::default()).unwrap();
+        assert!(scores[&1] > scores[&4]);
+        assert!(scores[&4] > scores[&2]);
+        assert!(!scores.contains_key(&3));
+
+        // or mode matches either term, rows with both score highest
+        let query = Query::term(0, SearchType::FullTextAny("rust, python".into()));
+        assert_eq!(engine.search_query(&query, &all_rows).unwrap(), vec![1, 2, 3, 4]);
+        let scores = engine.scores(&query, &Bm25::default()).unwrap();
+        assert!(scores.values().all(|s| *s <= scores[&4]));
+
+        // only full-text clauses score, must_not ones don't count
+        let query = Query::bool(
+            vec![Query::term(1, SearchType::between(Value::Int(0), Value::Int(10)))],
+            vec![],
+            vec![Query::term(0, SearchType::FullText("python".into()))],
+        );
+        assert!(engine.scores(&query, &Bm25::default()).is_none());
+    }
+
+    #[test]
+    fn test_substring_search() {
+        let mut engine = SearchEngine::new(1);
+        let rows = indexed(&mut engine, vec![
+            vec![Value::String("alice".into())],
+            vec![Value::String("malice aforethought".into())],
+            vec![Value::String("bob".into())],
+            vec![Value::Int(7)],
+            vec![Value::String("héllo wörld".into())],
+        ]);
+        let search = |search_type| engine.search(0, search_type, &rows).unwrap().row_ids;
+
+        // inside tokens, across them, and short patterns that fit in a gram
+        assert_eq!(search(SearchType::Contains("lic".into())), vec![1, 2]);
+        assert_eq!(search(SearchType::Contains("ce af".into())), vec![2]);
+        assert_eq!(search(SearchType::Contains("o".into())), vec![2, 3, 5]);
+        assert_eq!(search(SearchType::Contains("lo wö".into())), vec![5]);
+        assert!(search(SearchType::Contains("alicex".into())).is_empty());
+        // every gram present but not in that order: dropped on verification
+        assert!(search(SearchType::Contains("licem".into())).is_empty());
+        // empty pattern: every string
+        assert_eq!(search(SearchType::Contains("".into())), vec![1, 2, 3, 5]);
+
+        assert_eq!(search(SearchType::Suffix("ice".into())), vec![1]);
+        assert_eq!(search(SearchType::Suffix("b".into())), vec![3]);
+        assert_eq!(search(SearchType::Suffix("alice".into())), vec![1]);
+        assert!(search(SearchType::Suffix("al".into())).is_empty());
+    }
+
+    #[test]
+    fn test_fuzzy_search() {
+        let mut engine = SearchEngine::new(1);
+        let rows = indexed(&mut engine, vec![
+            vec![Value::String("jonathan smith".into())],
+            vec![Value::String("jonathon smyth".into())],
+            vec![Value::String("john smith".into())],
+        ]);
+
+        assert_eq!(engine.search_fuzzy(0, "jonathan smith", 0).unwrap(), vec![(1, 0)]);
+        assert_eq!(engine.search_fuzzy(0, "jonathan smith", 2).unwrap(), vec![(1, 0), (2, 2)]);
+
+        // per term: the closest spelling, summed over the terms
+        assert_eq!(engine.search_fuzzy_text(0, "smith", 1).unwrap(), vec![(1, 0), (2, 1), (3, 0)]);
+        assert_eq!(engine.search_fuzzy_text(0, "jonathen smith", 1).unwrap(), vec![(1, 1), (2, 2)]);
+        // terms go through the analyzer first
+        assert_eq!(engine.search_fuzzy_text(0, "Jonathan SMITH", 0).unwrap(), vec![(1, 0)]);
+
+        let found = engine.search(0, SearchType::FuzzyText { query: "jon".into(), max_edits: 1 }, &rows).unwrap();
+        assert_eq!(found.row_ids, vec![3]);
+        assert!(matches!(engine.search_fuzzy(0, "x", 3), Err(SearchError::TooManyEdits(3))));
+
+        let query = Query::bool(
+            vec![Query::term(0, SearchType::FuzzyText { query: "smyth".into(), max_edits: 1 })],
+            vec![],
+            vec![],
+        );
+        let distances = engine.distances(&query).unwrap();
+        assert_eq!(distances.get(&2), Some(&0));
+        assert_eq!(distances.get(&1), Some(&1));
+        assert!(engine.distances(&Query::term(0, SearchType::Prefix("j".into()))).is_none());
+    }
+
     #[test]
     fn test_search_result_pagination() {
         let result = SearchResult::new(vec![1, 2, 3, 4, 5]);
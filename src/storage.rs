@@ -1,5 +1,6 @@
+use std::cmp::Ordering;
 use std::collections::HashMap;
-use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::atomic::{self, AtomicU64};
 
 pub type RowId = u64;
 
@@ -16,6 +17,10 @@ pub enum Value {
     Float(f64),
     String(Box<str>),
     Bytes(Box<[u8]>),
+    Bool(bool),
+    Timestamp(i64),         // microseconds since the unix epoch, utc
+    Uuid([u8; 16]),
+    Array(Box<[Value]>),
 }
 
 impl Value {
@@ -42,6 +47,212 @@ impl Value {
             _ => None,
         }
     }
+
+    #[inline(always)]
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Value::Bool(b) => Some(*b),
+            _ => None,
+        }
+    }
+
+    // total order used for sorting results. false before true, numbers
+    // compare by value across int and float (nan after every other number),
+    // then timestamps, strings, uuids, bytes and arrays element by element.
+    // nulls sort first here, callers decide where they go
+    pub fn sort_cmp(&self, other: &Value) -> Ordering {
+        fn rank(v: &Value) -> u8 {
+            match v {
+                Value::Null => 0,
+                Value::Bool(_) => 1,
+                Value::Int(_) | Value::Float(_) => 2,
+                Value::Timestamp(_) => 3,
+                Value::String(_) => 4,
+                Value::Uuid(_) => 5,
+                Value::Bytes(_) => 6,
+                Value::Array(_) => 7,
+            }
+        }
+
+        match (self, other) {
+            (Value::Int(a), Value::Int(b)) => a.cmp(b),
+            (Value::Float(a), Value::Float(b)) => cmp_floats(*a, *b),
+            (Value::Int(a), Value::Float(b)) => cmp_int_float(*a, *b),
+            (Value::Float(a), Value::Int(b)) => cmp_int_float(*b, *a).reverse(),
+            (Value::String(a), Value::String(b)) => a.cmp(b),
+            (Value::Bytes(a), Value::Bytes(b)) => a.cmp(b),
+            (Value::Bool(a), Value::Bool(b)) => a.cmp(b),
+            (Value::Timestamp(a), Value::Timestamp(b)) => a.cmp(b),
+            (Value::Uuid(a), Value::Uuid(b)) => a.cmp(b),
+            (Value::Array(a), Value::Array(b)) => a
+                .iter()
+                .zip(b.iter())
+                .map(|(a, b)| a.sort_cmp(b))
+                .find(|o| o.is_ne())
+                .unwrap_or_else(|| a.len().cmp(&b.len())),
+            _ => rank(self).cmp(&rank(other)),
+        }
+    }
+}
+
+const MICROS: i64 = 1_000_000;
+
+// an rfc 3339 timestamp as microseconds since the epoch. the `T` may be a
+// space and the offset left out for utc, so `2024-05-01 12:00:00` and plain
+// dates parse too. digits past microseconds are dropped
+pub fn parse_timestamp(s: &str) -> Option<i64> {
+    fn number(s: &[u8]) -> Option<i64> {
+        s.iter().try_fold(0i64, |n, b| b.is_ascii_digit().then(|| n * 10 + (b - b'0') as i64))
+    }
+
+    let s = s.trim().as_bytes();
+    if s.len() < 10 || s[4] != b'-' || s[7] != b'-' {
+        return None;
+    }
+    let (year, month, day) = (number(&s[..4])?, number(&s[5..7])?, number(&s[8..10])?);
+    if !(1..=12).contains(&month) || day < 1 || day > days_in_month(year, month) {
+        return None;
+    }
+    let mut micros = days_from_civil(year, month, day) * 86_400 * MICROS;
+    let rest = &s[10..];
+    if rest.is_empty() {
+        return Some(micros);
+    }
+
+    if !matches!(rest[0], b'T' | b't' | b' ') || rest.len() < 9 || rest[3] != b':' || rest[6] != b':' {
+        return None;
+    }
+    let (hour, minute, second) = (number(&rest[1..3])?, number(&rest[4..6])?, number(&rest[7..9])?);
+    // 60 is a leap second, folded into the next minute
+    if hour > 23 || minute > 59 || second > 60 {
+        return None;
+    }
+    micros += (hour * 3600 + minute * 60 + second) * MICROS;
+
+    let mut rest = &rest[9..];
+    if let Some(fraction) = rest.strip_prefix(b".") {
+        let digits = fraction.iter().take_while(|b| b.is_ascii_digit()).count();
+        if digits == 0 {
+            return None;
+        }
+        let kept = &fraction[..digits.min(6)];
+        micros += number(kept)? * 10i64.pow(6 - kept.len() as u32);
+        rest = &fraction[digits..];
+    }
+
+    match rest {
+        [] | [b'Z'] | [b'z'] => Some(micros),
+        [sign @ (b'+' | b'-'), h1, h2, b':', m1, m2] => {
+            let (hours, minutes) = (number(&[*h1, *h2])?, number(&[*m1, *m2])?);
+            if hours > 23 || minutes > 59 {
+                return None;
+            }
+            let offset = (hours * 3600 + minutes * 60) * MICROS;
+            Some(if *sign == b'+' { micros - offset } else { micros + offset })
+        }
+        _ => None,
+    }
+}
+
+// rfc 3339 in utc, with a fraction only when there is one
+pub fn format_timestamp(micros: i64) -> String {
+    let seconds = micros.div_euclid(MICROS);
+    let fraction = micros.rem_euclid(MICROS);
+    let (year, month, day) = civil_from_days(seconds.div_euclid(86_400));
+    let time = seconds.rem_euclid(86_400);
+    let mut out = format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+        year, month, day, time / 3600, time / 60 % 60, time % 60
+    );
+    if fraction != 0 {
+        out.push_str(format!(".{:06}", fraction).trim_end_matches('0'));
+    }
+    out.push('Z');
+    out
+}
+
+fn days_in_month(year: i64, month: i64) -> i64 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+// days since 1970-01-01 for a proleptic gregorian date, and back
+// (howard hinnant's algorithms)
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 };
+    (year_of_era + era * 400 + i64::from(month <= 2), month, day)
+}
+
+// a uuid written as 32 hex digits, hyphenated (`67e55044-10b1-426f-9247-bb680e5fe0c8`) or not
+pub fn parse_uuid(s: &str) -> Option<[u8; 16]> {
+    let s = s.trim();
+    let hex: Vec<u8> = match s.len() {
+        32 => s.bytes().collect(),
+        36 if [8, 13, 18, 23].iter().all(|&i| s.as_bytes()[i] == b'-') => s.bytes().filter(|&b| b != b'-').collect(),
+        _ => return None,
+    };
+    if hex.len() != 32 || !hex.iter().all(u8::is_ascii_hexdigit) {
+        return None;
+    }
+    let mut uuid = [0u8; 16];
+    for (byte, pair) in uuid.iter_mut().zip(hex.chunks(2)) {
+        *byte = u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok()?;
+    }
+    Some(uuid)
+}
+
+pub fn format_uuid(uuid: &[u8; 16]) -> String {
+    let mut out = String::with_capacity(36);
+    for (i, byte) in uuid.iter().enumerate() {
+        if matches!(i, 4 | 6 | 8 | 10) {
+            out.push('-');
+        }
+        out.push_str(&format!("{:02x}", byte));
+    }
+    out
+}
+
+// nan after every number, -0.0 equal to 0.0 so ints compare consistently
+fn cmp_floats(a: f64, b: f64) -> Ordering {
+    match (a.is_nan(), b.is_nan()) {
+        (true, true) => Ordering::Equal,
+        (true, false) => Ordering::Greater,
+        (false, true) => Ordering::Less,
+        (false, false) => a.partial_cmp(&b).unwrap_or(Ordering::Equal),
+    }
+}
+
+// exact for the whole i64 range, unlike converting the int to a float
+fn cmp_int_float(i: i64, f: f64) -> Ordering {
+    const LIMIT: f64 = 9_223_372_036_854_775_808.0; // 2^63
+    if f.is_nan() || f >= LIMIT {
+        return Ordering::Less;
+    }
+    if f < -LIMIT {
+        return Ordering::Greater;
+    }
+    let whole = f.trunc();
+    i.cmp(&(whole as i64)).then_with(|| cmp_floats(0.0, f - whole))
 }
 
 pub struct Storage {
@@ -66,12 +277,18 @@ impl Storage {
 
     #[inline(always)]
     pub fn insert(&mut self, columns: Vec<Value>) -> RowId {
-        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let id = self.next_id.fetch_add(1, atomic::Ordering::Relaxed);
         let row = Row { id, columns };
         self.rows.insert(id, row);
         id
     }
 
+    // insert a row under a known id (snapshot restore), keeping next_id ahead of it
+    pub fn insert_with_id(&mut self, id: RowId, columns: Vec<Value>) {
+        self.next_id.fetch_max(id + 1, atomic::Ordering::Relaxed);
+        self.rows.insert(id, Row { id, columns });
+    }
+
     #[inline(always)]
     pub fn get(&self, id: RowId) -> Option<&Row> {
         self.rows.get(&id)
@@ -92,6 +309,13 @@ impl Storage {
         }
     }
 
+    // replace one column of a row, handing back what it held
+    #[inline(always)]
+    pub fn set(&mut self, id: RowId, column: usize, value: Value) -> Option<Value> {
+        let slot = self.rows.get_mut(&id)?.columns.get_mut(column)?;
+        Some(std::mem::replace(slot, value))
+    }
+
     #[inline(always)]
     pub fn len(&self) -> usize {
         self.rows.len()
@@ -106,6 +330,18 @@ impl Storage {
         self.rows.values()
     }
 
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut Row> {
+        self.rows.values_mut()
+    }
+
+    pub fn next_id(&self) -> RowId {
+        self.next_id.load(atomic::Ordering::Relaxed)
+    }
+
+    pub fn set_next_id(&mut self, id: RowId) {
+        self.next_id.fetch_max(id, atomic::Ordering::Relaxed);
+    }
+
     #[inline(always)]
     pub fn get_many(&self, ids: &[RowId]) -> Vec<&Row> {
         ids.iter().filter_map(|id| self.rows.get(id)).collect()
@@ -131,6 +367,74 @@ mod tests {
         assert_eq!(row.columns[0], Value::String("test".into()));
     }
 
+    #[test]
+    fn test_value_sort_order() {
+        let mut values = vec![
+            Value::String("b".into()),
+            Value::Float(f64::NAN),
+            Value::Int(2),
+            Value::Null,
+            Value::Float(1.5),
+            Value::Bytes(vec![0].into_boxed_slice()),
+            Value::Int(i64::MAX),
+            Value::Float(-0.5),
+            Value::String("a".into()),
+            Value::Int(-1),
+        ];
+        values.sort_by(|a, b| a.sort_cmp(b));
+
+        assert_eq!(values[0], Value::Null);
+        assert_eq!(&values[1..6], &[
+            Value::Int(-1),
+            Value::Float(-0.5),
+            Value::Float(1.5),
+            Value::Int(2),
+            Value::Int(i64::MAX),
+        ]);
+        assert!(matches!(values[6], Value::Float(f) if f.is_nan()));
+        assert_eq!(values[7], Value::String("a".into()));
+        assert_eq!(values[9], Value::Bytes(vec![0].into_boxed_slice()));
+        assert_eq!(Value::Int(1).sort_cmp(&Value::Float(1.0)), Ordering::Equal);
+        assert_eq!(Value::Int(0).sort_cmp(&Value::Float(-0.0)), Ordering::Equal);
+        assert_eq!(Value::Float(0.0).sort_cmp(&Value::Float(-0.0)), Ordering::Equal);
+
+        let short = Value::Array(vec![Value::Int(1)].into());
+        let long = Value::Array(vec![Value::Int(1), Value::Int(0)].into());
+        assert_eq!(short.sort_cmp(&long), Ordering::Less);
+        assert_eq!(Value::Bool(true).sort_cmp(&Value::Int(0)), Ordering::Less);
+        assert_eq!(Value::Timestamp(0).sort_cmp(&Value::String("".into())), Ordering::Less);
+    }
+
+    #[test]
+    fn test_timestamps() {
+        assert_eq!(parse_timestamp("1970-01-01T00:00:00Z"), Some(0));
+        assert_eq!(parse_timestamp("2024-02-29"), Some(1_709_164_800_000_000));
+        assert_eq!(parse_timestamp("2024-05-01 12:00:00"), Some(1_714_564_800_000_000));
+        assert_eq!(parse_timestamp("2024-05-01t14:00:00.25+02:00"), Some(1_714_564_800_250_000));
+        assert_eq!(parse_timestamp("1969-12-31T23:59:59.9999999Z"), Some(-1));
+        for bad in ["2023-02-29", "2024-13-01", "2024-05-01T25:00:00Z", "2024-05-01T12:00", "2024-05-01T12:00:00+2", "yesterday"] {
+            assert_eq!(parse_timestamp(bad), None, "{}", bad);
+        }
+
+        assert_eq!(format_timestamp(0), "1970-01-01T00:00:00Z");
+        assert_eq!(format_timestamp(-1), "1969-12-31T23:59:59.999999Z");
+        assert_eq!(format_timestamp(1_714_564_800_250_000), "2024-05-01T12:00:00.25Z");
+        for micros in [0, -1, 951_782_400_000_000, 253_402_300_799_999_999] {
+            assert_eq!(parse_timestamp(&format_timestamp(micros)), Some(micros));
+        }
+    }
+
+    #[test]
+    fn test_uuids() {
+        let uuid = parse_uuid("67e55044-10b1-426f-9247-bb680e5fe0c8").unwrap();
+        assert_eq!(uuid[0], 0x67);
+        assert_eq!(parse_uuid("67E5504410B1426F9247BB680E5FE0C8"), Some(uuid));
+        assert_eq!(format_uuid(&uuid), "67e55044-10b1-426f-9247-bb680e5fe0c8");
+        assert_eq!(parse_uuid("67e55044-10b1-426f-9247-bb680e5fe0c"), None);
+        assert_eq!(parse_uuid("67e55044-10b1-426f-9247+bb680e5fe0c8"), None);
+        assert_eq!(parse_uuid("+7e5504410b1426f9247bb680e5fe0c8"), None);
+    }
+
     #[test]
     fn test_storage_delete() {
         let mut storage = Storage::new();
@@ -148,6 +452,11 @@ mod tests {
         
         storage.update(id, vec![Value::Int(2)]);
         assert_eq!(storage.get(id).unwrap().columns[0], Value::Int(2));
+
+        assert_eq!(storage.set(id, 0, Value::Int(3)), Some(Value::Int(2)));
+        assert_eq!(storage.get(id).unwrap().columns[0], Value::Int(3));
+        assert_eq!(storage.set(id, 1, Value::Int(4)), None);
+        assert_eq!(storage.set(id + 1, 0, Value::Int(4)), None);
     }
 
     #[test]
@@ -159,15 +468,28 @@ mod tests {
         assert_eq!(storage.len(), 1);
     }
 
+    #[test]
+    fn test_storage_insert_with_id() {
+        let mut storage = Storage::new();
+        storage.insert_with_id(41, vec![Value::Int(1)]);
+
+        assert_eq!(storage.next_id(), 42);
+        assert_eq!(storage.insert(vec![Value::Int(2)]), 42);
+
+        // restoring an older id never moves next_id backwards
+        storage.insert_with_id(7, vec![Value::Int(3)]);
+        assert_eq!(storage.next_id(), 43);
+    }
+
     #[test]
     fn test_value_accessors() {
         let s = Value::String("hello".into());
         let i = Value::Int(42);
-        let f = Value::Float(3.14);
+        let f = Value::Float(2.5);
 
         assert_eq!(s.as_str(), Some("hello"));
         assert_eq!(i.as_int(), Some(42));
-        assert_eq!(f.as_float(), Some(3.14));
+        assert_eq!(f.as_float(), Some(2.5));
         assert_eq!(s.as_int(), None);
     }
 }
@@ -5,7 +5,7 @@ use std::io::{BufRead, BufReader, Read, Write};
 use std::net::TcpStream;
 use std::time::Duration;
 
-use crate::storage::Value;
+use crate::storage::{parse_timestamp, parse_uuid, Value};
 use crate::table::ColumnType;
 use crate::{log_debug, log_info};
 
@@ -206,7 +206,65 @@ impl ClickHouseSource {
             ColumnType::Bytes => {
                 Value::Bytes(s.as_bytes().to_vec().into_boxed_slice())
             }
+            ColumnType::Bool => match s {
+                "1" | "true" => Value::Bool(true),
+                "0" | "false" => Value::Bool(false),
+                _ => Value::Null,
+            },
+            // DateTime and DateTime64 come as `2024-05-01 12:00:00[.123]`
+            ColumnType::Timestamp => {
+                parse_timestamp(s).map(Value::Timestamp).unwrap_or(Value::Null)
+            }
+            ColumnType::Uuid => parse_uuid(s).map(Value::Uuid).unwrap_or(Value::Null),
+            ColumnType::Array(element) => match Self::split_array(s) {
+                Some(items) => Value::Array(
+                    items
+                        .into_iter()
+                        .map(|item| match (element, item) {
+                            (ColumnType::String, (true, text)) => Value::String(text.into_boxed_str()),
+                            (_, (_, text)) => Self::parse_value(&text, *element),
+                        })
+                        .filter(|v| *v != Value::Null)
+                        .collect(),
+                ),
+                None => Value::Null,
+            },
+        }
+    }
+
+    // the elements of an array like `['a','b\'c']` or `[1,2]`, each with
+    // whether it was quoted. quoted ones come back unescaped
+    fn split_array(s: &str) -> Option<Vec<(bool, String)>> {
+        let inner = s.strip_prefix('[')?.strip_suffix(']')?;
+        let mut items = Vec::new();
+        let mut chars = inner.chars();
+        let (mut item, mut quoted) = (String::new(), false);
+        while let Some(c) = chars.next() {
+            match c {
+                '\'' => {
+                    quoted = true;
+                    loop {
+                        match chars.next()? {
+                            '\'' => break,
+                            '\\' => item.push(match chars.next()? {
+                                't' => '\t',
+                                'n' => '\n',
+                                '0' => '\0',
+                                other => other,
+                            }),
+                            other => item.push(other),
+                        }
+                    }
+                }
+                ',' => items.push((std::mem::take(&mut quoted), std::mem::take(&mut item))),
+                c if c.is_whitespace() && !quoted => {}
+                c => item.push(c),
+            }
+        }
+        if quoted || !item.is_empty() || !items.is_empty() {
+            items.push((quoted, item));
         }
+        Some(items)
     }
 
     // parse tsv response into rows
@@ -330,5 +388,18 @@ mod tests {
             ClickHouseSource::parse_value("\\N", ColumnType::Int),
             Value::Null
         );
+        assert_eq!(
+            ClickHouseSource::parse_value("2024-05-01 12:00:00", ColumnType::Timestamp),
+            Value::Timestamp(1_714_564_800_000_000)
+        );
+        assert_eq!(
+            ClickHouseSource::parse_value("['a','b\\'c', '']", ColumnType::array_of(ColumnType::String).unwrap()),
+            Value::Array(vec![Value::String("a".into()), Value::String("b'c".into()), Value::String("".into())].into())
+        );
+        assert_eq!(
+            ClickHouseSource::parse_value("[]", ColumnType::array_of(ColumnType::Int).unwrap()),
+            Value::Array(Box::new([]))
+        );
+        assert_eq!(ClickHouseSource::parse_value("true", ColumnType::Bool), Value::Bool(true));
     }
 }
@@ -1,12 +1,13 @@
 // sync manager - coordinates syncing data from sources to quickset
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::{Arc, RwLock};
 use std::thread;
 use std::time::{Duration, Instant};
 
-use crate::table::{Column, Database};
+use crate::storage::{RowId, Value};
+use crate::table::{Coercion, Column, Database, Table, TableError};
 use crate::{log_debug, log_error, log_info, log_warn};
 
 use super::source::{Source, SyncTable};
@@ -28,6 +29,7 @@ pub struct SyncResult {
     pub table: String,
     pub success: bool,
     pub rows_synced: usize,
+    pub rows_failed: usize,     // rows the source sent that didn't fit the table
     pub duration_ms: u64,
     pub error: Option<String>,
 }
@@ -135,6 +137,7 @@ impl SyncManager {
                     table: target.clone(),
                     success: false,
                     rows_synced: 0,
+                    rows_failed: 0,
                     duration_ms: start.elapsed().as_millis() as u64,
                     error: Some(error_msg),
                 };
@@ -143,62 +146,85 @@ impl SyncManager {
 
         log_debug!("sync", "fetched {} rows from source for {}", fetch_result.row_count, target);
 
-        // update quickset
-        let mut db = db.write().unwrap();
-
-        // optionally clear and recreate table
-        if self.config.clear_before_sync {
-            let _ = db.drop_table(target);
-            
-            let columns: Vec<Column> = table.columns.iter()
-                .map(|c| Column {
-                    name: c.target_name.clone().into_boxed_str(),
-                    col_type: c.col_type,
-                })
-                .collect();
-
-            if let Err(e) = db.create_table_with_capacity(target, columns, fetch_result.row_count) {
-                let error_msg = format!("failed to create table: {}", e);
-                log_error!("sync", "{}", error_msg);
-                
-                self.update_status(target, 0, start.elapsed(), Some(error_msg.clone()));
-                
-                return SyncResult {
-                    table: target.clone(),
-                    success: false,
-                    rows_synced: 0,
-                    duration_ms: start.elapsed().as_millis() as u64,
-                    error: Some(error_msg),
+        let columns: Vec<Column> = table.columns.iter()
+            .map(|c| {
+                Column::new(&c.target_name, c.col_type)
+                    .with_indexes(c.indexes)
+                    .with_primary_key(table.primary_key.contains(&c.target_name))
+            })
+            .collect();
+
+        // a keyed table is upserted in place so row ids stay put, a full
+        // refresh then drops the rows the source no longer has
+        let result = if !table.primary_key.is_empty() {
+            let handle = {
+                let mut db = db.write().unwrap();
+                match db.table(target) {
+                    Some(handle) => Ok(handle),
+                    None => db.create_table(target, columns)
+                        .map(|_| db.table(target).unwrap())
+                        .map_err(|e| format!("failed to create table: {}", e)),
+                }
+            };
+            handle.and_then(|handle| {
+                let mut t = handle.write().unwrap();
+                if t.primary_key().is_empty() {
+                    return Err(format!("{} has no primary key to upsert by", target));
+                }
+                // rows are kept by the keys the source sent, so one whose
+                // upsert failed isn't swept away as stale
+                let keys: Vec<Vec<Value>> = if self.config.clear_before_sync {
+                    fetch_result.rows.iter()
+                        .map(|row| t.primary_key().iter().map(|&c| row.get(c).cloned().unwrap_or(Value::Null)).collect())
+                        .collect()
+                } else {
+                    Vec::new()
                 };
+                let counts = written(target, &t.upsert_batch(fetch_result.rows, Coercion::Strict))?;
+                if self.config.clear_before_sync {
+                    let kept: HashSet<RowId> = keys.iter().filter_map(|key| t.find_key(key)).collect();
+                    let stale: Vec<RowId> = t.rows().map(|(id, _)| id).filter(|id| !kept.contains(id)).collect();
+                    t.delete_many(&stale).map_err(|e| e.to_string())?;
+                }
+                Ok(counts)
+            })
+        } else if self.config.clear_before_sync {
+            // a full refresh is loaded into a fresh table off to the side and swapped
+            // in at the end, so readers keep seeing the old data until then
+            let mut fresh = Table::with_capacity(target, columns, fetch_result.row_count);
+            written(target, &fresh.insert_batch(fetch_result.rows, Coercion::Strict)).and_then(|counts| {
+                db.write().unwrap()
+                    .replace_table(fresh)
+                    .map(|_| counts)
+                    .map_err(|e| format!("failed to create table: {}", e))
+            })
+        } else {
+            let handle = db.read().unwrap().table(target);
+            match handle {
+                // one batch so a logged table gets a single append for the whole sync
+                Some(handle) => written(target, &handle.write().unwrap().insert_batch(fetch_result.rows, Coercion::Strict)),
+                None => Err("table not found".to_string()),
             }
-        }
+        };
 
-        // insert rows
-        let table_ref = match db.get_table_mut(target) {
-            Some(t) => t,
-            None => {
-                let error_msg = "table not found after creation".to_string();
+        let (inserted, failed) = match result {
+            Ok(counts) => counts,
+            Err(error_msg) => {
                 log_error!("sync", "{}", error_msg);
-                
+
                 self.update_status(target, 0, start.elapsed(), Some(error_msg.clone()));
-                
+
                 return SyncResult {
                     table: target.clone(),
                     success: false,
                     rows_synced: 0,
+                    rows_failed: 0,
                     duration_ms: start.elapsed().as_millis() as u64,
                     error: Some(error_msg),
                 };
             }
         };
 
-        let mut inserted = 0;
-        for row in fetch_result.rows {
-            if table_ref.insert(row).is_ok() {
-                inserted += 1;
-            }
-        }
-
         let duration = start.elapsed();
         log_info!("sync", "synced {} rows to {} in {}ms", inserted, target, duration.as_millis());
 
@@ -209,6 +235,7 @@ impl SyncManager {
             table: target.clone(),
             success: true,
             rows_synced: inserted,
+            rows_failed: failed,
             duration_ms: duration.as_millis() as u64,
             error: None,
         }
@@ -297,9 +324,25 @@ impl SyncManager {
     }
 }
 
+// how many rows of a batch were written and how many refused, logging the
+// first refusal. a failed log append fails the whole sync
+fn written<T>(target: &str, results: &[Result<T, TableError>]) -> Result<(usize, usize), String> {
+    let mut failures = results.iter().enumerate().filter_map(|(row, r)| r.as_ref().err().map(|e| (row, e)));
+    let Some((row, first)) = failures.next() else {
+        return Ok((results.len(), 0));
+    };
+    if *first == TableError::Log {
+        return Err(format!("failed to write {}: {}", target, first));
+    }
+    let failed = failures.count() + 1;
+    log_warn!("sync", "{} of {} rows not written to {}, first at row {}: {}", failed, results.len(), target, row, first);
+    Ok((results.len() - failed, failed))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::sync::source::{FetchResult, SourceError};
     use crate::table::ColumnType;
 
     #[test]
@@ -316,6 +359,58 @@ mod tests {
         assert_eq!(config.tables.len(), 1);
     }
 
+    // hands back whatever rows it was last given
+    struct FixedSource(Arc<RwLock<Vec<Vec<Value>>>>);
+
+    impl Source for FixedSource {
+        fn connect(&mut self) -> Result<(), SourceError> { Ok(()) }
+        fn disconnect(&mut self) {}
+        fn is_connected(&self) -> bool { true }
+        fn fetch_table(&self, _table: &SyncTable) -> Result<FetchResult, SourceError> {
+            let rows = self.0.read().unwrap().clone();
+            Ok(FetchResult { row_count: rows.len(), rows })
+        }
+        fn name(&self) -> &str { "fixed" }
+    }
+
+    #[test]
+    fn test_keyed_sync_upserts() {
+        let user = |id: i64, name: &str| vec![Value::Int(id), Value::String(name.into())];
+        let table = SyncTable::new("src_users", "users")
+            .with_column("id", "id", ColumnType::Int)
+            .with_column("name", "name", ColumnType::String)
+            .with_primary_key(&["id"]);
+        let rows = Arc::new(RwLock::new(vec![user(1, "alice"), user(2, "bob")]));
+        let manager = SyncManager::new(Box::new(FixedSource(Arc::clone(&rows))), SyncConfig::new().with_table(table.clone()));
+        let db = Arc::new(RwLock::new(Database::new()));
+
+        assert_eq!(manager.sync_table(&table, &db).rows_synced, 2);
+        let bob = db.read().unwrap().get_table("users").unwrap().find_key(&[Value::Int(2)]).unwrap();
+
+        // bob keeps his row id, alice is gone from the source and from the table
+        *rows.write().unwrap() = vec![user(2, "robert"), user(3, "carol")];
+        assert!(manager.sync_table(&table, &db).success);
+        {
+            let db = db.read().unwrap();
+            let users = db.get_table("users").unwrap();
+            assert_eq!(users.len(), 2);
+            assert_eq!(users.find_key(&[Value::Int(2)]), Some(bob));
+            assert_eq!(users.get(bob).unwrap()[1], Value::String("robert".into()));
+            assert_eq!(users.find_key(&[Value::Int(1)]), None);
+        }
+
+        // carol's row doesn't fit this time, she's kept as she was rather than swept
+        *rows.write().unwrap() = vec![user(2, "robert"), vec![Value::Int(3), Value::Int(7)]];
+        let result = manager.sync_table(&table, &db);
+        assert!(result.success);
+        assert_eq!((result.rows_synced, result.rows_failed), (1, 1));
+        let db = db.read().unwrap();
+        let users = db.get_table("users").unwrap();
+        assert_eq!(users.len(), 2);
+        let carol = users.find_key(&[Value::Int(3)]).unwrap();
+        assert_eq!(users.get(carol).unwrap()[1], Value::String("carol".into()));
+    }
+
     #[test]
     fn test_sync_config_manual_only() {
         let config = SyncConfig::new()
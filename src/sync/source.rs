@@ -1,5 +1,6 @@
 // source trait - implement this to add new data sources
 
+use crate::index::IndexSet;
 use crate::storage::Value;
 use crate::table::ColumnType;
 
@@ -9,6 +10,7 @@ pub struct ColumnMapping {
     pub source_name: String,    // column name in source
     pub target_name: String,    // column name in quickset
     pub col_type: ColumnType,   // quickset column type
+    pub indexes: IndexSet,      // indexes to keep on the quickset side
 }
 
 // describes a table to sync
@@ -18,6 +20,7 @@ pub struct SyncTable {
     pub target_table: String,       // table name in quickset
     pub columns: Vec<ColumnMapping>,
     pub query_override: Option<String>, // optional: custom query instead of SELECT *
+    pub primary_key: Vec<String>,   // target columns; keyed tables are upserted instead of rebuilt
 }
 
 impl SyncTable {
@@ -27,14 +30,21 @@ impl SyncTable {
             target_table: target.to_string(),
             columns: Vec::new(),
             query_override: None,
+            primary_key: Vec::new(),
         }
     }
 
-    pub fn with_column(mut self, source: &str, target: &str, col_type: ColumnType) -> Self {
+    // a column with the default indexes for its type
+    pub fn with_column(self, source: &str, target: &str, col_type: ColumnType) -> Self {
+        self.with_indexed_column(source, target, col_type, col_type.default_indexes())
+    }
+
+    pub fn with_indexed_column(mut self, source: &str, target: &str, col_type: ColumnType, indexes: IndexSet) -> Self {
         self.columns.push(ColumnMapping {
             source_name: source.to_string(),
             target_name: target.to_string(),
             col_type,
+            indexes,
         });
         self
     }
@@ -43,6 +53,11 @@ impl SyncTable {
         self.query_override = Some(query.to_string());
         self
     }
+
+    pub fn with_primary_key(mut self, columns: &[&str]) -> Self {
+        self.primary_key = columns.iter().map(|c| c.to_string()).collect();
+        self
+    }
 }
 
 // configuration for connecting to a source
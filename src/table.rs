@@ -1,11 +1,88 @@
-use crate::search::{SearchEngine, SearchType};
-use crate::storage::{RowId, Storage, Value};
-use std::collections::HashMap;
+use crate::aggregate::{self, Aggregation, AggregationResult};
+use crate::analyzer::Analyzer;
+use crate::persist::{Wal, WalEntry, WalRecord};
+use crate::index::{IndexKind, IndexSet};
+use crate::search::{Bm25, Facet, Query, SearchEngine, SearchError, SearchType, SortKey, Suggestion};
+use crate::storage::{format_timestamp, format_uuid, parse_timestamp, parse_uuid, RowId, Storage, Value};
+use crate::{log_error, log_warn};
+use std::cmp::Ordering;
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard};
 
 #[derive(Clone, Debug)]
 pub struct Column {
     pub name: Box<str>,
     pub col_type: ColumnType,
+    pub indexes: IndexSet,
+    pub analyzer: Analyzer,     // how the full-text index splits and normalizes text
+    pub nullable: bool,         // whether rows may leave it null
+    pub default: Value,         // what a row given by column name gets when it leaves this one out
+    pub primary_key: bool,      // part of the table's primary key, in column order
+}
+
+impl Column {
+    // a column with the default indexes for its type
+    pub fn new(name: &str, col_type: ColumnType) -> Self {
+        Self {
+            name: name.into(),
+            col_type,
+            indexes: col_type.default_indexes(),
+            analyzer: Analyzer::default(),
+            nullable: true,
+            default: Value::Null,
+            primary_key: false,
+        }
+    }
+
+    pub fn with_indexes(mut self, indexes: IndexSet) -> Self {
+        self.indexes = indexes;
+        self
+    }
+
+    pub fn with_analyzer(mut self, analyzer: Analyzer) -> Self {
+        self.analyzer = analyzer;
+        self
+    }
+
+    pub fn with_nullable(mut self, nullable: bool) -> Self {
+        self.nullable = nullable;
+        self
+    }
+
+    pub fn with_default(mut self, default: Value) -> Self {
+        self.default = default;
+        self
+    }
+
+    // key columns are never null and always get an exact index, it's what
+    // finds the row holding a key
+    pub fn with_primary_key(mut self, primary_key: bool) -> Self {
+        self.primary_key = primary_key;
+        if primary_key {
+            self.nullable = false;
+            self.indexes = self.indexes.with(IndexKind::Exact);
+        }
+        self
+    }
+
+    // the value as this column stores it. ints widen into float columns, and
+    // with `Coercion::Convert` strings and numbers are converted when nothing
+    // is lost doing so
+    pub fn check(&self, value: Value, coercion: Coercion) -> Result<Value, Value> {
+        match value {
+            Value::Null if self.nullable => Ok(Value::Null),
+            value => self.col_type.check(value, coercion),
+        }
+    }
+}
+
+// what to do with a value whose type doesn't match its column
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Coercion {
+    #[default]
+    Strict,     // reject it
+    Convert,    // convert it when that's lossless, "42" into an int column is 42
 }
 
 #[derive(Clone, Copy, Debug, PartialEq)]
@@ -14,40 +91,346 @@ pub enum ColumnType {
     Float,
     String,
     Bytes,
+    Bool,
+    Timestamp,  // stored as microseconds since the epoch
+    Uuid,
+    Array(&'static ColumnType), // any other type's values, never nested
+}
+
+impl ColumnType {
+    // an array of `element`, none for arrays of arrays
+    pub fn array_of(element: ColumnType) -> Option<ColumnType> {
+        Some(Self::Array(match element {
+            Self::Int => &Self::Int,
+            Self::Float => &Self::Float,
+            Self::String => &Self::String,
+            Self::Bytes => &Self::Bytes,
+            Self::Bool => &Self::Bool,
+            Self::Timestamp => &Self::Timestamp,
+            Self::Uuid => &Self::Uuid,
+            Self::Array(_) => return None,
+        }))
+    }
+
+    // what an array holds, any other type is its own element
+    pub fn element(self) -> ColumnType {
+        match self {
+            Self::Array(element) => *element,
+            other => other,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Int => "int",
+            Self::Float => "float",
+            Self::String => "string",
+            Self::Bytes => "bytes",
+            Self::Bool => "bool",
+            Self::Timestamp => "timestamp",
+            Self::Uuid => "uuid",
+            Self::Array(Self::Int) => "array<int>",
+            Self::Array(Self::Float) => "array<float>",
+            Self::Array(Self::String) => "array<string>",
+            Self::Array(Self::Bytes) => "array<bytes>",
+            Self::Array(Self::Bool) => "array<bool>",
+            Self::Array(Self::Timestamp) => "array<timestamp>",
+            Self::Array(Self::Uuid) => "array<uuid>",
+            Self::Array(Self::Array(_)) => "array<array>",
+        }
+    }
+
+    // what a column gets when the schema doesn't say. arrays index every
+    // element the way a column of their element type would
+    pub fn default_indexes(self) -> IndexSet {
+        match self {
+            Self::String => IndexSet::NONE
+                .with(IndexKind::Exact)
+                .with(IndexKind::Prefix)
+                .with(IndexKind::FullText)
+                .with(IndexKind::Substring),
+            Self::Int | Self::Float | Self::Timestamp => IndexSet::NONE.with(IndexKind::Exact).with(IndexKind::Range),
+            Self::Bytes | Self::Bool | Self::Uuid => IndexSet::NONE.with(IndexKind::Exact),
+            Self::Array(element) => element.default_indexes(),
+        }
+    }
+
+    // whether an index of this kind can be built over values of this type
+    pub fn supports(self, kind: IndexKind) -> bool {
+        match (self, kind) {
+            (Self::Array(element), kind) => element.supports(kind),
+            (_, IndexKind::Exact) => true,
+            (_, IndexKind::Prefix | IndexKind::FullText | IndexKind::Substring) => self == Self::String,
+            (_, IndexKind::Range) => matches!(self, Self::Int | Self::Float | Self::String | Self::Timestamp),
+        }
+    }
+
+    // a non-null value as this type stores it, see `Column::check`. timestamps
+    // take rfc 3339 strings or epoch seconds and uuids their text form or 16
+    // bytes, that's how json carries them
+    pub fn check(self, value: Value, coercion: Coercion) -> Result<Value, Value> {
+        let convert = coercion == Coercion::Convert;
+        match (self, value) {
+            (Self::Int, v @ Value::Int(_))
+            | (Self::Float, v @ Value::Float(_))
+            | (Self::String, v @ Value::String(_))
+            | (Self::Bytes, v @ Value::Bytes(_))
+            | (Self::Bool, v @ Value::Bool(_))
+            | (Self::Timestamp, v @ Value::Timestamp(_))
+            | (Self::Uuid, v @ Value::Uuid(_)) => Ok(v),
+            (Self::Float, Value::Int(i)) => Ok(Value::Float(i as f64)),
+            (Self::Timestamp, Value::String(s)) => parse_timestamp(&s).map(Value::Timestamp).ok_or(Value::String(s)),
+            (Self::Timestamp, Value::Int(i)) => i.checked_mul(1_000_000).map(Value::Timestamp).ok_or(Value::Int(i)),
+            (Self::Timestamp, Value::Float(f)) if (f * 1e6).is_finite() && (f * 1e6).abs() < i64::MAX as f64 => {
+                Ok(Value::Timestamp((f * 1e6).round() as i64))
+            }
+            (Self::Uuid, Value::String(s)) => parse_uuid(&s).map(Value::Uuid).ok_or(Value::String(s)),
+            (Self::Uuid, Value::Bytes(b)) => match <[u8; 16]>::try_from(&*b) {
+                Ok(uuid) => Ok(Value::Uuid(uuid)),
+                Err(_) => Err(Value::Bytes(b)),
+            },
+            (Self::Array(element), Value::Array(items)) => {
+                let mut checked = Vec::with_capacity(items.len());
+                for item in items.into_vec() {
+                    match item {
+                        Value::Null => return Err(Value::Array(Box::new([Value::Null]))),
+                        item => checked.push(element.check(item, coercion)?),
+                    }
+                }
+                Ok(Value::Array(checked.into_boxed_slice()))
+            }
+            // json reads a list of small ints as bytes
+            (Self::Array(element), Value::Bytes(b)) if b.is_empty() || *element == Self::Int || *element == Self::Float => {
+                element_array(element, b.iter().map(|&byte| Value::Int(byte as i64)), coercion).ok_or(Value::Bytes(b))
+            }
+            (Self::Int, Value::Float(f)) if convert && f.fract() == 0.0 && f >= i64::MIN as f64 && f < i64::MAX as f64 => {
+                Ok(Value::Int(f as i64))
+            }
+            (Self::Int, Value::Bool(b)) if convert => Ok(Value::Int(b as i64)),
+            (Self::Int, Value::String(s)) if convert => s.trim().parse().map(Value::Int).map_err(|_| Value::String(s)),
+            (Self::Float, Value::String(s)) if convert => s.trim().parse().map(Value::Float).map_err(|_| Value::String(s)),
+            (Self::String, Value::Int(i)) if convert => Ok(Value::String(i.to_string().into())),
+            (Self::String, Value::Float(f)) if convert => Ok(Value::String(f.to_string().into())),
+            (Self::String, Value::Bool(b)) if convert => Ok(Value::String(b.to_string().into())),
+            (Self::String, Value::Timestamp(t)) if convert => Ok(Value::String(format_timestamp(t).into())),
+            (Self::String, Value::Uuid(u)) if convert => Ok(Value::String(format_uuid(&u).into())),
+            (Self::Bytes, Value::String(s)) if convert => Ok(Value::Bytes(s.into_boxed_bytes())),
+            (Self::Bytes, Value::Uuid(u)) if convert => Ok(Value::Bytes(Box::new(u))),
+            (Self::Bool, Value::Int(i @ (0 | 1))) if convert => Ok(Value::Bool(i == 1)),
+            (Self::Bool, Value::String(s)) if convert => match s.trim().to_lowercase().as_str() {
+                "true" | "1" => Ok(Value::Bool(true)),
+                "false" | "0" => Ok(Value::Bool(false)),
+                _ => Err(Value::String(s)),
+            },
+            (_, v) => Err(v),
+        }
+    }
+}
+
+// the elements as an array of `element`, none if one doesn't fit
+fn element_array(element: &ColumnType, items: impl Iterator<Item = Value>, coercion: Coercion) -> Option<Value> {
+    items
+        .map(|item| element.check(item, coercion).ok())
+        .collect::<Option<Vec<_>>>()
+        .map(|items| Value::Array(items.into_boxed_slice()))
+}
+
+// why a row was refused. `row` is its position in the batch it came in
+#[derive(Clone, Debug, PartialEq)]
+pub enum TableError {
+    ColumnCount { row: usize, expected: usize, got: usize },
+    Type { row: usize, column: Box<str>, expected: ColumnType, got: &'static str },
+    Null { row: usize, column: Box<str> },
+    DuplicateKey { row: usize, key: String },
+    NoPrimaryKey,
+    UnknownTable,
+    TableExists,
+    UnknownColumn(Box<str>),
+    ColumnExists(Box<str>),
+    KeyColumn(Box<str>),            // the change would touch the primary key
+    NoDefault(Box<str>),            // a column whose default is missing or doesn't fit its type
+    Conversion { row_id: RowId, column: Box<str>, to: ColumnType },
+    Log,
+}
+
+impl std::fmt::Display for TableError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::ColumnCount { row, expected, got } => {
+                write!(f, "row {}: expected {} values, got {}", row, expected, got)
+            }
+            Self::Type { row, column, expected, got } => {
+                write!(f, "row {}: column '{}' expects {}, got {}", row, column, expected.name(), got)
+            }
+            Self::Null { row, column } => write!(f, "row {}: column '{}' is not nullable", row, column),
+            Self::DuplicateKey { row, key } => write!(f, "row {}: primary key {} already exists", row, key),
+            Self::NoPrimaryKey => write!(f, "table has no primary key"),
+            Self::UnknownTable => write!(f, "table not found"),
+            Self::TableExists => write!(f, "table already exists"),
+            Self::UnknownColumn(column) => write!(f, "column not found: {}", column),
+            Self::ColumnExists(column) => write!(f, "column {} already exists", column),
+            Self::KeyColumn(column) => write!(f, "column {} is part of the primary key", column),
+            Self::NoDefault(column) => write!(f, "column {} has no default it can hold", column),
+            Self::Conversion { row_id, column, to } => {
+                write!(f, "row id {}: column '{}' can't be converted to {}", row_id, column, to.name())
+            }
+            Self::Log => write!(f, "failed to write log"),
+        }
+    }
+}
+
+impl std::error::Error for TableError {}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Int(_) => "int",
+        Value::Float(_) => "float",
+        Value::String(_) => "string",
+        Value::Bytes(_) => "bytes",
+        Value::Bool(_) => "bool",
+        Value::Timestamp(_) => "timestamp",
+        Value::Uuid(_) => "uuid",
+        Value::Array(_) => "array",
+    }
+}
+
+// `Column::check` with the refusal as an error for row `row`
+fn check_value(column: &Column, row: usize, value: Value, coercion: Coercion) -> Result<Value, TableError> {
+    column.check(value, coercion).map_err(|value| match value {
+        Value::Null => TableError::Null { row, column: column.name.clone() },
+        value => TableError::Type {
+            row,
+            column: column.name.clone(),
+            expected: column.col_type,
+            got: type_name(&value),
+        },
+    })
+}
+
+// a primary key for error messages, `(eu, 7)`
+fn key_string(key: &[&Value]) -> String {
+    let parts: Vec<String> = key
+        .iter()
+        .map(|v| match v {
+            Value::Null => "null".to_string(),
+            Value::Int(i) => i.to_string(),
+            Value::Float(f) => f.to_string(),
+            Value::String(s) => format!("{:?}", s),
+            Value::Bytes(b) => format!("<{} bytes>", b.len()),
+            Value::Bool(b) => b.to_string(),
+            Value::Timestamp(t) => format_timestamp(*t),
+            Value::Uuid(u) => format_uuid(u),
+            Value::Array(items) => format!("<{} elements>", items.len()),
+        })
+        .collect();
+    format!("({})", parts.join(", "))
+}
+
+// a schema change, applied to every row in place
+#[derive(Clone, Debug)]
+pub enum Alteration {
+    AddColumn(Column),                                  // rows get the column's default
+    DropColumn(Box<str>),
+    RenameColumn { from: Box<str>, to: Box<str> },
+    Retype { column: Box<str>, col_type: ColumnType },  // values go through `Coercion::Convert`
+}
+
+// an alteration that has been checked and is ready to apply
+enum AlterPlan {
+    Add(Column),
+    Drop(usize),
+    Rename(usize, Box<str>),
+    Retype { position: usize, column: Column, converted: HashMap<RowId, Value> },
+}
+
+// what an upsert did with a row
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Upsert {
+    Inserted(RowId),
+    Updated(RowId),
+}
+
+impl Upsert {
+    pub fn row_id(self) -> RowId {
+        match self {
+            Self::Inserted(id) | Self::Updated(id) => id,
+        }
+    }
+}
+
+// the columns flagged as the primary key, in column order
+fn key_columns(columns: &[Column]) -> Vec<usize> {
+    columns.iter().enumerate().filter(|(_, c)| c.primary_key).map(|(i, _)| i).collect()
 }
 
 pub struct Table {
     name: Box<str>,
     columns: Vec<Column>,
+    primary_key: Vec<usize>,    // key columns, empty if the table has none
     storage: Storage,
     search_engine: SearchEngine,
+    wal: Option<Arc<Wal>>,
 }
 
 impl Table {
     pub fn new(name: &str, columns: Vec<Column>) -> Self {
-        let num_cols = columns.len();
         Self {
             name: name.into(),
+            search_engine: SearchEngine::for_columns(&columns, None),
+            primary_key: key_columns(&columns),
             columns,
             storage: Storage::new(),
-            search_engine: SearchEngine::new(num_cols),
+            wal: None,
         }
     }
 
     pub fn with_capacity(name: &str, columns: Vec<Column>, capacity: usize) -> Self {
-        let num_cols = columns.len();
         Self {
             name: name.into(),
+            search_engine: SearchEngine::for_columns(&columns, Some(capacity)),
+            primary_key: key_columns(&columns),
             columns,
             storage: Storage::with_capacity(capacity),
-            search_engine: SearchEngine::with_capacity(num_cols, capacity),
+            wal: None,
+        }
+    }
+
+    // rebuild a table from persisted rows, indexes are recreated as rows go in
+    pub fn restore(
+        name: &str,
+        columns: Vec<Column>,
+        rows: Vec<(RowId, Vec<Value>)>,
+        next_id: RowId,
+    ) -> Self {
+        let mut table = Self::with_capacity(name, columns, rows.len().max(1024));
+        for (row_id, values) in rows {
+            table.search_engine.index_row(row_id, &values);
+            table.storage.insert_with_id(row_id, values);
         }
+        table.storage.set_next_id(next_id);
+        table
     }
 
     pub fn name(&self) -> &str {
         &self.name
     }
 
+    // writes are logged here once attached, the database does this for its tables
+    pub fn set_wal(&mut self, wal: Option<Arc<Wal>>) {
+        self.wal = wal;
+    }
+
+    fn log(&self, records: &[WalRecord<'_>]) -> Result<(), TableError> {
+        match &self.wal {
+            Some(wal) => wal.append(records).map_err(|e| {
+                log_error!("persist", "log append failed for {}: {}", self.name, e);
+                TableError::Log
+            }),
+            None => Ok(()),
+        }
+    }
+
     pub fn columns(&self) -> &[Column] {
         &self.columns
     }
@@ -56,20 +439,287 @@ impl Table {
         self.columns.iter().position(|c| &*c.name == name)
     }
 
-    #[inline(always)]
-    pub fn insert(&mut self, values: Vec<Value>) -> Result<RowId, &'static str> {
+    // check `values` against the schema, `row` is only used to report errors
+    pub fn prepare(&self, row: usize, values: Vec<Value>, coercion: Coercion) -> Result<Vec<Value>, TableError> {
         if values.len() != self.columns.len() {
-            return Err("column count mismatch");
+            return Err(TableError::ColumnCount { row, expected: self.columns.len(), got: values.len() });
         }
 
-        let row_id = self.storage.insert(values.clone());
+        self.columns
+            .iter()
+            .zip(values)
+            .map(|(column, value)| check_value(column, row, value, coercion))
+            .collect()
+    }
+
+    // change the schema. every row is converted and the indexes of the
+    // columns involved rebuilt before this returns, nothing changes if any
+    // row can't be converted or the change can't be logged
+    pub fn alter(&mut self, alteration: &Alteration) -> Result<(), TableError> {
+        let plan = self.plan_alter(alteration)?;
+        self.log(&[WalRecord::AlterTable { table: &self.name, alteration }])?;
+        self.apply_alter(plan);
+        Ok(())
+    }
+
+    // everything that can refuse an alteration happens here, before it's logged
+    fn plan_alter(&self, alteration: &Alteration) -> Result<AlterPlan, TableError> {
+        match alteration {
+            Alteration::AddColumn(column) => {
+                if self.column_index(&column.name).is_some() {
+                    return Err(TableError::ColumnExists(column.name.clone()));
+                }
+                if column.primary_key {
+                    return Err(TableError::KeyColumn(column.name.clone()));
+                }
+                let default = column.check(column.default.clone(), Coercion::Strict)
+                    .map_err(|_| TableError::NoDefault(column.name.clone()))?;
+                Ok(AlterPlan::Add(column.clone().with_default(default)))
+            }
+            Alteration::DropColumn(name) => {
+                let position = self.existing_column(name)?;
+                if self.columns[position].primary_key {
+                    return Err(TableError::KeyColumn(name.clone()));
+                }
+                Ok(AlterPlan::Drop(position))
+            }
+            Alteration::RenameColumn { from, to } => {
+                let position = self.existing_column(from)?;
+                if from != to && self.column_index(to).is_some() {
+                    return Err(TableError::ColumnExists(to.clone()));
+                }
+                Ok(AlterPlan::Rename(position, to.clone()))
+            }
+            Alteration::Retype { column: name, col_type } => {
+                let position = self.existing_column(name)?;
+                let old = &self.columns[position];
+                if old.primary_key {
+                    return Err(TableError::KeyColumn(name.clone()));
+                }
+
+                // columns on their type's defaults move to the new type's,
+                // otherwise they keep what the new type can still hold
+                let indexes = if old.indexes == old.col_type.default_indexes() {
+                    col_type.default_indexes()
+                } else {
+                    old.indexes.iter().filter(|k| col_type.supports(*k)).fold(IndexSet::NONE, IndexSet::with)
+                };
+                let mut column = Column::new(name, *col_type)
+                    .with_indexes(indexes)
+                    .with_nullable(old.nullable);
+                if col_type.element() == ColumnType::String {
+                    column = column.with_analyzer(old.analyzer.clone());
+                }
+
+                // convert everything first so a bad value leaves the table as it was
+                let converted = self.storage
+                    .iter()
+                    .map(|row| {
+                        column.check(row.columns[position].clone(), Coercion::Convert)
+                            .map(|value| (row.id, value))
+                            .map_err(|_| TableError::Conversion { row_id: row.id, column: name.clone(), to: *col_type })
+                    })
+                    .collect::<Result<HashMap<RowId, Value>, TableError>>()?;
+
+                // the default converts too, rather than quietly turning into null
+                let default = match &old.default {
+                    Value::Null => Value::Null,
+                    default => column.check(default.clone(), Coercion::Convert)
+                        .map_err(|_| TableError::NoDefault(name.clone()))?,
+                };
+                let column = column.with_default(default);
+                Ok(AlterPlan::Retype { position, column, converted })
+            }
+        }
+    }
+
+    fn apply_alter(&mut self, plan: AlterPlan) {
+        let capacity = Some(self.len().max(1024));
+        match plan {
+            AlterPlan::Add(column) => {
+                let position = self.columns.len();
+                self.search_engine.insert_column(position, &column, capacity);
+                for row in self.storage.iter_mut() {
+                    row.columns.push(column.default.clone());
+                    self.search_engine.index_value(position, row.id, &column.default);
+                }
+                self.columns.push(column);
+            }
+            AlterPlan::Drop(position) => {
+                for row in self.storage.iter_mut() {
+                    row.columns.remove(position);
+                }
+                self.search_engine.remove_column(position);
+                self.columns.remove(position);
+                self.primary_key = key_columns(&self.columns);
+            }
+            AlterPlan::Rename(position, to) => {
+                self.columns[position].name = to;
+            }
+            AlterPlan::Retype { position, column, mut converted } => {
+                self.search_engine.remove_column(position);
+                self.search_engine.insert_column(position, &column, capacity);
+                for row in self.storage.iter_mut() {
+                    let value = converted.remove(&row.id).unwrap_or(Value::Null);
+                    self.search_engine.index_value(position, row.id, &value);
+                    row.columns[position] = value;
+                }
+                self.columns[position] = column;
+            }
+        }
+    }
+
+    fn existing_column(&self, name: &str) -> Result<usize, TableError> {
+        self.column_index(name).ok_or_else(|| TableError::UnknownColumn(name.into()))
+    }
+
+    pub fn primary_key(&self) -> &[usize] {
+        &self.primary_key
+    }
+
+    // the row holding `key`, given in key column order
+    pub fn find_key(&self, key: &[Value]) -> Option<RowId> {
+        if key.len() != self.primary_key.len() {
+            return None;
+        }
+        self.lookup(&key.iter().collect::<Vec<_>>())
+    }
+
+    // key columns always have an exact index. the rows sharing the key's
+    // rarest column value are the only candidates, so a composite key costs
+    // that column's postings rather than the first one's
+    fn lookup(&self, key: &[&Value]) -> Option<RowId> {
+        let candidates = self
+            .primary_key
+            .iter()
+            .zip(key)
+            .map(|(&c, v)| self.search_engine.exact_ids(c, v))
+            .min_by_key(|ids| ids.len())?;
+        candidates.iter().copied().find(|&id| match self.get(id) {
+            Some(values) => self.primary_key.iter().zip(key).all(|(&c, v)| values[c] == **v),
+            None => false,
+        })
+    }
+
+    fn key_of<'a>(&self, values: &'a [Value]) -> Vec<&'a Value> {
+        self.primary_key.iter().map(|&c| &values[c]).collect()
+    }
+
+    // refuse values whose key some row other than `row_id` already holds
+    fn check_key(&self, row: usize, values: &[Value], row_id: Option<RowId>) -> Result<(), TableError> {
+        if self.primary_key.is_empty() {
+            return Ok(());
+        }
+        let key = self.key_of(values);
+        match self.lookup(&key) {
+            Some(owner) if Some(owner) != row_id => Err(TableError::DuplicateKey { row, key: key_string(&key) }),
+            _ => Ok(()),
+        }
+    }
+
+    // refuse a new row whose key is already held, by the table or by an
+    // earlier row of the same batch (`seen`)
+    fn check_new_key(&self, row: usize, values: &[Value], seen: &mut HashSet<String>) -> Result<(), TableError> {
+        if self.primary_key.is_empty() {
+            return Ok(());
+        }
+        self.check_key(row, values, None)?;
+        let key = key_string(&self.key_of(values));
+        if !seen.insert(key.clone()) {
+            return Err(TableError::DuplicateKey { row, key });
+        }
+        Ok(())
+    }
+
+    #[inline(always)]
+    fn apply_insert(&mut self, row_id: RowId, values: Vec<Value>) {
         self.search_engine.index_row(row_id, &values);
-        Ok(row_id)
+        self.storage.insert_with_id(row_id, values);
     }
 
+    // every write is logged before it touches memory, so a failed append
+    // leaves the table as it was
     #[inline(always)]
-    pub fn insert_batch(&mut self, rows: Vec<Vec<Value>>) -> Vec<Result<RowId, &'static str>> {
-        rows.into_iter().map(|values| self.insert(values)).collect()
+    pub fn insert(&mut self, values: Vec<Value>, coercion: Coercion) -> Result<RowId, TableError> {
+        let values = self.prepare(0, values, coercion)?;
+        self.check_key(0, &values, None)?;
+        let row_id = self.storage.next_id();
+        self.log(&[WalRecord::Insert { table: &self.name, row_id, values: &values }])?;
+        self.apply_insert(row_id, values);
+        Ok(row_id)
+    }
+
+    // logs every row that passes its checks with a single append, then
+    // inserts them. ids are handed out up front so they can be logged
+    pub fn insert_batch(&mut self, rows: Vec<Vec<Value>>, coercion: Coercion) -> Vec<Result<RowId, TableError>> {
+        let (mut results, staged) = self.stage_inserts(rows, coercion);
+        if let Err(e) = self.commit_inserts(staged) {
+            for result in results.iter_mut().filter(|r| r.is_ok()) {
+                *result = Err(e.clone());
+            }
+        }
+        results
+    }
+
+    // like insert_batch, but one row that doesn't pass refuses them all
+    pub fn insert_all(&mut self, rows: Vec<Vec<Value>>, coercion: Coercion) -> Result<Vec<RowId>, TableError> {
+        let (results, staged) = self.stage_inserts(rows, coercion);
+        let ids = results.into_iter().collect::<Result<Vec<_>, _>>()?;
+        self.commit_inserts(staged)?;
+        Ok(ids)
+    }
+
+    // check new rows and hand out their ids, without touching the table.
+    // returns a result per row and the rows that passed
+    #[allow(clippy::type_complexity)]
+    fn stage_inserts(
+        &self,
+        rows: Vec<Vec<Value>>,
+        coercion: Coercion,
+    ) -> (Vec<Result<RowId, TableError>>, Vec<(RowId, Vec<Value>)>) {
+        let mut next_id = self.storage.next_id();
+        let mut seen = HashSet::new();
+        let mut staged = Vec::with_capacity(rows.len());
+        let results = rows
+            .into_iter()
+            .enumerate()
+            .map(|(row, values)| {
+                let values = self.prepare(row, values, coercion)?;
+                self.check_new_key(row, &values, &mut seen)?;
+                let row_id = next_id;
+                next_id += 1;
+                staged.push((row_id, values));
+                Ok(row_id)
+            })
+            .collect();
+        (results, staged)
+    }
+
+    fn commit_inserts(&mut self, staged: Vec<(RowId, Vec<Value>)>) -> Result<(), TableError> {
+        let records: Vec<WalRecord> = staged
+            .iter()
+            .map(|(row_id, values)| WalRecord::Insert { table: &self.name, row_id: *row_id, values })
+            .collect();
+        self.log(&records)?;
+
+        for (row_id, values) in staged {
+            self.apply_insert(row_id, values);
+        }
+        Ok(())
+    }
+
+    // insert under a known row id, used when replaying the log. the values
+    // were checked when first written so only their count is checked again
+    pub fn insert_with_id(&mut self, row_id: RowId, values: Vec<Value>) -> Result<(), TableError> {
+        if values.len() != self.columns.len() {
+            return Err(TableError::ColumnCount { row: 0, expected: self.columns.len(), got: values.len() });
+        }
+        self.log(&[WalRecord::Insert { table: &self.name, row_id, values: &values }])?;
+        if let Some(old) = self.storage.delete(row_id) {
+            self.search_engine.remove_row(row_id, &old.columns);
+        }
+        self.apply_insert(row_id, values);
+        Ok(())
     }
 
     #[inline(always)]
@@ -86,32 +736,232 @@ impl Table {
     }
 
     #[inline(always)]
-    pub fn delete(&mut self, row_id: RowId) -> bool {
+    fn apply_delete(&mut self, row_id: RowId) {
         if let Some(row) = self.storage.delete(row_id) {
             self.search_engine.remove_row(row_id, &row.columns);
-            true
-        } else {
-            false
         }
     }
 
+    // false if there's no such row
     #[inline(always)]
-    pub fn update(&mut self, row_id: RowId, values: Vec<Value>) -> Result<bool, &'static str> {
-        if values.len() != self.columns.len() {
-            return Err("column count mismatch");
+    pub fn delete(&mut self, row_id: RowId) -> Result<bool, TableError> {
+        if self.storage.get(row_id).is_none() {
+            return Ok(false);
+        }
+        self.log(&[WalRecord::Delete { table: &self.name, row_id }])?;
+        self.apply_delete(row_id);
+        Ok(true)
+    }
+
+    // logs every id it can find with a single append, then deletes them
+    pub fn delete_many(&mut self, row_ids: &[RowId]) -> Result<usize, TableError> {
+        let mut seen = HashSet::with_capacity(row_ids.len());
+        let row_ids: Vec<RowId> = row_ids
+            .iter()
+            .copied()
+            .filter(|&id| self.storage.get(id).is_some() && seen.insert(id))
+            .collect();
+        let records: Vec<WalRecord> = row_ids
+            .iter()
+            .map(|&row_id| WalRecord::Delete { table: &self.name, row_id })
+            .collect();
+        self.log(&records)?;
+        for &row_id in &row_ids {
+            self.apply_delete(row_id);
+        }
+        Ok(row_ids.len())
+    }
+
+    // replaces the whole row. false if there's no such row
+    #[inline(always)]
+    pub fn update(&mut self, row_id: RowId, values: Vec<Value>, coercion: Coercion) -> Result<bool, TableError> {
+        let values = self.prepare(0, values, coercion)?;
+        self.check_key(0, &values, Some(row_id))?;
+        if self.storage.get(row_id).is_none() {
+            return Ok(false);
+        }
+
+        self.log(&[WalRecord::Update { table: &self.name, row_id, values: &values }])?;
+        self.apply_update(row_id, values);
+        Ok(true)
+    }
+
+    // sets only the columns in `changes`, the rest of the row and the other
+    // columns' indexes are left alone. false if there's no such row
+    pub fn patch(&mut self, row_id: RowId, changes: Vec<(usize, Value)>, coercion: Coercion) -> Result<bool, TableError> {
+        let changes = self.prepare_changes(0, changes, coercion)?;
+        if self.storage.get(row_id).is_none() {
+            return Ok(false);
         }
+        self.check_patched_keys(&[row_id], &changes)?;
+
+        self.log(&[WalRecord::Patch { table: &self.name, row_id, changes: &changes }])?;
+        self.apply_patch(row_id, &changes);
+        Ok(true)
+    }
+
+    // patches every row of `row_ids` that exists with the same `changes`,
+    // logged with a single append. nothing changes if a value doesn't fit its
+    // column, two rows would end up with the same key or the append fails
+    pub fn update_many(&mut self, row_ids: &[RowId], changes: Vec<(usize, Value)>, coercion: Coercion) -> Result<usize, TableError> {
+        let changes = self.prepare_changes(0, changes, coercion)?;
+        let mut seen = HashSet::with_capacity(row_ids.len());
+        let row_ids: Vec<RowId> = row_ids
+            .iter()
+            .copied()
+            .filter(|&id| self.storage.get(id).is_some() && seen.insert(id))
+            .collect();
+        self.check_patched_keys(&row_ids, &changes)?;
 
+        let records: Vec<WalRecord> = row_ids
+            .iter()
+            .map(|&row_id| WalRecord::Patch { table: &self.name, row_id, changes: &changes })
+            .collect();
+        self.log(&records)?;
+        for &row_id in &row_ids {
+            self.apply_patch(row_id, &changes);
+        }
+        Ok(row_ids.len())
+    }
+
+    // check (column, value) pairs against their columns, `row` is only used
+    // to report errors
+    pub fn prepare_changes(&self, row: usize, changes: Vec<(usize, Value)>, coercion: Coercion) -> Result<Vec<(usize, Value)>, TableError> {
+        changes
+            .into_iter()
+            .map(|(position, value)| {
+                let column = self.columns.get(position).ok_or_else(|| TableError::UnknownColumn(position.to_string().into()))?;
+                Ok((position, check_value(column, row, value, coercion)?))
+            })
+            .collect()
+    }
+
+    // refuse `changes` if they'd give one of the rows a key another row
+    // holds, or give two of them the same key
+    fn check_patched_keys(&self, row_ids: &[RowId], changes: &[(usize, Value)]) -> Result<(), TableError> {
+        if !changes.iter().any(|(position, _)| self.primary_key.contains(position)) {
+            return Ok(());
+        }
+        let mut keys = HashSet::with_capacity(row_ids.len());
+        for (row, &row_id) in row_ids.iter().enumerate() {
+            let mut values = self.get(row_id).unwrap_or(&[]).to_vec();
+            for (position, value) in changes {
+                values[*position] = value.clone();
+            }
+            self.check_key(row, &values, Some(row_id))?;
+            let key = key_string(&self.key_of(&values));
+            if !keys.insert(key.clone()) {
+                return Err(TableError::DuplicateKey { row, key });
+            }
+        }
+        Ok(())
+    }
+
+    // only the changed columns are reindexed, which is what makes a patch
+    // cheap next to an update on wide rows
+    fn apply_patch(&mut self, row_id: RowId, changes: &[(usize, Value)]) {
+        for (column, value) in changes {
+            if let Some(old) = self.storage.set(row_id, *column, value.clone()) {
+                self.search_engine.remove_value(*column, row_id, &old);
+                self.search_engine.index_value(*column, row_id, value);
+            }
+        }
+    }
+
+    fn apply_update(&mut self, row_id: RowId, values: Vec<Value>) {
         if let Some(old_row) = self.storage.get(row_id) {
             let old_columns = old_row.columns.clone();
             self.search_engine.remove_row(row_id, &old_columns);
-            self.storage.update(row_id, values.clone());
             self.search_engine.index_row(row_id, &values);
-            Ok(true)
-        } else {
-            Ok(false)
+            self.storage.update(row_id, values);
         }
     }
 
+    pub fn upsert(&mut self, values: Vec<Value>, coercion: Coercion) -> Result<Upsert, TableError> {
+        self.upsert_batch(vec![values], coercion).pop().unwrap()
+    }
+
+    // inserts rows with a new key and overwrites the rows holding the others,
+    // logged with a single append before any of them is applied. a key seen
+    // earlier in the batch updates the row that one inserts
+    pub fn upsert_batch(&mut self, rows: Vec<Vec<Value>>, coercion: Coercion) -> Vec<Result<Upsert, TableError>> {
+        let (mut results, staged) = self.stage_upserts(rows, coercion);
+        if let Err(e) = self.commit_upserts(staged) {
+            for result in results.iter_mut().filter(|r| r.is_ok()) {
+                *result = Err(e.clone());
+            }
+        }
+        results
+    }
+
+    // like upsert_batch, but one row that doesn't pass refuses them all
+    pub fn upsert_all(&mut self, rows: Vec<Vec<Value>>, coercion: Coercion) -> Result<Vec<Upsert>, TableError> {
+        if self.primary_key.is_empty() {
+            return Err(TableError::NoPrimaryKey);
+        }
+        let (results, staged) = self.stage_upserts(rows, coercion);
+        let upserts = results.into_iter().collect::<Result<Vec<_>, _>>()?;
+        self.commit_upserts(staged)?;
+        Ok(upserts)
+    }
+
+    // check rows and work out which row each one lands on, without touching
+    // the table. returns a result per row and the rows that passed
+    #[allow(clippy::type_complexity)]
+    fn stage_upserts(
+        &self,
+        rows: Vec<Vec<Value>>,
+        coercion: Coercion,
+    ) -> (Vec<Result<Upsert, TableError>>, Vec<(Upsert, Vec<Value>)>) {
+        let mut next_id = self.storage.next_id();
+        let mut inserted: HashMap<String, RowId> = HashMap::new();
+        let mut staged = Vec::with_capacity(rows.len());
+        let results = rows
+            .into_iter()
+            .enumerate()
+            .map(|(row, values)| {
+                if self.primary_key.is_empty() {
+                    return Err(TableError::NoPrimaryKey);
+                }
+                let values = self.prepare(row, values, coercion)?;
+                let key = self.key_of(&values);
+                let upsert = match self.lookup(&key) {
+                    Some(row_id) => Upsert::Updated(row_id),
+                    None => match inserted.entry(key_string(&key)) {
+                        Entry::Occupied(e) => Upsert::Updated(*e.get()),
+                        Entry::Vacant(e) => {
+                            let row_id = next_id;
+                            next_id += 1;
+                            Upsert::Inserted(*e.insert(row_id))
+                        }
+                    },
+                };
+                staged.push((upsert, values));
+                Ok(upsert)
+            })
+            .collect();
+        (results, staged)
+    }
+
+    fn commit_upserts(&mut self, staged: Vec<(Upsert, Vec<Value>)>) -> Result<(), TableError> {
+        let records: Vec<WalRecord> = staged
+            .iter()
+            .map(|(upsert, values)| match *upsert {
+                Upsert::Inserted(row_id) => WalRecord::Insert { table: &self.name, row_id, values },
+                Upsert::Updated(row_id) => WalRecord::Update { table: &self.name, row_id, values },
+            })
+            .collect();
+        self.log(&records)?;
+
+        for (upsert, values) in staged {
+            match upsert {
+                Upsert::Inserted(row_id) => self.apply_insert(row_id, values),
+                Upsert::Updated(row_id) => self.apply_update(row_id, values),
+            }
+        }
+        Ok(())
+    }
+
     #[inline(always)]
     pub fn search_exact(&self, column: usize, value: &Value) -> Vec<RowId> {
         self.search_engine.search_exact(column, value).row_ids
@@ -152,13 +1002,101 @@ impl Table {
     }
 
     #[inline(always)]
-    pub fn search_range(&mut self, column: usize, min: i64, max: i64) -> Vec<RowId> {
+    pub fn search_range(&self, column: usize, min: i64, max: i64) -> Vec<RowId> {
         self.search_engine.search_range(column, min, max).row_ids
     }
 
     #[inline(always)]
-    pub fn search(&mut self, column: usize, search_type: SearchType) -> Vec<RowId> {
-        self.search_engine.search(column, search_type).row_ids
+    // fails if the column doesn't keep the index the search needs
+    pub fn search(&self, column: usize, search_type: SearchType) -> Result<Vec<RowId>, SearchError> {
+        self.search_engine.search(column, search_type, &self.storage).map(|r| r.row_ids)
+    }
+
+    // evaluate a compound query, sorted by row id
+    pub fn search_query(&self, query: &Query) -> Result<Vec<RowId>, SearchError> {
+        self.search_engine.search_query(query, &self.storage)
+    }
+
+    // completed values for a search box, see `SearchEngine::suggest`
+    pub fn suggest(&self, column: usize, prefix: &str, limit: usize, weight: Option<usize>) -> Result<Vec<Suggestion>, SearchError> {
+        self.search_engine.suggest(column, prefix, limit, weight, &self.storage)
+    }
+
+    // sort ids by `keys`, nulls last in either direction and row id breaking
+    // ties. with a limit only that many rows get sorted, the rest are just
+    // partitioned off behind them
+    pub fn order(&self, ids: Vec<RowId>, keys: &[SortKey], limit: Option<usize>) -> Vec<RowId> {
+        let mut rows: Vec<(RowId, &[Value])> = ids
+            .into_iter()
+            .filter_map(|id| self.get(id).map(|values| (id, values)))
+            .collect();
+
+        let cmp = |a: &(RowId, &[Value]), b: &(RowId, &[Value])| {
+            for key in keys {
+                let (x, y) = (&a.1[key.column], &b.1[key.column]);
+                let ord = match (x, y) {
+                    (Value::Null, Value::Null) => Ordering::Equal,
+                    (Value::Null, _) => Ordering::Greater,
+                    (_, Value::Null) => Ordering::Less,
+                    _ if key.descending => y.sort_cmp(x),
+                    _ => x.sort_cmp(y),
+                };
+                if ord != Ordering::Equal {
+                    return ord;
+                }
+            }
+            a.0.cmp(&b.0)
+        };
+
+        top_k(&mut rows, limit, cmp);
+        rows.into_iter().map(|(id, _)| id).collect()
+    }
+
+    // aggregations over the matched rows, see `aggregate::aggregate`
+    pub fn aggregate(&self, aggregations: &[(String, Aggregation)], row_ids: &[RowId]) -> Vec<(String, AggregationResult)> {
+        aggregate::aggregate(aggregations, row_ids, &self.storage)
+    }
+
+    // value counts among the matched rows, see `SearchEngine::facet`
+    pub fn facet(&self, facet: Facet, row_ids: &[RowId]) -> Vec<(Value, usize)> {
+        self.search_engine.facet(facet, row_ids, &self.storage)
+    }
+
+    // bm25 relevance of the rows a query matches, none without full-text clauses
+    pub fn scores(&self, query: &Query) -> Option<HashMap<RowId, f64>> {
+        self.search_engine.scores(query, &Bm25::default())
+    }
+
+    // sort ids by score, best first, row id breaking ties. like `order`, a
+    // limit only sorts that many rows
+    pub fn rank(&self, mut ids: Vec<RowId>, scores: &HashMap<RowId, f64>, limit: Option<usize>) -> Vec<RowId> {
+        let score = |id: &RowId| scores.get(id).copied().unwrap_or(0.0);
+        top_k(&mut ids, limit, |a, b| score(b).total_cmp(&score(a)).then(a.cmp(b)));
+        ids
+    }
+
+    pub fn distances(&self, query: &Query) -> Option<HashMap<RowId, u32>> {
+        self.search_engine.distances(query)
+    }
+
+    // sort ids by edit distance, closest first, row id breaking ties. rows
+    // without a distance matched exactly
+    pub fn closest(&self, mut ids: Vec<RowId>, distances: &HashMap<RowId, u32>, limit: Option<usize>) -> Vec<RowId> {
+        let distance = |id: &RowId| distances.get(id).copied().unwrap_or(0);
+        top_k(&mut ids, limit, |a, b| distance(a).cmp(&distance(b)).then(a.cmp(b)));
+        ids
+    }
+
+    pub fn has_index(&self, column: usize, kind: IndexKind) -> bool {
+        self.search_engine.has_index(column, kind)
+    }
+
+    pub fn rows(&self) -> impl Iterator<Item = (RowId, &[Value])> {
+        self.storage.iter().map(|r| (r.id, r.columns.as_slice()))
+    }
+
+    pub fn next_id(&self) -> RowId {
+        self.storage.next_id()
     }
 
     pub fn len(&self) -> usize {
@@ -185,23 +1123,49 @@ pub struct TableStats {
     pub column_count: usize,
 }
 
+// catalog of tables. each table sits behind its own lock so reads and writes
+// on different tables never contend; the catalog itself only needs to be
+// locked long enough to look up or swap a handle
 pub struct Database {
-    tables: HashMap<Box<str>, Table>,
+    tables: HashMap<Box<str>, Arc<RwLock<Table>>>,
+    wal: Option<Arc<Wal>>,
 }
 
 impl Database {
     pub fn new() -> Self {
         Self {
             tables: HashMap::new(),
+            wal: None,
         }
     }
 
-    pub fn create_table(&mut self, name: &str, columns: Vec<Column>) -> Result<(), &'static str> {
+    // log schema changes and every table's writes from now on
+    pub fn set_wal(&mut self, wal: Arc<Wal>) {
+        for table in self.tables.values() {
+            table.write().unwrap().set_wal(Some(Arc::clone(&wal)));
+        }
+        self.wal = Some(wal);
+    }
+
+    pub fn wal(&self) -> Option<&Arc<Wal>> {
+        self.wal.as_ref()
+    }
+
+    fn log(&self, records: &[WalRecord<'_>]) -> Result<(), TableError> {
+        match &self.wal {
+            Some(wal) => wal.append(records).map_err(|e| {
+                log_error!("persist", "log append failed: {}", e);
+                TableError::Log
+            }),
+            None => Ok(()),
+        }
+    }
+
+    pub fn create_table(&mut self, name: &str, columns: Vec<Column>) -> Result<(), TableError> {
         if self.tables.contains_key(name) {
-            return Err("table already exists");
+            return Err(TableError::TableExists);
         }
-        self.tables.insert(name.into(), Table::new(name, columns));
-        Ok(())
+        self.add_table(Table::new(name, columns))
     }
 
     pub fn create_table_with_capacity(
@@ -209,25 +1173,133 @@ impl Database {
         name: &str,
         columns: Vec<Column>,
         capacity: usize,
-    ) -> Result<(), &'static str> {
+    ) -> Result<(), TableError> {
         if self.tables.contains_key(name) {
-            return Err("table already exists");
+            return Err(TableError::TableExists);
         }
-        self.tables
-            .insert(name.into(), Table::with_capacity(name, columns, capacity));
+        self.add_table(Table::with_capacity(name, columns, capacity))
+    }
+
+    fn add_table(&mut self, mut table: Table) -> Result<(), TableError> {
+        self.log(&[WalRecord::CreateTable { name: table.name(), columns: table.columns() }])?;
+        table.set_wal(self.wal.clone());
+        self.tables.insert(table.name().into(), Arc::new(RwLock::new(table)));
         Ok(())
     }
 
-    pub fn drop_table(&mut self, name: &str) -> bool {
-        self.tables.remove(name).is_some()
+    // add an already built table, used when restoring from disk (not logged)
+    pub fn insert_table(&mut self, mut table: Table) -> Result<(), TableError> {
+        if self.tables.contains_key(table.name()) {
+            return Err(TableError::TableExists);
+        }
+        table.set_wal(self.wal.clone());
+        self.tables.insert(table.name().into(), Arc::new(RwLock::new(table)));
+        Ok(())
+    }
+
+    // swap in a table built off to the side (e.g. by a full sync), replacing any
+    // table of the same name. readers holding the old handle finish undisturbed
+    pub fn replace_table(&mut self, mut table: Table) -> Result<(), TableError> {
+        if self.wal.is_some() {
+            let mut records = Vec::with_capacity(table.len() + 2);
+            if self.tables.contains_key(table.name()) {
+                records.push(WalRecord::DropTable { name: table.name() });
+            }
+            records.push(WalRecord::CreateTable { name: table.name(), columns: table.columns() });
+            records.extend(table.rows().map(|(row_id, values)| WalRecord::Insert {
+                table: table.name(),
+                row_id,
+                values,
+            }));
+            self.log(&records)?;
+        }
+
+        table.set_wal(self.wal.clone());
+        self.tables.insert(table.name().into(), Arc::new(RwLock::new(table)));
+        Ok(())
+    }
+
+    // change a table's schema in place. writers wait for the conversion pass,
+    // the rest of the database doesn't
+    pub fn alter_table(&self, name: &str, alteration: &Alteration) -> Result<(), TableError> {
+        match self.get_table_mut(name) {
+            Some(mut table) => table.alter(alteration),
+            None => Err(TableError::UnknownTable),
+        }
+    }
+
+    // false if there's no such table
+    pub fn drop_table(&mut self, name: &str) -> Result<bool, TableError> {
+        if !self.tables.contains_key(name) {
+            return Ok(false);
+        }
+        self.log(&[WalRecord::DropTable { name }])?;
+        self.tables.remove(name);
+        Ok(true)
+    }
+
+    // re-apply a logged operation during startup, before the log is attached
+    pub fn apply_wal_entry(&mut self, entry: WalEntry) {
+        let result = match entry {
+            WalEntry::CreateTable { name, columns } => {
+                // already there if the snapshot holds records it was never told
+                // it covers, the ones that follow are idempotent on top of it
+                if self.tables.contains_key(name.as_str()) {
+                    Ok(())
+                } else {
+                    self.create_table(&name, columns)
+                }
+            }
+            WalEntry::DropTable { name } => self.drop_table(&name).map(|_| ()),
+            WalEntry::Insert { table, row_id, values } => match self.get_table_mut(&table) {
+                Some(mut t) => t.insert_with_id(row_id, values),
+                None => Err(TableError::UnknownTable),
+            },
+            WalEntry::Update { table, row_id, values } => match self.get_table_mut(&table) {
+                Some(mut t) => t.update(row_id, values, Coercion::Strict).map(|_| ()),
+                None => Err(TableError::UnknownTable),
+            },
+            WalEntry::Patch { table, row_id, changes } => match self.get_table_mut(&table) {
+                Some(mut t) => t.patch(row_id, changes, Coercion::Strict).map(|_| ()),
+                None => Err(TableError::UnknownTable),
+            },
+            WalEntry::AlterTable { table, alteration } => self.alter_table(&table, &alteration),
+            WalEntry::Delete { table, row_id } => match self.get_table_mut(&table) {
+                Some(mut t) => t.delete(row_id).map(|_| ()),
+                None => Err(TableError::UnknownTable),
+            },
+        };
+
+        if let Err(e) = result {
+            log_warn!("persist", "skipping log entry during replay: {}", e);
+        }
+    }
+
+    // shared handle to a table, lock it for as long as you need it
+    pub fn table(&self, name: &str) -> Option<Arc<RwLock<Table>>> {
+        self.tables.get(name).cloned()
+    }
+
+    pub fn get_table(&self, name: &str) -> Option<RwLockReadGuard<'_, Table>> {
+        self.tables.get(name).map(|t| t.read().unwrap())
     }
 
-    pub fn get_table(&self, name: &str) -> Option<&Table> {
-        self.tables.get(name)
+    pub fn get_table_mut(&self, name: &str) -> Option<RwLockWriteGuard<'_, Table>> {
+        self.tables.get(name).map(|t| t.write().unwrap())
     }
 
-    pub fn get_table_mut(&mut self, name: &str) -> Option<&mut Table> {
-        self.tables.get_mut(name)
+    // shared handles to every table, in name order
+    pub fn tables(&self) -> Vec<Arc<RwLock<Table>>> {
+        let mut names: Vec<&Box<str>> = self.tables.keys().collect();
+        names.sort();
+        names.into_iter().map(|n| Arc::clone(&self.tables[n])).collect()
+    }
+
+    // read locks on every table, taken in name order
+    pub fn read_all(&self) -> Vec<RwLockReadGuard<'_, Table>> {
+        let mut names: Vec<&Box<str>> = self.tables.keys().collect();
+        names.sort();
+        names.into_iter().map(|n| self.tables[n].read().unwrap()).collect()
     }
 
     pub fn table_names(&self) -> Vec<&str> {
@@ -235,7 +1307,7 @@ impl Database {
     }
 
     pub fn stats(&self) -> Vec<TableStats> {
-        self.tables.values().map(|t| t.stats()).collect()
+        self.tables.values().map(|t| t.read().unwrap().stats()).collect()
     }
 }
 
@@ -245,16 +1317,33 @@ impl Default for Database {
     }
 }
 
+// sort the k smallest by `cmp`, leaving only those. without a limit it's a
+// plain sort
+fn top_k<T>(items: &mut Vec<T>, limit: Option<usize>, mut cmp: impl FnMut(&T, &T) -> Ordering) {
+    if let Some(k) = limit {
+        if k == 0 {
+            items.clear();
+            return;
+        }
+        if k < items.len() {
+            items.select_nth_unstable_by(k - 1, &mut cmp);
+            items.truncate(k);
+        }
+    }
+    items.sort_unstable_by(cmp);
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::ops::Bound;
 
     fn create_test_table() -> Table {
         Table::new(
             "users",
             vec![
-                Column { name: "name".into(), col_type: ColumnType::String },
-                Column { name: "age".into(), col_type: ColumnType::Int },
+                Column::new("name", ColumnType::String),
+                Column::new("age", ColumnType::Int),
             ],
         )
     }
@@ -266,7 +1355,7 @@ mod tests {
         let id = table.insert(vec![
             Value::String("alice".into()),
             Value::Int(30),
-        ]).unwrap();
+        ], Coercion::Strict).unwrap();
         
         let row = table.get(id).unwrap();
         assert_eq!(row[0], Value::String("alice".into()));
@@ -277,8 +1366,8 @@ mod tests {
     fn test_table_search_exact() {
         let mut table = create_test_table();
         
-        table.insert(vec![Value::String("alice".into()), Value::Int(30)]).unwrap();
-        table.insert(vec![Value::String("bob".into()), Value::Int(25)]).unwrap();
+        table.insert(vec![Value::String("alice".into()), Value::Int(30)], Coercion::Strict).unwrap();
+        table.insert(vec![Value::String("bob".into()), Value::Int(25)], Coercion::Strict).unwrap();
         
         let results = table.search_exact_by_name("name", &Value::String("alice".into()));
         assert_eq!(results.len(), 1);
@@ -291,9 +1380,9 @@ mod tests {
         let id = table.insert(vec![
             Value::String("alice".into()),
             Value::Int(30),
-        ]).unwrap();
+        ], Coercion::Strict).unwrap();
         
-        assert!(table.delete(id));
+        assert_eq!(table.delete(id), Ok(true));
         assert!(table.get(id).is_none());
     }
 
@@ -304,12 +1393,12 @@ mod tests {
         let id = table.insert(vec![
             Value::String("alice".into()),
             Value::Int(30),
-        ]).unwrap();
+        ], Coercion::Strict).unwrap();
         
         table.update(id, vec![
             Value::String("alice".into()),
             Value::Int(31),
-        ]).unwrap();
+        ], Coercion::Strict).unwrap();
         
         let row = table.get(id).unwrap();
         assert_eq!(row[1], Value::Int(31));
@@ -320,19 +1409,406 @@ mod tests {
         let mut db = Database::new();
         
         db.create_table("users", vec![
-            Column { name: "name".into(), col_type: ColumnType::String },
+            Column::new("name", ColumnType::String),
         ]).unwrap();
         
         assert!(db.get_table("users").is_some());
-        assert!(db.drop_table("users"));
+        assert_eq!(db.drop_table("users"), Ok(true));
         assert!(db.get_table("users").is_none());
     }
 
+    #[test]
+    fn test_table_restore() {
+        let columns = create_test_table().columns().to_vec();
+        let table = Table::restore(
+            "users",
+            columns,
+            vec![
+                (3, vec![Value::String("alice".into()), Value::Int(30)]),
+                (7, vec![Value::String("bob".into()), Value::Int(25)]),
+            ],
+            10,
+        );
+
+        assert_eq!(table.len(), 2);
+        assert_eq!(table.next_id(), 10);
+        assert_eq!(table.search_exact_by_name("name", &Value::String("bob".into())), vec![7]);
+        assert_eq!(table.search_exact_by_name("age", &Value::Int(30)), vec![3]);
+    }
+
+    #[test]
+    fn test_replay_over_snapshot_overlap() {
+        let mut db = Database::new();
+        db.create_table("users", create_test_table().columns().to_vec()).unwrap();
+        db.get_table_mut("users").unwrap()
+            .insert(vec![Value::String("alice".into()), Value::Int(30)], Coercion::Strict).unwrap();
+
+        // the same records again, as if the snapshot didn't know it held them
+        db.apply_wal_entry(WalEntry::CreateTable {
+            name: "users".to_string(),
+            columns: create_test_table().columns().to_vec(),
+        });
+        db.apply_wal_entry(WalEntry::Insert {
+            table: "users".to_string(),
+            row_id: 1,
+            values: vec![Value::String("alice".into()), Value::Int(30)],
+        });
+
+        let users = db.get_table("users").unwrap();
+        assert_eq!(users.len(), 1);
+        assert_eq!(users.search_exact_by_name("name", &Value::String("alice".into())), vec![1]);
+    }
+
+    #[test]
+    fn test_tables_locked_independently() {
+        let mut db = Database::new();
+        db.create_table("users", create_test_table().columns().to_vec()).unwrap();
+        db.create_table("events", vec![Column::new("kind", ColumnType::String)]).unwrap();
+
+        // a writer parked on one table leaves the other free to read and write
+        let users = db.get_table_mut("users").unwrap();
+        assert!(db.table("events").unwrap().try_write().is_ok());
+        assert!(db.table("events").unwrap().try_read().is_ok());
+        assert!(db.table("users").unwrap().try_read().is_err());
+        drop(users);
+
+        // handles outlive the catalog lookup, and a dropped table's handle stays usable
+        let events = db.table("events").unwrap();
+        assert_eq!(db.drop_table("events"), Ok(true));
+        events.write().unwrap().insert(vec![Value::String("orphan".into())], Coercion::Strict).unwrap();
+        assert!(db.get_table("events").is_none());
+    }
+
+    #[test]
+    fn test_order_by() {
+        let mut table = create_test_table();
+        for (name, age) in [("carol", Value::Int(30)), ("alice", Value::Null), ("bob", Value::Int(30)), ("dave", Value::Int(20))] {
+            table.insert(vec![Value::String(name.into()), age], Coercion::Strict).unwrap();
+        }
+        let ids: Vec<RowId> = vec![4, 3, 2, 1];
+        let age = |descending| SortKey { column: 1, descending };
+
+        // equal ages fall back to row id, nulls stay last both ways
+        assert_eq!(table.order(ids.clone(), &[age(false)], None), vec![4, 1, 3, 2]);
+        assert_eq!(table.order(ids.clone(), &[age(true)], None), vec![1, 3, 4, 2]);
+        assert_eq!(
+            table.order(ids.clone(), &[age(true), SortKey { column: 0, descending: false }], None),
+            vec![3, 1, 4, 2]
+        );
+
+        // top-k agrees with the full sort
+        assert_eq!(table.order(ids.clone(), &[age(false)], Some(2)), vec![4, 1]);
+        assert!(table.order(ids, &[age(false)], Some(0)).is_empty());
+    }
+
     #[test]
     fn test_column_mismatch() {
         let mut table = create_test_table();
         
-        let result = table.insert(vec![Value::String("alice".into())]);
-        assert!(result.is_err());
+        let result = table.insert(vec![Value::String("alice".into())], Coercion::Strict);
+        assert_eq!(result, Err(TableError::ColumnCount { row: 0, expected: 2, got: 1 }));
+    }
+
+    #[test]
+    fn test_primary_key() {
+        let mut table = Table::new("stock", vec![
+            Column::new("region", ColumnType::String).with_primary_key(true),
+            Column::new("sku", ColumnType::Int).with_indexes(IndexSet::NONE).with_primary_key(true),
+            Column::new("count", ColumnType::Int),
+        ]);
+        assert_eq!(table.primary_key(), &[0, 1]);
+        assert!(table.has_index(1, IndexKind::Exact));
+
+        let row = |region: &str, sku: i64, count: i64| vec![Value::String(region.into()), Value::Int(sku), Value::Int(count)];
+        let eu = table.insert(row("eu", 7, 1), Coercion::Strict).unwrap();
+        let us = table.insert(row("us", 7, 2), Coercion::Strict).unwrap();
+        assert_eq!(
+            table.insert(row("eu", 7, 3), Coercion::Strict),
+            Err(TableError::DuplicateKey { row: 0, key: r#"("eu", 7)"#.to_string() })
+        );
+        assert!(table.insert(vec![Value::Null, Value::Int(1), Value::Int(1)], Coercion::Strict).is_err());
+        assert_eq!(table.find_key(&[Value::String("us".into()), Value::Int(7)]), Some(us));
+        assert_eq!(table.find_key(&[Value::String("us".into()), Value::Int(8)]), None);
+
+        // a batch can't collide with itself either
+        let batch = vec![row("asia", 1, 0), row("asia", 1, 5)];
+        assert!(matches!(table.insert_all(batch, Coercion::Strict), Err(TableError::DuplicateKey { row: 1, .. })));
+        assert_eq!(table.find_key(&[Value::String("asia".into()), Value::Int(1)]), None);
+
+        // moving a row onto another's key is refused, keeping its own is fine
+        assert!(matches!(table.update(us, row("eu", 7, 2), Coercion::Strict), Err(TableError::DuplicateKey { .. })));
+        assert!(table.update(eu, row("eu", 7, 10), Coercion::Strict).unwrap());
+
+        let results = table.upsert_batch(vec![row("eu", 7, 11), row("asia", 1, 0), row("asia", 1, 4)], Coercion::Strict);
+        let asia = table.find_key(&[Value::String("asia".into()), Value::Int(1)]).unwrap();
+        assert_eq!(results, vec![Ok(Upsert::Updated(eu)), Ok(Upsert::Inserted(asia)), Ok(Upsert::Updated(asia))]);
+        assert_eq!(table.get(eu).unwrap()[2], Value::Int(11));
+        assert_eq!(table.get(asia).unwrap()[2], Value::Int(4));
+        assert_eq!(table.len(), 3);
+
+        assert_eq!(create_test_table().upsert(vec![Value::String("a".into()), Value::Int(1)], Coercion::Strict), Err(TableError::NoPrimaryKey));
+        assert_eq!(create_test_table().upsert_all(Vec::new(), Coercion::Strict), Err(TableError::NoPrimaryKey));
+    }
+
+    #[test]
+    fn test_composite_key_lookup() {
+        let mut table = Table::new("stock", vec![
+            Column::new("region", ColumnType::String).with_primary_key(true),
+            Column::new("sku", ColumnType::Int).with_primary_key(true),
+        ]);
+        let rows: Vec<Vec<Value>> = (0..1000).map(|sku| vec![Value::String("eu".into()), Value::Int(sku)]).collect();
+        table.insert_all(rows, Coercion::Strict).unwrap();
+        let us = table.insert(vec![Value::String("us".into()), Value::Int(7)], Coercion::Strict).unwrap();
+
+        // either column can be the selective one
+        assert_eq!(table.find_key(&[Value::String("eu".into()), Value::Int(999)]), Some(1000));
+        assert_eq!(table.find_key(&[Value::String("us".into()), Value::Int(7)]), Some(us));
+        assert_eq!(table.find_key(&[Value::String("us".into()), Value::Int(8)]), None);
+        assert_eq!(table.find_key(&[Value::String("asia".into()), Value::Int(7)]), None);
+
+        table.patch(us, vec![(1, Value::Int(8))], Coercion::Strict).unwrap();
+        assert_eq!(table.find_key(&[Value::String("us".into()), Value::Int(8)]), Some(us));
+        assert_eq!(table.find_key(&[Value::String("us".into()), Value::Int(7)]), None);
+    }
+
+    #[test]
+    fn test_alter() {
+        let mut table = create_test_table();
+        let alice = table.insert(vec![Value::String("alice".into()), Value::Int(30)], Coercion::Strict).unwrap();
+        let bob = table.insert(vec![Value::String("bob".into()), Value::Int(25)], Coercion::Strict).unwrap();
+
+        // new columns are backfilled with their default and indexed
+        let status = Column::new("status", ColumnType::String).with_default(Value::String("active".into()));
+        table.alter(&Alteration::AddColumn(status.clone())).unwrap();
+        assert_eq!(table.get(bob).unwrap()[2], Value::String("active".into()));
+        assert_eq!(table.search_prefix(2, "act").len(), 2);
+        assert_eq!(table.alter(&Alteration::AddColumn(status)), Err(TableError::ColumnExists("status".into())));
+        let strict = Column::new("score", ColumnType::Float).with_nullable(false);
+        assert_eq!(table.alter(&Alteration::AddColumn(strict)), Err(TableError::NoDefault("score".into())));
+
+        // dropping shifts the later columns' indexes down with them
+        table.alter(&Alteration::DropColumn("age".into())).unwrap();
+        assert_eq!(table.columns().len(), 2);
+        assert_eq!(table.get(alice).unwrap(), &[Value::String("alice".into()), Value::String("active".into())]);
+        assert_eq!(table.search_exact(1, &Value::String("active".into())).len(), 2);
+        assert_eq!(table.alter(&Alteration::DropColumn("age".into())), Err(TableError::UnknownColumn("age".into())));
+
+        table.alter(&Alteration::RenameColumn { from: "status".into(), to: "level".into() }).unwrap();
+        assert_eq!(table.column_index("level"), Some(1));
+        assert!(table.alter(&Alteration::RenameColumn { from: "level".into(), to: "name".into() }).is_err());
+
+        // a value that won't convert leaves the column as it was
+        table.update(alice, vec![Value::String("alice".into()), Value::String("3".into())], Coercion::Strict).unwrap();
+        let retype = Alteration::Retype { column: "level".into(), col_type: ColumnType::Int };
+        assert_eq!(table.alter(&retype), Err(TableError::Conversion { row_id: bob, column: "level".into(), to: ColumnType::Int }));
+        assert_eq!(table.columns()[1].col_type, ColumnType::String);
+        // and so does a default that won't
+        table.update(bob, vec![Value::String("bob".into()), Value::String("12".into())], Coercion::Strict).unwrap();
+        assert_eq!(table.alter(&retype), Err(TableError::NoDefault("level".into())));
+        assert_eq!(table.columns()[1].default, Value::String("active".into()));
+
+        let rank = Column::new("rank", ColumnType::String).with_default(Value::String("0".into()));
+        table.alter(&Alteration::AddColumn(rank)).unwrap();
+        table.patch(bob, vec![(2, Value::String("12".into()))], Coercion::Strict).unwrap();
+        table.alter(&Alteration::Retype { column: "rank".into(), col_type: ColumnType::Int }).unwrap();
+        assert_eq!(table.columns()[2].indexes, ColumnType::Int.default_indexes());
+        assert_eq!(table.columns()[2].default, Value::Int(0));
+        assert_eq!(table.get(bob).unwrap()[2], Value::Int(12));
+        assert_eq!(table.search_range(2, 10, 20), vec![bob]);
+        assert!(table.search_prefix(2, "1").is_empty());
+
+        let mut keyed = Table::new("k", vec![Column::new("id", ColumnType::Int).with_primary_key(true)]);
+        assert_eq!(keyed.alter(&Alteration::DropColumn("id".into())), Err(TableError::KeyColumn("id".into())));
+    }
+
+    #[test]
+    fn test_typed_values() {
+        let mut table = Table::new("t", vec![
+            Column::new("name", ColumnType::String).with_nullable(false),
+            Column::new("age", ColumnType::Int),
+            Column::new("score", ColumnType::Float),
+        ]);
+
+        // a string in an int column would be invisible to range search
+        let results = table.insert_batch(vec![
+            vec![Value::String("alice".into()), Value::Int(30), Value::Float(1.5)],
+            vec![Value::String("bob".into()), Value::String("42".into()), Value::Null],
+            vec![Value::Null, Value::Null, Value::Null],
+        ], Coercion::Strict);
+        assert!(results[0].is_ok());
+        let err = results[1].clone().unwrap_err();
+        assert_eq!(err.to_string(), "row 1: column 'age' expects int, got string");
+        assert_eq!(results[2], Err(TableError::Null { row: 2, column: "name".into() }));
+        assert_eq!(table.len(), 1);
+
+        // ints widen into float columns
+        let id = table.insert(vec![Value::String("carol".into()), Value::Null, Value::Int(2)], Coercion::Strict).unwrap();
+        assert_eq!(table.get(id).unwrap()[2], Value::Float(2.0));
+        assert_eq!(table.search_range(1, 0, 100).len(), 1);
+
+        // converting only what survives the trip
+        let row = vec![Value::String("dave".into()), Value::String(" 42 ".into()), Value::String("2.5".into())];
+        assert!(table.prepare(0, row.clone(), Coercion::Strict).is_err());
+        assert_eq!(
+            table.prepare(0, row, Coercion::Convert).unwrap(),
+            vec![Value::String("dave".into()), Value::Int(42), Value::Float(2.5)]
+        );
+        assert_eq!(
+            table.prepare(0, vec![Value::Int(7), Value::Float(3.0), Value::Null], Coercion::Convert).unwrap(),
+            vec![Value::String("7".into()), Value::Int(3), Value::Null]
+        );
+        assert!(table.prepare(0, vec![Value::Int(7), Value::Float(3.5), Value::Null], Coercion::Convert).is_err());
+        assert!(table.prepare(0, vec![Value::Int(7), Value::String("4x".into()), Value::Null], Coercion::Convert).is_err());
+
+        assert!(table.update(id, vec![Value::String("carol".into()), Value::Float(1.0), Value::Null], Coercion::Strict).is_err());
+        assert!(table.update(id, vec![Value::String("carol".into()), Value::Int(1), Value::Null], Coercion::Strict).unwrap());
+    }
+
+    #[test]
+    fn test_column_types() {
+        let tags = ColumnType::array_of(ColumnType::String).unwrap();
+        let mut table = Table::new("t", vec![
+            Column::new("active", ColumnType::Bool),
+            Column::new("seen", ColumnType::Timestamp),
+            Column::new("id", ColumnType::Uuid),
+            Column::new("tags", tags),
+        ]);
+        assert_eq!(ColumnType::array_of(tags), None);
+        assert_eq!(tags.default_indexes(), ColumnType::String.default_indexes());
+
+        // timestamps and uuids come in as json carries them
+        let a = table.insert(vec![
+            Value::Bool(true),
+            Value::String("2024-05-01T12:00:00+02:00".into()),
+            Value::String("67E55044-10B1-426F-9247-BB680E5FE0C8".into()),
+            Value::Array(vec![Value::String("red".into()), Value::String("blue".into()), Value::String("red".into())].into()),
+        ], Coercion::Strict).unwrap();
+        let b = table.insert(vec![
+            Value::Bool(false),
+            Value::Int(1_714_600_000),
+            Value::Null,
+            Value::Array(vec![Value::String("blue".into())].into()),
+        ], Coercion::Strict).unwrap();
+        assert_eq!(table.get(a).unwrap()[1], Value::Timestamp(1_714_557_600_000_000));
+        assert_eq!(table.get(b).unwrap()[1], Value::Timestamp(1_714_600_000_000_000));
+
+        let err = table.insert(vec![Value::Null, Value::Null, Value::Null, Value::Array(vec![Value::Int(1)].into())], Coercion::Strict);
+        assert_eq!(err.unwrap_err().to_string(), "row 0: column 'tags' expects array<string>, got int");
+        assert!(table.insert(vec![Value::Int(1), Value::Null, Value::Null, Value::Null], Coercion::Strict).is_err());
+        assert!(table.insert(vec![Value::Null, Value::String("yesterday".into()), Value::Null, Value::Null], Coercion::Strict).is_err());
+        assert_eq!(
+            table.prepare(0, vec![Value::String("TRUE".into()), Value::Null, Value::Null, Value::Null], Coercion::Convert).unwrap()[0],
+            Value::Bool(true)
+        );
+
+        // an exact search matches any element, each row once
+        assert_eq!(table.search_exact(3, &Value::String("red".into())), vec![a]);
+        let mut blue = table.search_exact(3, &Value::String("blue".into()));
+        blue.sort_unstable();
+        assert_eq!(blue, vec![a, b]);
+        assert_eq!(table.search_exact(0, &Value::Bool(false)), vec![b]);
+        assert_eq!(table.search_exact(2, &Value::Uuid(parse_uuid("67e55044-10b1-426f-9247-bb680e5fe0c8").unwrap())), vec![a]);
+
+        let since = SearchType::Range {
+            min: Bound::Included(Value::Timestamp(1_714_560_000_000_000)),
+            max: Bound::Unbounded,
+        };
+        assert_eq!(table.search(1, since).unwrap(), vec![b]);
+
+        assert_eq!(table.delete(a), Ok(true));
+        assert_eq!(table.search_exact(3, &Value::String("blue".into())), vec![b]);
+        assert!(table.search_exact(3, &Value::String("red".into())).is_empty());
+
+        // rows matching on several elements come back once
+        let mut table = Table::new("t", vec![
+            Column::new("sizes", ColumnType::array_of(ColumnType::Int).unwrap()),
+            Column::new("tags", tags),
+        ]);
+        let id = table.insert(vec![
+            Value::Array(vec![Value::Int(2), Value::Int(3)].into()),
+            Value::Array(vec![Value::String("apple".into()), Value::String("apricot".into())].into()),
+        ], Coercion::Strict).unwrap();
+        assert_eq!(table.search(0, SearchType::between(Value::Int(1), Value::Int(10))).unwrap(), vec![id]);
+        assert_eq!(table.search_range(0, 1, 10), vec![id]);
+        assert_eq!(table.search(1, SearchType::Prefix("ap".into())).unwrap(), vec![id]);
+    }
+
+    #[test]
+    fn test_array_full_text() {
+        let strings = |items: &[&str]| Value::Array(items.iter().map(|&s| Value::String(s.into())).collect());
+        let phrase = |text: &str| SearchType::FullText(format!("\"{}\"", text));
+        let mut table = Table::new("t", vec![Column::new("notes", ColumnType::array_of(ColumnType::String).unwrap())]);
+        let id = table.insert(vec![strings(&["red car", "x blue car"])], Coercion::Strict).unwrap();
+
+        // the array is one document, a phrase matches within any element but not across two
+        assert_eq!(table.search(0, phrase("red car")).unwrap(), vec![id]);
+        assert_eq!(table.search(0, phrase("blue car")).unwrap(), vec![id]);
+        assert!(table.search(0, phrase("car x")).unwrap().is_empty());
+        assert_eq!(table.search(0, SearchType::FullText("car NEAR/3 blue".into())).unwrap(), vec![id]);
+
+        table.patch(id, vec![(0, strings(&["green van"]))], Coercion::Strict).unwrap();
+        assert!(table.search(0, SearchType::FullText("car".into())).unwrap().is_empty());
+        assert_eq!(table.search(0, phrase("green van")).unwrap(), vec![id]);
+    }
+
+    #[test]
+    fn test_update_many() {
+        let mut table = Table::new("stock", vec![
+            Column::new("sku", ColumnType::Int).with_primary_key(true),
+            Column::new("status", ColumnType::String),
+            Column::new("count", ColumnType::Int),
+        ]);
+        let ids: Vec<RowId> = (1..=3)
+            .map(|sku| table.insert(vec![Value::Int(sku), Value::String("new".into()), Value::Int(sku * 10)], Coercion::Strict).unwrap())
+            .collect();
+
+        // only the changed columns move, ids that don't exist are skipped
+        let changes = vec![(1, Value::String("sold".into())), (2, Value::String("0".into()))];
+        assert_eq!(table.update_many(&[ids[0], ids[2], 99], changes, Coercion::Convert), Ok(2));
+        assert_eq!(table.get(ids[2]).unwrap(), &[Value::Int(3), Value::String("sold".into()), Value::Int(0)]);
+        assert_eq!(table.get(ids[1]).unwrap()[1], Value::String("new".into()));
+        assert_eq!(table.search_exact(1, &Value::String("sold".into())).len(), 2);
+        assert!(table.search_exact(2, &Value::Int(30)).is_empty());
+
+        // nothing changes when a value doesn't fit or two rows would share a key
+        let err = table.update_many(&ids, vec![(2, Value::String("lots".into()))], Coercion::Strict);
+        assert_eq!(err.unwrap_err().to_string(), "row 0: column 'count' expects int, got string");
+        let err = table.update_many(&ids[..2], vec![(0, Value::Int(9)), (2, Value::Int(1))], Coercion::Strict);
+        assert!(matches!(err, Err(TableError::DuplicateKey { row: 1, .. })));
+        assert!(matches!(table.update_many(&ids[..1], vec![(0, Value::Int(2))], Coercion::Strict), Err(TableError::DuplicateKey { .. })));
+        assert_eq!(table.get(ids[0]).unwrap(), &[Value::Int(1), Value::String("sold".into()), Value::Int(0)]);
+        assert_eq!(table.update_many(&ids[..1], vec![(0, Value::Int(9))], Coercion::Strict), Ok(1));
+        assert_eq!(table.find_key(&[Value::Int(9)]), Some(ids[0]));
+    }
+
+    #[test]
+    fn test_patch() {
+        let mut table = Table::new("users", vec![
+            Column::new("email", ColumnType::String).with_primary_key(true),
+            Column::new("bio", ColumnType::String),
+            Column::new("age", ColumnType::Int),
+        ]);
+        let a = table.insert(vec![Value::String("a@x".into()), Value::String("likes rust".into()), Value::Int(30)], Coercion::Strict).unwrap();
+        let b = table.insert(vec![Value::String("b@x".into()), Value::String("likes go".into()), Value::Int(40)], Coercion::Strict).unwrap();
+
+        assert_eq!(table.patch(a, vec![(2, Value::Int(31))], Coercion::Strict), Ok(true));
+        assert_eq!(table.get(a).unwrap(), &[Value::String("a@x".into()), Value::String("likes rust".into()), Value::Int(31)]);
+        assert_eq!(table.search_range(2, 31, 31), vec![a]);
+        assert!(table.search_range(2, 30, 30).is_empty());
+        assert_eq!(table.search_fulltext(1, "rust"), vec![a]);
+
+        assert_eq!(table.patch(a, vec![(1, Value::String("likes zig".into()))], Coercion::Strict), Ok(true));
+        assert!(table.search_fulltext(1, "rust").is_empty());
+        assert_eq!(table.search_fulltext(1, "zig"), vec![a]);
+
+        assert_eq!(table.patch(99, vec![(2, Value::Int(1))], Coercion::Strict), Ok(false));
+        assert!(matches!(table.patch(a, vec![(2, Value::String("old".into()))], Coercion::Strict), Err(TableError::Type { .. })));
+        assert_eq!(table.patch(a, vec![(2, Value::String("32".into()))], Coercion::Convert), Ok(true));
+        assert_eq!(table.get(a).unwrap()[2], Value::Int(32));
+        assert!(matches!(table.patch(a, vec![(0, Value::String("b@x".into()))], Coercion::Strict), Err(TableError::DuplicateKey { .. })));
+        assert_eq!(table.patch(a, vec![(0, Value::String("c@x".into()))], Coercion::Strict), Ok(true));
+        assert_eq!(table.find_key(&[Value::String("c@x".into())]), Some(a));
+        assert_eq!(table.find_key(&[Value::String("a@x".into())]), None);
+        assert_eq!(table.get(b).unwrap()[2], Value::Int(40));
     }
 }
@@ -1,5 +1,5 @@
 use quickset::storage::Value;
-use quickset::table::{Table, Column, ColumnType, Database};
+use quickset::table::{Table, Column, ColumnType, Coercion};
 use std::time::Instant;
 
 fn generate_string(len: usize, seed: u64) -> String {
@@ -23,10 +23,10 @@ fn test_million_row_performance() {
     let mut table = Table::with_capacity(
         "million_rows",
         vec![
-            Column { name: "id".into(), col_type: ColumnType::Int },
-            Column { name: "name".into(), col_type: ColumnType::String },
-            Column { name: "description".into(), col_type: ColumnType::String },
-            Column { name: "value".into(), col_type: ColumnType::Int },
+            Column::new("id", ColumnType::Int),
+            Column::new("name", ColumnType::String),
+            Column::new("description", ColumnType::String),
+            Column::new("value", ColumnType::Int),
         ],
         row_count,
     );
@@ -43,7 +43,7 @@ fn test_million_row_performance() {
             Value::String(name.into()),
             Value::String(desc.into()),
             Value::Int((i * 7) as i64),
-        ]).unwrap();
+        ], Coercion::Strict).unwrap();
         
         if i > 0 && i % 100_000 == 0 {
             println!("  inserted {} rows...", i);
@@ -105,12 +105,11 @@ fn test_million_row_performance() {
     
     // range search benchmark  
     println!("benchmarking range search...");
-    let mut table_mut = table;
     let start = Instant::now();
     
     for i in 0..iterations {
         let base = ((i * 97) % row_count) as i64;
-        let _ = table_mut.search_range(3, base, base + 1000);
+        let _ = table.search_range(3, base, base + 1000);
     }
     
     let range_time = start.elapsed();
@@ -124,7 +123,7 @@ fn test_million_row_performance() {
     
     for i in 0..iterations {
         let id = ((i * 97) % row_count) as u64 + 1;
-        let row = table_mut.get(id);
+        let row = table.get(id);
         assert!(row.is_some());
     }
     
@@ -139,7 +138,7 @@ fn test_million_row_performance() {
     println!("fulltext search: {} ns/op", fulltext_per_op);
     println!("range search:    {} ns/op", range_per_op);
     println!("get by id:       {} ns/op", get_per_op);
-    println!("total rows:      {}", table_mut.len());
+    println!("total rows:      {}", table.len());
 }
 
 #[test]
@@ -152,8 +151,8 @@ fn test_five_million_rows() {
     let mut table = Table::with_capacity(
         "five_million",
         vec![
-            Column { name: "id".into(), col_type: ColumnType::Int },
-            Column { name: "data".into(), col_type: ColumnType::String },
+            Column::new("id", ColumnType::Int),
+            Column::new("data", ColumnType::String),
         ],
         row_count,
     );
@@ -166,7 +165,7 @@ fn test_five_million_rows() {
         table.insert(vec![
             Value::Int(i as i64),
             Value::String(data.into()),
-        ]).unwrap();
+        ], Coercion::Strict).unwrap();
         
         if i > 0 && i % 500_000 == 0 {
             println!("  inserted {} rows...", i);
@@ -197,9 +196,9 @@ fn test_hundred_thousand_rows() {
     let mut table = Table::with_capacity(
         "hundred_thousand",
         vec![
-            Column { name: "id".into(), col_type: ColumnType::Int },
-            Column { name: "name".into(), col_type: ColumnType::String },
-            Column { name: "value".into(), col_type: ColumnType::Int },
+            Column::new("id", ColumnType::Int),
+            Column::new("name", ColumnType::String),
+            Column::new("value", ColumnType::Int),
         ],
         row_count,
     );
@@ -210,7 +209,7 @@ fn test_hundred_thousand_rows() {
             Value::Int(i as i64),
             Value::String(name.into()),
             Value::Int((i * 3) as i64),
-        ]).unwrap();
+        ], Coercion::Strict).unwrap();
     }
     
     assert_eq!(table.len(), row_count);